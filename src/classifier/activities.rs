@@ -4,11 +4,14 @@ use super::{
     },
     rules::RuleInternal,
 };
+use crate::record_store::is_valid_tag;
+use std::collections::BTreeSet;
 
 #[derive(Debug, Default)]
 pub struct ActivityInternal {
     pub name: String,
     pub productivity: i8,
+    pub tags: BTreeSet<String>,
     pub rules: Vec<RuleInternal>
 }
 
@@ -34,6 +37,8 @@ impl From<Activity> for Option<ActivityInternal> {
         Some(ActivityInternal {
             name: (&act_conf.name?).clone(),
             productivity: act_conf.productivity.unwrap_or(0),
+            // Tags that couldn't be exported are skipped like malformed rules
+            tags: act_conf.tags.unwrap_or_default().into_iter().filter(|tag| is_valid_tag(tag)).collect(),
             rules
         })
     }
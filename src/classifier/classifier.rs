@@ -1,22 +1,36 @@
 use super::{
     config::ClassifierConfig,
     activities::ActivityInternal,
+    projects::ProjectInternal,
+    privacy::{ apply_privacy, PrivacyInternal },
+    super::constants::DEFAULT_MACHINE_NAME,
     super::record_store::{
         ProductivityStatus,
-        Archetype
+        Archetype,
+        HashKey
     }
 };
+use std::collections::BTreeSet;
 
 pub trait Classifiable {
     fn get_archetype(&self) -> &Archetype;
     fn assign_productivity(&mut self, productivity: ProductivityStatus);
+    /// Adds tags of activities whose rules matched, things that can't be tagged ignore them
+    fn assign_tags(&mut self, _tags: BTreeSet<String>) {}
+    fn has_tag(&self, _tag: &str) -> bool {
+        false
+    }
+    /// Sets the project whose rules matched, `None` if no project did
+    fn assign_project(&mut self, _project: Option<String>) {}
 }
 
 #[derive(Debug, Default)]
 pub struct Classifier {
     pub afk_timeout: std::time::Duration,
     machine_name: String,
-    activities: Vec<ActivityInternal>
+    activities: Vec<ActivityInternal>,
+    projects: Vec<ProjectInternal>,
+    privacy: Vec<PrivacyInternal>
 }
 
 impl From<ClassifierConfig> for Classifier {
@@ -26,7 +40,7 @@ impl From<ClassifierConfig> for Classifier {
                 std::time::Duration::from_secs(75),
                 |secs| std::time::Duration::from_secs(secs)
             ),
-            machine_name: config.name.unwrap_or(String::from("unnamed machine")),
+            machine_name: config.name.unwrap_or(String::from(DEFAULT_MACHINE_NAME)),
             activities: match config.activity {
                 Some(conf_acts) => {
                     let mut acts: Vec<ActivityInternal> = vec![];
@@ -41,20 +55,49 @@ impl From<ClassifierConfig> for Classifier {
                     acts
                 },
                 None => vec![]
-            }
+            },
+            projects: config.project
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(Option::<ProjectInternal>::from)
+                .collect(),
+            privacy: config.privacy
+                .unwrap_or_default()
+                .into_iter()
+                .map(PrivacyInternal::from)
+                .collect()
         }
     }
 }
 
 impl Classifier {
+    /// Name of this machine set as `name` in the config file, records tracked here are stamped with it
+    pub fn machine_name(&self) -> &str {
+        &self.machine_name
+    }
+
+    /// Hides what privacy rules of the config file ask to hide, `None` if the window must not be tracked.
+    ///
+    /// Titles are hashed with the key of the store they're going to
+    pub fn apply_privacy(&self, archetype: Option<Archetype>, hash_key: &HashKey) -> Option<Archetype> {
+        archetype.and_then(|archetype| apply_privacy(&self.privacy, archetype, hash_key))
+    }
+
     pub fn classify(&self, record: &mut dyn Classifiable) {
         let activities = &self.activities;
-        let arch = record.get_archetype();
+        let no_title = String::new();
+        let window = match record.get_archetype() {
+            Archetype::ActiveWindow(title, name, class) => Some((title, name, class)),
+            // Incognito windows are classified by their app alone
+            Archetype::Incognito(name, class) => Some((&no_title, name, class)),
+            Archetype::AFK | Archetype::Private => None,
+        };
 
-        match arch {
-            Archetype::AFK => { record.assign_productivity(ProductivityStatus::Neutral); },
-            Archetype::ActiveWindow(title, name, class) => {
+        match window {
+            None => { record.assign_productivity(ProductivityStatus::Neutral); },
+            Some((title, name, class)) => {
                 let mut productivity: ProductivityStatus = ProductivityStatus::Neutral;
+                let mut tags: BTreeSet<String> = BTreeSet::new();
 
                 for activity in activities {
                     let act_prod = if activity.productivity > 0 {
@@ -66,16 +109,42 @@ impl Classifier {
                     };
         
                     'rules: for rule in &activity.rules {
-                        if rule.apply(&name, &class, &title) {
+                        if rule.apply_tagged(&name, &class, &title, |tag| record.has_tag(tag)) {
                             productivity = act_prod;
+                            tags.extend(activity.tags.iter().cloned());
                             break 'rules;
                         }
                     }
                 }
 
                 record.assign_productivity(productivity);
+                if !tags.is_empty() {
+                    record.assign_tags(tags);
+                }
             }
         }   
+
+        self.assign_project(record);
+    }
+
+    /// Matches projects after activities so that their rules see tags added by activities.
+    /// AFK and private records have no window, they only get projects from tag rules
+    fn assign_project(&self, record: &mut dyn Classifiable) {
+        let no_window = (String::new(), String::new(), String::new());
+        let (title, name, class) = match record.get_archetype() {
+            Archetype::ActiveWindow(title, name, class) => (title, name, class),
+            Archetype::Incognito(name, class) => (&no_window.0, name, class),
+            Archetype::AFK | Archetype::Private => (&no_window.0, &no_window.1, &no_window.2),
+        };
+        let mut project: Option<String> = None;
+
+        for candidate in &self.projects {
+            if candidate.rules.iter().any(|rule| rule.apply_tagged(name, class, title, |tag| record.has_tag(tag))) {
+                project = Some(candidate.name.clone());
+            }
+        }
+
+        record.assign_project(project);
     }
 }
 
@@ -1,15 +1,15 @@
 use toml;
-use std::{
-    fs::File,
-    io::Read
-};
-use crate::constants::*;
+use crate::config_file::{ config_file_path, read_config_file };
 
 #[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub struct Rule {
     pub for_name: Option<Vec<String>>,
     pub for_class: Option<Vec<String>>,
     pub for_title: Option<Vec<String>>,
+    /// Git repos by name, path or URL, matched when their name is a word of the title
+    pub for_repo: Option<Vec<String>>,
+    /// Tags a record needs any of, whether added by activities or by hand
+    pub for_tag: Option<Vec<String>>,
 
     pub title_contains_any: Option<Vec<String>>,
     pub title_contains_all: Option<Vec<String>>,
@@ -22,51 +22,45 @@ pub struct Rule {
 pub struct Activity {
     pub name: Option<String>,
     pub productivity: Option<i8>,
+    /// Tags of records matched by any of the rules
+    pub tags: Option<Vec<String>>,
     pub rule: Option<Vec<Rule>>
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ClassifierConfig {
+/// Client or piece of work records are billed to, independent of their activity
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Project {
     pub name: Option<String>,
-    pub afk_interval: Option<u64>,
-    pub activity: Option<Vec<Activity>>
+    pub rule: Option<Vec<Rule>>
 }
 
-fn get_config_from_dbg_file() -> ClassifierConfig {
-        let mut config = String::default();
-        let mut config_file = File::open(DEV_CONFIG_PATH)
-            .expect(&format!("Could not open dev config file at {}", DEV_CONFIG_PATH));
-        
-        config_file.read_to_string(&mut config).unwrap();
-        toml::from_str(&config).unwrap()
+/// Windows whose titles must not be stored as they are, applied before anything is stored
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Privacy {
+    /// `drop`, `private`, `hash` or `redact`
+    pub action: Option<String>,
+    /// Regular expressions of title parts replaced by `redact`
+    pub redact: Option<Vec<String>>,
+    pub rule: Option<Vec<Rule>>
 }
 
-fn get_global_config () -> ClassifierConfig {
-    match directories::ProjectDirs::from(APP_CLASSIFIER, APP_CORP, APP_NAME) {
-        Some(dirs) => {
-            let config_path = dirs.data_dir().join("config.toml");
-            let mut config: String = String::default();
-
-            match File::open(&config_path) {
-                Ok(ref mut file) => {
-                    file.read_to_string(&mut config).unwrap();
-                    toml::from_str(&config)
-                        .expect(&format!("Could not parse config file at {:?}", &config_path))
-                },
-                _ => get_config_from_dbg_file()
-            }
-        },
-        _ => get_config_from_dbg_file()
-    }
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClassifierConfig {
+    pub name: Option<String>,
+    pub afk_interval: Option<u64>,
+    pub activity: Option<Vec<Activity>>,
+    pub project: Option<Vec<Project>>,
+    pub privacy: Option<Vec<Privacy>>
 }
 
 impl Default for ClassifierConfig {
     fn default() -> Self {
-        #[cfg(debug_assertions)]
-        { get_config_from_dbg_file() }
-        
-        #[cfg(not(debug_assertions))]
-        { get_global_config() }
+        let config_path = config_file_path();
+        let config = read_config_file()
+            .expect(&format!("Could not open config file at {:?}", &config_path));
+
+        toml::from_str(&config)
+            .expect(&format!("Could not parse config file at {:?}", &config_path))
     }
 }
 
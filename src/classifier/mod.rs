@@ -1,10 +1,14 @@
 mod config;
 mod rules;
 mod activities;
+mod projects;
+mod privacy;
 mod classifier;
+mod reclassify;
 
 
 pub use self::{
     classifier::{ Classifier, Classifiable },
     config::ClassifierConfig,
+    reclassify::reclassify,
 };
@@ -0,0 +1,142 @@
+use super::{
+    config::Privacy,
+    rules::RuleInternal,
+};
+use crate::record_store::{ Archetype, HashKey };
+use regex::Regex;
+
+/// Replaces parts of titles matched by `redact` patterns
+const REDACTED: &'static str = "[redacted]";
+/// Number of hex digits of the HMAC kept in hashed titles
+const HASH_LENGTH: usize = 16;
+
+/// What happens to windows matched by a privacy rule, from the least to the most strict
+#[derive(Debug)]
+pub enum PrivacyAction {
+    /// Parts of the title matching any of the patterns are replaced
+    Redact(Vec<Regex>),
+    /// Title is replaced by its hash keyed with the store's `HashKey`, so that time of a window
+    /// still adds up while the title can't be guessed without the key
+    Hash,
+    /// Window is stored as `Archetype::Private`
+    Private,
+    /// Window is not tracked at all
+    Drop,
+}
+
+impl PrivacyAction {
+    fn strictness(&self) -> u8 {
+        match self {
+            PrivacyAction::Redact(_) => 0,
+            PrivacyAction::Hash => 1,
+            PrivacyAction::Private => 2,
+            PrivacyAction::Drop => 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PrivacyInternal {
+    pub action: PrivacyAction,
+    pub rules: Vec<RuleInternal>
+}
+
+impl From<Privacy> for PrivacyInternal {
+    /// Unknown actions and invalid patterns make windows private rather than leak their titles
+    fn from(privacy_conf: Privacy) -> PrivacyInternal {
+        let patterns: Result<Vec<Regex>, regex::Error> = privacy_conf.redact
+            .unwrap_or_default()
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect();
+        let action = match (privacy_conf.action.as_deref(), patterns) {
+            (Some("drop"), _) => PrivacyAction::Drop,
+            (Some("hash"), _) => PrivacyAction::Hash,
+            (Some("redact"), Ok(patterns)) => PrivacyAction::Redact(patterns),
+            _ => PrivacyAction::Private,
+        };
+
+        PrivacyInternal {
+            action,
+            rules: privacy_conf.rule
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(Option::<RuleInternal>::from)
+                .collect()
+        }
+    }
+}
+
+/// Applies the strictest of the privacy rules matching a window, `None` if it must not be tracked
+pub fn apply_privacy(rules: &[PrivacyInternal], archetype: Archetype, hash_key: &HashKey) -> Option<Archetype> {
+    let (title, name, class) = match archetype {
+        Archetype::ActiveWindow(title, name, class) => (title, name, class),
+        other => return Some(other),
+    };
+    let action = rules.iter()
+        .filter(|privacy| privacy.rules.iter().any(|rule| rule.apply(&name, &class, &title)))
+        .map(|privacy| &privacy.action)
+        .max_by_key(|action| action.strictness());
+
+    match action {
+        None => Some(Archetype::ActiveWindow(title, name, class)),
+        Some(PrivacyAction::Redact(patterns)) => {
+            let redacted = patterns.iter()
+                .fold(title, |title, pattern| pattern.replace_all(&title, REDACTED).into_owned());
+
+            Some(Archetype::ActiveWindow(redacted, name, class))
+        },
+        Some(PrivacyAction::Hash) => {
+            let digest = hash_key.hash(&title);
+
+            Some(Archetype::ActiveWindow(format!("hash:{}", &digest[..HASH_LENGTH]), name, class))
+        },
+        Some(PrivacyAction::Private) => Some(Archetype::Private),
+        Some(PrivacyAction::Drop) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn privacy(action: &str, redact: Option<Vec<&str>>, for_name: &str) -> PrivacyInternal {
+        let config: Privacy = toml::from_str(&format!(r#"
+            action = "{}"
+            [[rule]]
+                for_name = ["{}"]
+        "#, action, for_name)).unwrap();
+
+        PrivacyInternal::from(Privacy {
+            redact: redact.map(|patterns| patterns.into_iter().map(String::from).collect()),
+            ..config
+        })
+    }
+
+    fn window(title: &str, name: &str) -> Archetype {
+        Archetype::ActiveWindow(String::from(title), String::from(name), String::from("Class"))
+    }
+
+    #[test]
+    fn strictest_action_wins() {
+        let rules = vec![
+            privacy("redact", Some(vec![r"\d{4}-\d{4}"]), "Navigator"),
+            privacy("hash", None, "Navigator"),
+            privacy("private", None, "keepassxc"),
+            privacy("drop", None, "keepassxc"),
+            privacy("redact", Some(vec![r"\d{4}-\d{4}"]), "evince"),
+            privacy("nonsense", None, "signal"),
+        ];
+
+        let key = HashKey::generate();
+        let hashed = format!("hash:{}", &key.hash("Bank")[..HASH_LENGTH]);
+
+        assert_eq!(apply_privacy(&rules, window("Inbox", "code-oss"), &key), Some(window("Inbox", "code-oss")));
+        assert_eq!(apply_privacy(&rules, window("Card 1234-5678.pdf", "evince"), &key), Some(window("Card [redacted].pdf", "evince")));
+        assert_eq!(apply_privacy(&rules, window("Bank", "Navigator"), &key), Some(window(&hashed, "Navigator")));
+        assert_ne!(apply_privacy(&rules, window("Bank", "Navigator"), &HashKey::generate()), Some(window(&hashed, "Navigator")));
+        assert_eq!(apply_privacy(&rules, window("Passwords", "keepassxc"), &key), None);
+        assert_eq!(apply_privacy(&rules, window("Chat", "signal"), &key), Some(Archetype::Private));
+        assert_eq!(apply_privacy(&rules, Archetype::AFK, &key), Some(Archetype::AFK));
+    }
+}
@@ -0,0 +1,25 @@
+use super::{
+    config::Project,
+    rules::RuleInternal,
+};
+
+#[derive(Debug, Default)]
+pub struct ProjectInternal {
+    pub name: String,
+    pub rules: Vec<RuleInternal>
+}
+
+impl From<Project> for Option<ProjectInternal> {
+    fn from(project_conf: Project) -> Option<ProjectInternal> {
+        let rules = project_conf.rule
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(Option::<RuleInternal>::from)
+            .collect();
+
+        Some(ProjectInternal {
+            name: project_conf.name?,
+            rules
+        })
+    }
+}
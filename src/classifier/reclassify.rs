@@ -0,0 +1,177 @@
+use super::classifier::Classifier;
+use crate::record_store::{
+    ActivityRecord, Archetype, ProductivityStatus, RecordStore, RecordStoreError,
+};
+use chrono::{DateTime, Local, NaiveDate};
+use std::{collections::BTreeSet, fmt};
+
+/// Stored record whose productivity, tags or project are different under the current classifier rules
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Reclassified {
+    pub date: NaiveDate,
+    /// Record as it is stored
+    pub record: ActivityRecord,
+    pub productivity: ProductivityStatus,
+    pub tags: BTreeSet<String>,
+    pub project: Option<String>,
+}
+
+impl fmt::Display for Reclassified {
+    /// Prints the change as a diff, e.g.
+    /// ```text
+    /// 2020-06-01 10:00:00-10:05:00 code-oss "trackr - Code"
+    /// - neutral
+    /// + productive: coding
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = DateTime::<Local>::from(self.record.time_range.0);
+        let end = DateTime::<Local>::from(self.record.time_range.1);
+        let window = match &self.record.archetype {
+            Archetype::ActiveWindow(title, name, _) => format!("{} {:?}", name, title),
+            Archetype::AFK => String::from("AFK"),
+            Archetype::Private => String::from("Private"),
+            Archetype::Incognito(name, _) => format!("{} (incognito)", name),
+        };
+
+        writeln!(f, "{} {}-{} {}", self.date, start.format("%H:%M:%S"), end.format("%H:%M:%S"), window)?;
+        writeln!(f, "- {}", describe(&self.record.productivity))?;
+        write!(f, "+ {}", describe(&self.productivity))?;
+        if self.tags != self.record.tags {
+            write!(f, "\n- tags: {}\n+ tags: {}", join_tags(&self.record.tags), join_tags(&self.tags))?;
+        }
+        if self.project != self.record.project {
+            let describe_project = |project: &Option<String>| project.clone().unwrap_or_else(|| String::from("none"));
+
+            write!(f, "\n- project: {}\n+ project: {}", describe_project(&self.record.project), describe_project(&self.project))?;
+        }
+        Ok(())
+    }
+}
+
+fn join_tags(tags: &BTreeSet<String>) -> String {
+    tags.iter().cloned().collect::<Vec<String>>().join(", ")
+}
+
+fn describe(productivity: &ProductivityStatus) -> String {
+    match productivity {
+        ProductivityStatus::Productive(name) => format!("productive: {}", name),
+        ProductivityStatus::Leisure(name) => format!("leisure: {}", name),
+        ProductivityStatus::Neutral => String::from("neutral"),
+    }
+}
+
+/// Re-runs the classifier on records of tracking dates from `from` to `to` inclusive.
+///
+/// Returns records whose activity, productivity, tags or project changed, with `dry_run`
+/// the store is left untouched. Tags of matching activities are added, tags are never removed
+pub fn reclassify(
+    classifier: &Classifier,
+    store: &RecordStore,
+    from: &NaiveDate,
+    to: &NaiveDate,
+    dry_run: bool,
+) -> Result<Vec<Reclassified>, RecordStoreError> {
+    let mut changes: Vec<Reclassified> = vec![];
+
+    for date in store.query_dates()? {
+        if date < *from || date > *to {
+            continue;
+        }
+
+        let mut records = match store.query_records_by_date(&date) {
+            Err(RecordStoreError::NoDataOnDate(_)) => continue,
+            records => records?,
+        };
+        let changes_before = changes.len();
+
+        for record in records.iter_mut() {
+            let stored = record.clone();
+
+            classifier.classify(record);
+            if record.productivity != stored.productivity || record.tags != stored.tags || record.project != stored.project {
+                changes.push(Reclassified {
+                    date,
+                    record: stored,
+                    productivity: record.productivity.clone(),
+                    tags: record.tags.clone(),
+                    project: record.project.clone(),
+                });
+            }
+        }
+
+        if !dry_run && changes.len() > changes_before {
+            store.replace_records(&date, records)?;
+        }
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        classifier::ClassifierConfig,
+        record_store::{test_dir, RecordStoreConfig},
+    };
+    use chrono::TimeZone;
+    use std::time::SystemTime;
+
+    fn record(hour: u32, title: &str) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (
+                SystemTime::from(Local.ymd(2020, 6, 1).and_hms(hour, 0, 0)),
+                SystemTime::from(Local.ymd(2020, 6, 1).and_hms(hour, 30, 0)),
+            ),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::ActiveWindow(String::from(title), String::from("code-oss"), String::from("Code")),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn dry_run_keeps_records() {
+        let store = RecordStore::new(RecordStoreConfig::in_dir(test_dir("reclassify"))).unwrap();
+        let config: ClassifierConfig = toml::from_str(r#"
+            [[activity]]
+                name = "coding"
+                productivity = 1
+                tags = ["trackr"]
+
+                [[activity.rule]]
+                    for_name = ["code-oss"]
+                    title_contains_any = ["trackr"]
+
+            [[project]]
+                name = "trackr"
+
+                [[project.rule]]
+                    for_tag = ["trackr"]
+        "#).unwrap();
+        let classifier = Classifier::from(config);
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+
+        store.push_record(record(10, "trackr")).unwrap();
+        store.push_record(record(11, "notes")).unwrap();
+
+        let changes = reclassify(&classifier, &store, &date, &date, true).unwrap();
+        assert_eq!(changes, vec![Reclassified {
+            date,
+            record: record(10, "trackr"),
+            productivity: ProductivityStatus::Productive(String::from("coding")),
+            tags: vec![String::from("trackr")].into_iter().collect(),
+            project: Some(String::from("trackr")),
+        }]);
+        assert_eq!(store.query_records_by_date(&date).unwrap()[0].productivity, ProductivityStatus::Neutral);
+
+        reclassify(&classifier, &store, &date, &date, false).unwrap();
+        assert_eq!(
+            store.query_records_by_date(&date).unwrap()[0].productivity,
+            ProductivityStatus::Productive(String::from("coding"))
+        );
+        assert_eq!(reclassify(&classifier, &store, &date, &date, true).unwrap(), vec![]);
+    }
+}
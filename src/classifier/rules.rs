@@ -22,7 +22,9 @@ pub enum Filter {
 pub enum RuleInternal {
     ForName(Vec<String>, Vec<Filter>),
     ForClass(Vec<String>, Vec<Filter>),
-    ForTitle(Vec<String>, Vec<Filter>)
+    ForTitle(Vec<String>, Vec<Filter>),
+    ForRepo(Vec<String>, Vec<Filter>),
+    ForTag(Vec<String>, Vec<Filter>)
 }
 
 impl Default for RuleInternal {
@@ -57,7 +59,13 @@ impl From<RawRule> for Option<RuleInternal> {
                 Some(names) => Some(RuleInternal::ForName(names, filters)),
                 _ => match raw.for_title {
                     Some(titles) => Some(RuleInternal::ForTitle(titles, filters)),
-                    _ => None
+                    _ => match raw.for_repo {
+                        Some(repos) => Some(RuleInternal::ForRepo(repos, filters)),
+                        _ => match raw.for_tag {
+                            Some(tags) => Some(RuleInternal::ForTag(tags, filters)),
+                            _ => None
+                        }
+                    }
                 }
             }
         }
@@ -85,10 +93,41 @@ impl RuleInternal {
                     true
                 } else {
                     false
-                }
+                },
+            RuleInternal::ForRepo(repos, filters) =>
+                if repos.iter().any(|repo| Self::mentions_repo(r_title, repo)) {
+                    Self::check_title(r_title, filters)
+                } else {
+                    false
+                },
+            // Tags are only known to `apply_tagged`
+            RuleInternal::ForTag(_, _) => false
+        }
+    }
+
+    /// Applies the rule to a record that may have tags
+    pub fn apply_tagged<F: Fn(&str) -> bool>(&self, r_name: &String, r_class: &String, r_title: &String, has_tag: F) -> bool {
+        match self {
+            RuleInternal::ForTag(tags, filters) =>
+                if tags.iter().any(|tag| has_tag(tag)) {
+                    Self::check_title(r_title, filters)
+                } else {
+                    false
+                },
+            _ => self.apply(r_name, r_class, r_title)
         }
     }
 
+    /// Checks whether the name of a repo given by name, path or URL is a word of the title,
+    /// editors and terminals show the directory they're opened in
+    fn mentions_repo(title: &str, repo: &str) -> bool {
+        let repo = repo.trim_end_matches('/');
+        let name = repo.trim_end_matches(".git").rsplit(&['/', ':'][..]).next().unwrap_or(repo);
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_' || c == '.';
+
+        !name.is_empty() && title.split(|c| !is_word_char(c)).any(|word| word == name)
+    }
+
     fn check_title(title: &String, filters: &Vec<Filter>) -> bool {
         let mut result: bool = filters.len() == 0;
 
@@ -192,4 +231,17 @@ mod tests {
 
         assert_eq!(res, true);
     }
+
+    #[test]
+    fn repo_and_tag_test() {
+        let repo = RuleInternal::ForRepo(vec![String::from("git@github.com:someone/trackr.git")], vec![]);
+        let tag = RuleInternal::ForTag(vec![String::from("client-x")], vec![]);
+        let title = |title: &str| String::from(title);
+
+        assert_eq!(repo.apply(&title("code-oss"), &title("Code"), &title("main.rs - trackr - Code - OSS")), true);
+        assert_eq!(repo.apply(&title("Alacritty"), &title("Alacritty"), &title("~/code/trackr/src")), true);
+        assert_eq!(repo.apply(&title("Alacritty"), &title("Alacritty"), &title("~/code/trackr-web")), false);
+        assert_eq!(tag.apply(&title("code-oss"), &title("Code"), &title("main.rs")), false);
+        assert_eq!(tag.apply_tagged(&title("code-oss"), &title("Code"), &title("main.rs"), |tag| tag == "client-x"), true);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,18 @@
+use crate::record_store::{RecordStore, RecordStoreConfig};
+use clap::{App, ArgMatches, SubCommand};
+
+pub const NAME: &'static str = "compact";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Merges records that continue each other and archives days past `retention_days`")
+}
+
+pub fn run(_args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let store = RecordStore::new(RecordStoreConfig::load()?)?;
+    let merged = store.compact()?;
+    let archived = store.apply_retention()?;
+
+    eprintln!("Merged {} records, archived {} days", merged, archived);
+    Ok({})
+}
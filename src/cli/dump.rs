@@ -0,0 +1,45 @@
+use crate::{
+    export::ExportRow,
+    record_store::{FsckReport, RecordStore, RecordStoreConfig},
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+pub const NAME: &'static str = "dump";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Dumps all readable days of the record store as JSON, even if the store is damaged")
+        .arg(Arg::with_name("output")
+            .long("output")
+            .short("o")
+            .value_name("FILE")
+            .help("File to write the dump to, defaults to standard output"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let config = RecordStoreConfig::load()?;
+    let cipher = RecordStore::unlock(&config)?;
+    let report = FsckReport::check(&config, cipher.as_ref())?;
+    let days: BTreeMap<String, Vec<ExportRow>> = report.days
+        .iter()
+        .map(|(date, records)| (date.to_string(), records.iter().map(ExportRow::from).collect()))
+        .collect();
+
+    let mut out: Box<dyn Write> = match args.value_of("output") {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+    serde_json::to_writer_pretty(&mut out, &days)?;
+    writeln!(out)?;
+
+    for problem in &report.problems {
+        eprintln!("{}", problem);
+    }
+    eprintln!("Dumped {} records of {} days", report.record_count(), report.days.len());
+    Ok({})
+}
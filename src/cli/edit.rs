@@ -0,0 +1,213 @@
+use super::time_arg;
+use crate::{
+    classifier::{Classifier, ClassifierConfig},
+    record_store::{
+        ActivityRecord, Archetype, AuditEntry, Edit, ProductivityStatus, RecordStore, RecordStoreConfig,
+        MANUAL_APP_CLASS, MANUAL_APP_NAME,
+    },
+};
+use chrono::{DateTime, Local};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::{collections::BTreeSet, time::SystemTime};
+
+pub const NAME: &'static str = "edit";
+
+fn time(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .value_name("TIME")
+        .required(true)
+        .help(help)
+}
+
+fn productivity_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("productivity")
+            .long("productivity")
+            .short("p")
+            .value_name("STATUS")
+            .possible_values(&["productive", "neutral", "leisure"]),
+        Arg::with_name("activity")
+            .long("activity")
+            .short("a")
+            .value_name("NAME")
+            .help("Activity of productive and leisure records"),
+    ]
+}
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Corrects stored records by hand, every edit is logged and can be reverted")
+        .after_help("Times are local and given as \"YYYY-MM-DD HH:MM[:SS]\", or as \"HH:MM[:SS]\" of today")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("classify")
+            .about("Sets productivity of the record tracked at a time")
+            .arg(time("at", "Time within the record"))
+            .args(&productivity_args()))
+        .subcommand(SubCommand::with_name("split")
+            .about("Splits the record tracked at a time in two")
+            .arg(time("at", "Time to split at")))
+        .subcommand(SubCommand::with_name("merge")
+            .about("Joins the record tracked at a time with the one right after it")
+            .arg(time("at", "Time within the first record")))
+        .subcommand(SubCommand::with_name("delete")
+            .about("Deletes records within a time range")
+            .arg(time("from", "Start of the range"))
+            .arg(time("to", "End of the range")))
+        .subcommand(SubCommand::with_name("insert")
+            .about("Adds a record, e.g. for a meeting away from the computer, in place of those it overlaps")
+            .arg(time("from", "Start of the record"))
+            .arg(time("to", "End of the record"))
+            .arg(Arg::with_name("title")
+                .long("title")
+                .short("t")
+                .value_name("TEXT")
+                .required(true))
+            .args(&productivity_args())
+            .after_help("Records are classified by the rules of the config file, --productivity overrides their productivity"))
+        .subcommand(SubCommand::with_name("tag")
+            .about("Tags records within a time range")
+            .arg(time("from", "Start of the range"))
+            .arg(time("to", "End of the range"))
+            .arg(Arg::with_name("tags")
+                .value_name("TAG")
+                .required(true)
+                .multiple(true)))
+        .subcommand(SubCommand::with_name("untag")
+            .about("Removes tags from records within a time range")
+            .arg(time("from", "Start of the range"))
+            .arg(time("to", "End of the range"))
+            .arg(Arg::with_name("tags")
+                .value_name("TAG")
+                .required(true)
+                .multiple(true)))
+        .subcommand(SubCommand::with_name("note")
+            .about("Adds a note to records within a time range")
+            .arg(time("from", "Start of the range"))
+            .arg(time("to", "End of the range"))
+            .arg(Arg::with_name("text")
+                .value_name("TEXT")
+                .help("Text of the note, notes of the records are cleared if it's not given")))
+        .subcommand(SubCommand::with_name("log")
+            .about("Prints edits made so far")
+            .arg(Arg::with_name("limit")
+                .long("limit")
+                .short("n")
+                .value_name("COUNT")
+                .help("Prints only the latest edits")))
+        .subcommand(SubCommand::with_name("revert")
+            .about("Brings back records as they were before an edit")
+            .arg(Arg::with_name("number")
+                .value_name("N")
+                .required(true)
+                .help("Number of the edit as printed by the log")))
+}
+
+fn productivity_arg(args: &ArgMatches) -> Result<Option<ProductivityStatus>, String> {
+    let activity = || args.value_of("activity")
+        .map(String::from)
+        .ok_or_else(|| String::from("Productive and leisure records need an --activity"));
+
+    match args.value_of("productivity") {
+        Some("productive") => Ok(Some(ProductivityStatus::Productive(activity()?))),
+        Some("leisure") => Ok(Some(ProductivityStatus::Leisure(activity()?))),
+        Some(_) => Ok(Some(ProductivityStatus::Neutral)),
+        None => Ok(None),
+    }
+}
+
+fn tags_arg(args: &ArgMatches) -> BTreeSet<String> {
+    args.values_of("tags").map_or_else(BTreeSet::new, |tags| tags.map(String::from).collect())
+}
+
+fn required_time(args: &ArgMatches, name: &str) -> Result<SystemTime, String> {
+    time_arg(args, name)?.ok_or_else(|| format!("Missing {}", name))
+}
+
+fn format_time(time: &SystemTime) -> String {
+    DateTime::<Local>::from(*time).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn print_entry(number: usize, entry: &AuditEntry) {
+    println!(
+        "#{} {} [{}] {}: -{} +{} records",
+        number, format_time(&entry.made_at), entry.date, entry.edit, entry.removed.len(), entry.added.len()
+    );
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let (command, args) = match args.subcommand() {
+        (command, Some(args)) => (command, args),
+        _ => return Ok({}),
+    };
+
+    if command == "log" {
+        let store = RecordStore::new_read_only(RecordStoreConfig::load()?)?;
+        let entries = store.audit_entries()?;
+        let limit = match args.value_of("limit") {
+            Some(limit) => limit.parse::<usize>().map_err(|_| format!("Invalid --limit \"{}\"", limit))?,
+            None => entries.len(),
+        };
+
+        for (index, entry) in entries.iter().enumerate().skip(entries.len().saturating_sub(limit)) {
+            print_entry(index + 1, entry);
+        }
+        return Ok({});
+    }
+
+    let store = RecordStore::new(RecordStoreConfig::load()?)?;
+    let edit = match command {
+        "classify" => Edit::SetProductivity(
+            required_time(args, "at")?,
+            productivity_arg(args)?.ok_or_else(|| String::from("Missing --productivity"))?,
+        ),
+        "split" => Edit::Split(required_time(args, "at")?),
+        "merge" => Edit::MergeNext(required_time(args, "at")?),
+        "delete" => Edit::Delete(required_time(args, "from")?, required_time(args, "to")?),
+        "tag" => Edit::AddTags(required_time(args, "from")?, required_time(args, "to")?, tags_arg(args)),
+        "untag" => Edit::RemoveTags(required_time(args, "from")?, required_time(args, "to")?, tags_arg(args)),
+        "note" => Edit::SetNote(
+            required_time(args, "from")?,
+            required_time(args, "to")?,
+            args.value_of("text").filter(|text| !text.is_empty()).map(String::from),
+        ),
+        "insert" => {
+            let classifier = Classifier::from(ClassifierConfig::default());
+            let mut record = ActivityRecord {
+                time_range: (required_time(args, "from")?, required_time(args, "to")?),
+                productivity: ProductivityStatus::Neutral,
+                archetype: Archetype::ActiveWindow(
+                    String::from(args.value_of("title").unwrap_or_default()),
+                    String::from(MANUAL_APP_NAME),
+                    String::from(MANUAL_APP_CLASS),
+                ),
+                machine: Some(String::from(classifier.machine_name())),
+                tags: BTreeSet::new(),
+                note: None,
+                project: None,
+            };
+
+            // Rules still assign tags and a project to records given a productivity
+            classifier.classify(&mut record);
+            if let Some(productivity) = productivity_arg(args)? {
+                record.productivity = productivity;
+            }
+            Edit::Insert(record)
+        },
+        _ => {
+            let number = args.value_of("number").unwrap_or_default();
+
+            Edit::Revert(number.parse().map_err(|_| format!("Invalid edit number \"{}\"", number))?)
+        },
+    };
+
+    let entries = store.edit_records(edit)?;
+    let first_number = store.audit_entries()?.len() - entries.len() + 1;
+
+    for (index, entry) in entries.iter().enumerate() {
+        print_entry(first_number + index, entry);
+    }
+    if entries.is_empty() {
+        eprintln!("Nothing changed");
+    }
+    Ok({})
+}
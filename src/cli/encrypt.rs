@@ -0,0 +1,29 @@
+use crate::record_store::{KeyParams, PassphraseSource, RecordStore, RecordStoreConfig};
+use clap::{App, ArgMatches, SubCommand};
+
+pub const NAME: &'static str = "encrypt";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Encrypts the record store with a passphrase")
+        .after_help(concat!(
+            "The passphrase is taken from TRACKR_PASSPHRASE, the keyfile set in the config file ",
+            "or a prompt, in this order. Use `rekey` to change the passphrase of an encrypted store"
+        ))
+}
+
+pub fn run(_args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let config = RecordStoreConfig::load()?;
+
+    if KeyParams::load(&config.data_dir)?.is_some() {
+        return Err(Box::from("Record store is already encrypted, use `rekey` to change its passphrase"));
+    }
+
+    let passphrase = PassphraseSource::detect(config.keyfile.as_deref()).read_new("New passphrase: ")?;
+    let (params, cipher) = KeyParams::create(&passphrase)?;
+    let mut store = RecordStore::open(config, None)?;
+
+    store.encrypt(&params, cipher)?;
+    eprintln!("Record store is encrypted");
+    Ok({})
+}
@@ -0,0 +1,77 @@
+use super::{date_arg, record_filter};
+use crate::{
+    export::{export_records, ExportFormat},
+    record_store::{format_duration, RecordStore, RecordStoreConfig},
+    stats::Stats,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    str::FromStr,
+    time::Duration,
+};
+
+pub const NAME: &'static str = "export";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Exports records of a range of tracking dates")
+        .arg(Arg::with_name("from")
+            .long("from")
+            .value_name("DATE")
+            .help("First tracking date to export, defaults to today"))
+        .arg(Arg::with_name("to")
+            .long("to")
+            .value_name("DATE")
+            .help("Last tracking date to export, defaults to --from"))
+        .arg(Arg::with_name("tag")
+            .long("tag")
+            .short("t")
+            .value_name("TAG")
+            .help("Exports only records with this tag"))
+        .arg(Arg::with_name("project")
+            .long("project")
+            .short("p")
+            .value_name("NAME")
+            .help("Exports only records of this project"))
+        .arg(Arg::with_name("format")
+            .long("format")
+            .short("f")
+            .possible_values(&["csv", "ndjson", "ics"])
+            .default_value("csv"))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .short("o")
+            .value_name("FILE")
+            .help("File to write records to, defaults to standard output"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    // Export may run alongside the tracker
+    let store = RecordStore::new_read_only(RecordStoreConfig::load()?)?;
+    let from = date_arg(args, "from", &store)?.unwrap_or(store.today());
+    let to = date_arg(args, "to", &store)?.unwrap_or(from);
+    let format = ExportFormat::from_str(args.value_of("format").unwrap_or("csv"))?;
+    let filter = record_filter(args);
+
+    let count = match args.value_of("output") {
+        Some(path) => export_records(&store, &from, &to, &filter, format, &mut BufWriter::new(File::create(path)?))?,
+        None => export_records(&store, &from, &to, &filter, format, &mut io::stdout().lock())?,
+    };
+
+    let stats = Stats::for_dates(&store, &from, &to, &filter)?;
+
+    eprintln!(
+        "Exported {} records from {} to {}, {} tracked",
+        count, from, to, format_duration(Duration::from_secs(stats.total_secs))
+    );
+    // Days past the retention window have no records left to export
+    if stats.archived_secs > 0 {
+        eprintln!(
+            "{} of it is archived as totals only and not exported, see `trackr report`",
+            format_duration(Duration::from_secs(stats.archived_secs))
+        );
+    }
+    Ok({})
+}
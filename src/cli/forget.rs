@@ -0,0 +1,47 @@
+use crate::{
+    control::{parse_minutes, send, ControlCommand, ControlError},
+    record_store::{ForgetMode, RecordStore, RecordStoreConfig},
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const NAME: &'static str = "forget";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Drops what was tracked over the last minutes, including the record the running tracker is on")
+        .arg(Arg::with_name("minutes")
+            .value_name("MINUTES")
+            .required(true))
+        .arg(Arg::with_name("anonymize")
+            .long("anonymize")
+            .help("Only drops window titles, the time still counts towards apps and activities"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let period = args.value_of("minutes")
+        .and_then(parse_minutes)
+        .ok_or("MINUTES must be a whole number of minutes")?;
+    let mode = match args.is_present("anonymize") {
+        true => ForgetMode::Anonymize,
+        false => ForgetMode::Remove,
+    };
+    let config = RecordStoreConfig::load()?;
+
+    // The running tracker holds the store, it forgets on our behalf
+    match send(&config.data_dir, ControlCommand::Forget(period, mode)) {
+        Ok(answer) => {
+            eprintln!("{}", answer);
+            return Ok({});
+        },
+        Err(ControlError::NotRunning) => {},
+        Err(err) => return Err(Box::new(err)),
+    }
+
+    let store = RecordStore::new(config)?;
+    let now = SystemTime::now();
+    let since = now.checked_sub(period).unwrap_or(UNIX_EPOCH);
+
+    eprintln!("forgot {} records", store.forget(&since, &now, mode)?);
+    Ok({})
+}
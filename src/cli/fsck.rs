@@ -0,0 +1,43 @@
+use crate::record_store::{FsckReport, RecordStore, RecordStoreConfig};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub const NAME: &'static str = "fsck";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Checks the record store for damaged files and inconsistent records")
+        .arg(Arg::with_name("salvage")
+            .long("salvage")
+            .help("Rewrites the store with its readable days, damaged files are kept as *.corrupt-*"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let config = RecordStoreConfig::load()?;
+    let cipher = RecordStore::unlock(&config)?;
+    let report = FsckReport::check(&config, cipher.as_ref())?;
+
+    for problem in &report.problems {
+        println!("{}", problem);
+    }
+    eprintln!(
+        "Checked {} days with {} records, found {} problems",
+        report.days.len(), report.record_count(), report.problems.len()
+    );
+
+    if report.is_clean() {
+        return Ok({});
+    }
+    if !args.is_present("salvage") {
+        return Err(Box::from("Record store is damaged, run `trackr fsck --salvage` to keep the readable days"));
+    }
+
+    let summary = report.salvage(&config, cipher.as_ref())?;
+    eprintln!(
+        "Salvaged {} records of {} days, dropped {} inconsistent records",
+        summary.records, summary.days, summary.dropped
+    );
+    for path in &summary.backups {
+        eprintln!("Damaged file is kept at {}", path.display());
+    }
+    Ok({})
+}
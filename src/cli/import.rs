@@ -0,0 +1,41 @@
+use crate::{
+    classifier::{Classifier, ClassifierConfig},
+    import::{import_records, ImportFormat},
+    record_store::{RecordStore, RecordStoreConfig},
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::str::FromStr;
+
+pub const NAME: &'static str = "import";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Imports history from ActivityWatch bucket exports or Timewarrior data files")
+        .arg(Arg::with_name("format")
+            .long("format")
+            .short("f")
+            .value_name("FORMAT")
+            .possible_values(&["aw", "timew"])
+            .required(true))
+        .arg(Arg::with_name("files")
+            .value_name("FILE")
+            .multiple(true)
+            .required(true))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let store = RecordStore::new(RecordStoreConfig::load()?)?;
+    let classifier = Classifier::from(ClassifierConfig::default());
+    let format = ImportFormat::from_str(args.value_of("format").unwrap_or_default())?;
+
+    for path in args.values_of("files").into_iter().flatten() {
+        let contents = std::fs::read_to_string(path)?;
+        let summary = import_records(&contents, format, &classifier, &store)?;
+
+        eprintln!(
+            "{}: imported {} of {} records, {} overlapped existing history",
+            path, summary.inserted, summary.parsed, summary.parsed - summary.inserted
+        );
+    }
+    Ok({})
+}
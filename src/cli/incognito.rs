@@ -0,0 +1,27 @@
+use crate::{
+    control::{send, ControlCommand},
+    record_store::RecordStoreConfig,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub const NAME: &'static str = "incognito";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Switches incognito mode of the running tracker, windows are tracked without titles while it is on")
+        .arg(Arg::with_name("mode")
+            .possible_values(&["on", "off", "toggle", "status"])
+            .default_value("status"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let command = match args.value_of("mode") {
+        Some("on") => ControlCommand::Incognito(Some(true)),
+        Some("off") => ControlCommand::Incognito(Some(false)),
+        Some("toggle") => ControlCommand::Incognito(None),
+        _ => ControlCommand::Status,
+    };
+
+    println!("{}", send(&RecordStoreConfig::load()?.data_dir, command)?);
+    Ok({})
+}
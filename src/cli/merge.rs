@@ -0,0 +1,37 @@
+use crate::{
+    classifier::{Classifier, ClassifierConfig},
+    import::{merge_records, MergeSource},
+    record_store::{RecordStore, RecordStoreConfig},
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::path::Path;
+
+pub const NAME: &'static str = "merge";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Merges history of another machine from its data directory, records.db or an NDJSON export")
+        .arg(Arg::with_name("path")
+            .value_name("PATH")
+            .required(true))
+        .arg(Arg::with_name("machine")
+            .long("machine")
+            .short("m")
+            .value_name("NAME")
+            .help("Machine name of records that have none, e.g. tracked before machines were named"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.value_of("path").unwrap_or_default();
+    let source = MergeSource::detect(Path::new(path));
+    let records = source.read_records()?;
+    let store = RecordStore::new(RecordStoreConfig::load()?)?;
+    let classifier = Classifier::from(ClassifierConfig::default());
+    let summary = merge_records(records, args.value_of("machine"), classifier.machine_name(), &store)?;
+
+    eprintln!(
+        "{}: merged {} of {} records, {} were tracked on this machine; {} days changed",
+        path, summary.merged, summary.read, summary.read - summary.merged, summary.days_changed
+    );
+    Ok({})
+}
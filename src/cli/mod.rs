@@ -0,0 +1,111 @@
+mod compact;
+mod dump;
+mod edit;
+mod encrypt;
+mod export;
+mod forget;
+mod fsck;
+mod import;
+mod incognito;
+mod merge;
+mod reclassify;
+mod rekey;
+mod report;
+mod search;
+mod serve;
+
+use crate::{
+    constants::APP_NAME,
+    record_store::{RecordFilter, RecordStore},
+};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use clap::{App, ArgMatches};
+use std::time::SystemTime;
+
+/// Command line interface of the app, the TUI is started when no subcommand is given
+pub fn app() -> App<'static, 'static> {
+    App::new(APP_NAME)
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Personal productivity tracker")
+        .subcommand(export::subcommand())
+        .subcommand(import::subcommand())
+        .subcommand(merge::subcommand())
+        .subcommand(reclassify::subcommand())
+        .subcommand(edit::subcommand())
+        .subcommand(encrypt::subcommand())
+        .subcommand(rekey::subcommand())
+        .subcommand(compact::subcommand())
+        .subcommand(fsck::subcommand())
+        .subcommand(dump::subcommand())
+        .subcommand(search::subcommand())
+        .subcommand(report::subcommand())
+        .subcommand(serve::subcommand())
+        .subcommand(incognito::subcommand())
+        .subcommand(forget::subcommand())
+}
+
+/// Runs the subcommand given in the arguments, returns `None` if there is none
+pub fn run(matches: &ArgMatches) -> Option<Result<(), Box<dyn std::error::Error>>> {
+    match matches.subcommand() {
+        (export::NAME, Some(args)) => Some(export::run(args)),
+        (import::NAME, Some(args)) => Some(import::run(args)),
+        (merge::NAME, Some(args)) => Some(merge::run(args)),
+        (reclassify::NAME, Some(args)) => Some(reclassify::run(args)),
+        (edit::NAME, Some(args)) => Some(edit::run(args)),
+        (encrypt::NAME, Some(args)) => Some(encrypt::run(args)),
+        (rekey::NAME, Some(args)) => Some(rekey::run(args)),
+        (compact::NAME, Some(args)) => Some(compact::run(args)),
+        (fsck::NAME, Some(args)) => Some(fsck::run(args)),
+        (dump::NAME, Some(args)) => Some(dump::run(args)),
+        (search::NAME, Some(args)) => Some(search::run(args)),
+        (report::NAME, Some(args)) => Some(report::run(args)),
+        (serve::NAME, Some(args)) => Some(serve::run(args)),
+        (incognito::NAME, Some(args)) => Some(incognito::run(args)),
+        (forget::NAME, Some(args)) => Some(forget::run(args)),
+        _ => None,
+    }
+}
+
+/// Gets a tracking date argument given as `YYYY-MM-DD`, `today` or `yesterday`
+pub fn date_arg(args: &ArgMatches, name: &str, store: &RecordStore) -> Result<Option<NaiveDate>, String> {
+    let value = match args.value_of(name) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    match value {
+        "today" => Ok(Some(store.today())),
+        "yesterday" => Ok(Some(store.today().pred())),
+        _ => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| format!("Invalid date \"{}\" for --{}, expected YYYY-MM-DD", value, name)),
+    }
+}
+
+/// Gets the filter given with `--tag` and `--project`
+pub fn record_filter(args: &ArgMatches) -> RecordFilter {
+    RecordFilter {
+        tag: args.value_of("tag").map(String::from),
+        project: args.value_of("project").map(String::from),
+    }
+}
+
+/// Gets a local time argument given as `YYYY-MM-DD HH:MM[:SS]`, or as `HH:MM[:SS]` of today
+pub fn time_arg(args: &ArgMatches, name: &str) -> Result<Option<SystemTime>, String> {
+    let value = match args.value_of(name) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let invalid = || format!("Invalid time \"{}\" for {}, expected YYYY-MM-DD HH:MM[:SS] or HH:MM[:SS]", value, name);
+    let time = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| ["%H:%M:%S", "%H:%M"].iter()
+            .find_map(|format| NaiveTime::parse_from_str(value, format).ok())
+            .map(|time| Local::today().naive_local().and_time(time)))
+        .ok_or_else(invalid)?;
+
+    Local.from_local_datetime(&time)
+        .earliest()
+        .map(|time| Some(SystemTime::from(time)))
+        .ok_or_else(invalid)
+}
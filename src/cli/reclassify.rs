@@ -0,0 +1,45 @@
+use super::date_arg;
+use crate::{
+    classifier::{reclassify, Classifier, ClassifierConfig},
+    record_store::{RecordStore, RecordStoreConfig},
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub const NAME: &'static str = "reclassify";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Re-runs classifier rules from the config file on stored records")
+        .arg(Arg::with_name("from")
+            .long("from")
+            .value_name("DATE")
+            .help("First tracking date to re-classify, defaults to today"))
+        .arg(Arg::with_name("to")
+            .long("to")
+            .value_name("DATE")
+            .help("Last tracking date to re-classify, defaults to --from"))
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Prints records that would change without storing anything"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let store = RecordStore::new(RecordStoreConfig::load()?)?;
+    let classifier = Classifier::from(ClassifierConfig::default());
+    let from = date_arg(args, "from", &store)?.unwrap_or(store.today());
+    let to = date_arg(args, "to", &store)?.unwrap_or(from);
+    let dry_run = args.is_present("dry-run");
+
+    let changes = reclassify(&classifier, &store, &from, &to, dry_run)?;
+
+    for change in &changes {
+        println!("{}", change);
+    }
+
+    if dry_run {
+        eprintln!("{} records would be re-classified", changes.len());
+    } else {
+        eprintln!("{} records were re-classified", changes.len());
+    }
+    Ok({})
+}
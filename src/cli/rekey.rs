@@ -0,0 +1,34 @@
+use crate::record_store::{KeyParams, PassphraseSource, RecordStore, RecordStoreConfig};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::path::PathBuf;
+
+pub const NAME: &'static str = "rekey";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Changes the passphrase of an encrypted record store")
+        .arg(Arg::with_name("new-keyfile")
+            .long("new-keyfile")
+            .value_name("FILE")
+            .help("Takes the new passphrase from a file instead of a prompt"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let config = RecordStoreConfig::load()?;
+
+    if KeyParams::load(&config.data_dir)?.is_none() {
+        return Err(Box::from("Record store is not encrypted, use `encrypt` to set a passphrase"));
+    }
+
+    let mut store = RecordStore::new(config)?;
+    let source = match args.value_of("new-keyfile") {
+        Some(path) => PassphraseSource::Keyfile(PathBuf::from(path)),
+        None => PassphraseSource::Prompt,
+    };
+    let passphrase = source.read_new("New passphrase: ")?;
+    let (params, cipher) = KeyParams::create(&passphrase)?;
+
+    store.encrypt(&params, cipher)?;
+    eprintln!("Passphrase of the record store is changed");
+    Ok({})
+}
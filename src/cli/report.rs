@@ -0,0 +1,121 @@
+use super::{date_arg, record_filter};
+use crate::{
+    record_store::{format_duration, RecordStore, RecordStoreConfig},
+    stats::Stats,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::{collections::BTreeMap, time::Duration};
+
+pub const NAME: &'static str = "report";
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+/// Width of the longest histogram bar
+const BAR_WIDTH: u64 = 40;
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Prints totals of tracked time for a range of tracking dates")
+        .arg(Arg::with_name("from")
+            .long("from")
+            .value_name("DATE")
+            .help("First tracking date of the report, defaults to today"))
+        .arg(Arg::with_name("to")
+            .long("to")
+            .value_name("DATE")
+            .help("Last tracking date of the report, defaults to --from"))
+        .arg(Arg::with_name("top")
+            .long("top")
+            .value_name("COUNT")
+            .default_value("10")
+            .help("Number of titles, apps and activities with the most time to print"))
+        .arg(Arg::with_name("tag")
+            .long("tag")
+            .short("t")
+            .value_name("TAG")
+            .help("Counts only records with this tag"))
+        .arg(Arg::with_name("project")
+            .long("project")
+            .short("p")
+            .value_name("NAME")
+            .help("Counts only records of this project"))
+        .arg(Arg::with_name("json")
+            .long("json")
+            .help("Prints the report as JSON"))
+}
+
+/// Report as printed with `--json`
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    from: String,
+    to: String,
+    #[serde(flatten)]
+    stats: &'a Stats,
+    top_titles: Vec<(String, u64)>,
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    // Reports may run alongside the tracker
+    let store = RecordStore::new_read_only(RecordStoreConfig::load()?)?;
+    let from = date_arg(args, "from", &store)?.unwrap_or(store.today());
+    let to = date_arg(args, "to", &store)?.unwrap_or(from);
+    let top: usize = args.value_of("top").unwrap_or("10").parse()
+        .map_err(|_| String::from("--top must be a number"))?;
+    let filter = record_filter(args);
+    let stats = Stats::for_dates(&store, &from, &to, &filter)?;
+
+    if args.is_present("json") {
+        let report = JsonReport { from: from.to_string(), to: to.to_string(), stats: &stats, top_titles: stats.top_titles(top) };
+
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok({});
+    }
+
+    println!(
+        "Tracked {}{} from {} to {}, {} of it AFK",
+        format_secs(stats.total_secs), filter, from, to, format_secs(stats.afk_secs)
+    );
+    print_totals("Productivity", &stats.by_productivity, stats.total_secs, top);
+    print_totals("Projects", &stats.by_project, stats.total_secs, top);
+    print_totals("Activities", &stats.by_activity, stats.total_secs, top);
+    print_totals("Apps", &stats.by_app, stats.total_secs, top);
+    print_totals("Classes", &stats.by_class, stats.total_secs, top);
+    print_totals("Machines", &stats.by_machine, stats.total_secs, top);
+    print_totals("Tags", &stats.by_tag, stats.total_secs, top);
+
+    println!("\nTitles");
+    for (title, secs) in stats.top_titles(top) {
+        println!("  {:>8}  {}", format_secs(secs), title);
+    }
+
+    println!("\nHours");
+    let hours: Vec<String> = (0..24).map(|hour| format!("{:02}", hour)).collect();
+    print_histogram(hours.iter().map(String::as_str).zip(stats.by_hour.iter().cloned()));
+
+    println!("\nWeekdays");
+    print_histogram(WEEKDAYS.iter().cloned().zip(stats.by_weekday.iter().cloned()));
+    Ok({})
+}
+
+fn format_secs(secs: u64) -> String {
+    format_duration(Duration::from_secs(secs))
+}
+
+/// Prints up to `top` keys with the most time along with their share of `total_secs`
+fn print_totals(header: &str, totals: &BTreeMap<String, u64>, total_secs: u64, top: usize) {
+    let mut totals: Vec<(&String, &u64)> = totals.iter().collect();
+    totals.sort_by(|a, b| b.1.cmp(a.1));
+
+    println!("\n{}", header);
+    for (key, secs) in totals.into_iter().take(top) {
+        println!("  {:>8} {:>4}%  {}", format_secs(*secs), secs * 100 / total_secs.max(1), key);
+    }
+}
+
+fn print_histogram<'a>(buckets: impl Iterator<Item = (&'a str, u64)> + Clone) {
+    let longest = buckets.clone().map(|(_, secs)| secs).max().unwrap_or(0).max(1);
+
+    for (label, secs) in buckets {
+        let bar = "#".repeat((secs * BAR_WIDTH / longest) as usize);
+        println!("  {} {:>8}  {}", label, format_secs(secs), bar);
+    }
+}
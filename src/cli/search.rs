@@ -0,0 +1,50 @@
+use super::date_arg;
+use crate::record_store::{format_duration, RecordStore, RecordStoreConfig, SearchQuery};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::time::Duration;
+
+pub const NAME: &'static str = "search";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Finds records by words of window titles, app names and classes")
+        .arg(Arg::with_name("query")
+            .value_name("QUERY")
+            .required(true)
+            .multiple(true)
+            .help("Words records must contain, quote words that must follow each other, e.g. '\"q3 report\"'"))
+        .arg(Arg::with_name("from")
+            .long("from")
+            .value_name("DATE")
+            .help("First tracking date to search"))
+        .arg(Arg::with_name("to")
+            .long("to")
+            .value_name("DATE")
+            .help("Last tracking date to search"))
+        .arg(Arg::with_name("limit")
+            .long("limit")
+            .short("n")
+            .value_name("COUNT")
+            .default_value("20")
+            .help("Number of latest records to print"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    // Search may run alongside the tracker
+    let store = RecordStore::new_read_only(RecordStoreConfig::load()?)?;
+    let from = date_arg(args, "from", &store)?;
+    let to = date_arg(args, "to", &store)?;
+    let limit: usize = args.value_of("limit").unwrap_or("20").parse()
+        .map_err(|_| String::from("--limit must be a number"))?;
+    let query = SearchQuery::parse(&args.values_of("query").unwrap_or_default().collect::<Vec<_>>().join(" "));
+
+    let hits = store.search(&query, from.as_ref(), to.as_ref())?;
+    let total: Duration = hits.iter().map(|hit| hit.record.duration()).sum();
+
+    for hit in hits.iter().take(limit) {
+        println!("{}", hit);
+    }
+
+    eprintln!("Found {} records, {} in total", hits.len(), format_duration(total));
+    Ok({})
+}
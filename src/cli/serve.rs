@@ -0,0 +1,50 @@
+use crate::{
+    record_store::{RecordStoreConfig, ServerStore},
+    server::{Server, DEFAULT_ADDRESS},
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::path::PathBuf;
+
+pub const NAME: &'static str = "serve";
+
+/// Environment variable the token of the server is read from
+const TOKEN_VAR: &'static str = "TRACKR_SERVER_TOKEN";
+
+pub fn subcommand() -> App<'static, 'static> {
+    SubCommand::with_name(NAME)
+        .about("Runs a trackr server that keeps records of many machines")
+        .arg(Arg::with_name("listen")
+            .long("listen")
+            .value_name("ADDRESS")
+            .default_value(DEFAULT_ADDRESS))
+        .arg(Arg::with_name("data-dir")
+            .long("data-dir")
+            .value_name("DIR")
+            .help("Directory to keep records of clients in, defaults to `server` in the data dir"))
+        .arg(Arg::with_name("token-file")
+            .long("token-file")
+            .value_name("FILE")
+            .help("File holding the token clients must send, read from TRACKR_SERVER_TOKEN otherwise"))
+}
+
+pub fn run(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let token = match args.value_of("token-file") {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => std::env::var(TOKEN_VAR).unwrap_or_default(),
+    };
+    let token = String::from(token.trim());
+
+    if token.is_empty() {
+        return Err(format!("Give the server a token with --token-file or {}", TOKEN_VAR).into());
+    }
+
+    let data_dir = match args.value_of("data-dir") {
+        Some(data_dir) => PathBuf::from(data_dir),
+        None => RecordStoreConfig::load()?.data_dir.join("server"),
+    };
+    let server = Server::bind(args.value_of("listen").unwrap_or(DEFAULT_ADDRESS), ServerStore::open(&data_dir)?, token)?;
+
+    eprintln!("Serving records in {:?} on http://{}", data_dir, server.address());
+    server.run();
+    Ok({})
+}
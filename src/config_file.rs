@@ -0,0 +1,34 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::PathBuf,
+};
+use crate::constants::*;
+
+/// Gets path to the configuration file shared by all parts of the app.
+///
+/// Debug builds always read the dev config, release builds prefer
+/// `config.toml` in the app's data directory and fall back to the dev config
+pub fn config_file_path() -> PathBuf {
+    #[cfg(debug_assertions)]
+    { PathBuf::from(DEV_CONFIG_PATH) }
+
+    #[cfg(not(debug_assertions))]
+    {
+        match directories::ProjectDirs::from(APP_CLASSIFIER, APP_CORP, APP_NAME) {
+            Some(dirs) if dirs.data_dir().join("config.toml").exists() => {
+                dirs.data_dir().join("config.toml")
+            },
+            _ => PathBuf::from(DEV_CONFIG_PATH)
+        }
+    }
+}
+
+/// Reads raw contents of the configuration file
+pub fn read_config_file() -> Option<String> {
+    let mut config = String::default();
+    let mut file = File::open(config_file_path()).ok()?;
+
+    file.read_to_string(&mut config).ok()?;
+    Some(config)
+}
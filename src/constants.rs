@@ -4,4 +4,7 @@ pub const DEV_CONFIG_PATH: &'static str = "./dev-data/sample_config.toml";
 /// Project dirs configuration
 pub const APP_CLASSIFIER: &'static str = "com";
 pub const APP_CORP: &'static str = "Immortal Science";
-pub const APP_NAME: &'static str = "Trackr";
\ No newline at end of file
+pub const APP_NAME: &'static str = "Trackr";
+
+/// Name of machines that have no `name` in the config file
+pub const DEFAULT_MACHINE_NAME: &'static str = "unnamed machine";
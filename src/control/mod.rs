@@ -0,0 +1,348 @@
+//! Unix socket the running tracker is controlled through, `trackr.sock` in the data directory.
+//!
+//! A client sends a single line and gets a single line back:
+//!
+//! - `incognito on`, `incognito off` or `incognito toggle` switch incognito mode
+//! - `status` only asks for the mode
+//! - `forget <minutes>` drops what was tracked over the last minutes,
+//!   `forget <minutes> anonymize` only drops the window titles
+//!
+//! The answer to incognito commands is the mode after the command, `incognito on` or `incognito off`,
+//! `forget` is answered with `forgot <n> records`. Failures are answered with `error: <reason>`
+use crate::record_store::ForgetMode;
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// How long a connected client may take to send its command before it's dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long `send` waits for the tracker, which answers commands once per poll
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest command a client may send
+const MAX_LINE_LENGTH: usize = 1024;
+const ERROR_PREFIX: &'static str = "error: ";
+
+pub fn get_path_for_socket(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("trackr.sock"))
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ControlCommand {
+    /// Turns incognito mode on or off, `None` toggles it
+    Incognito(Option<bool>),
+    Status,
+    /// Forgets a period ending now, a whole number of minutes
+    Forget(Duration, ForgetMode),
+}
+
+impl FromStr for ControlCommand {
+    type Err = ControlError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let unknown = || ControlError::Rejected(format!("unknown command \"{}\"", line.trim()));
+
+        match words.as_slice() {
+            ["incognito", "on"] => Ok(ControlCommand::Incognito(Some(true))),
+            ["incognito", "off"] => Ok(ControlCommand::Incognito(Some(false))),
+            ["incognito", "toggle"] => Ok(ControlCommand::Incognito(None)),
+            ["status"] => Ok(ControlCommand::Status),
+            ["forget", minutes] => Ok(ControlCommand::Forget(parse_minutes(minutes).ok_or_else(unknown)?, ForgetMode::Remove)),
+            ["forget", minutes, "anonymize"] => {
+                Ok(ControlCommand::Forget(parse_minutes(minutes).ok_or_else(unknown)?, ForgetMode::Anonymize))
+            },
+            _ => Err(unknown()),
+        }
+    }
+}
+
+impl std::fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControlCommand::Incognito(Some(true)) => write!(f, "incognito on"),
+            ControlCommand::Incognito(Some(false)) => write!(f, "incognito off"),
+            ControlCommand::Incognito(None) => write!(f, "incognito toggle"),
+            ControlCommand::Status => write!(f, "status"),
+            ControlCommand::Forget(period, ForgetMode::Remove) => write!(f, "forget {}", period.as_secs() / 60),
+            ControlCommand::Forget(period, ForgetMode::Anonymize) => write!(f, "forget {} anonymize", period.as_secs() / 60),
+        }
+    }
+}
+
+/// Reads a number of minutes into a period, `None` if it isn't a number or doesn't fit in seconds
+pub fn parse_minutes(minutes: &str) -> Option<Duration> {
+    minutes.parse::<u64>().ok()
+        .and_then(|minutes| minutes.checked_mul(60))
+        .map(Duration::from_secs)
+}
+
+#[derive(Debug)]
+pub enum ControlError {
+    /// No tracker is listening on the socket
+    NotRunning,
+    IOFailed(std::io::Error),
+    Rejected(String),
+}
+
+impl std::fmt::Display for ControlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ControlError::NotRunning => write!(f, "trackr is not running"),
+            ControlError::IOFailed(err) => std::fmt::Display::fmt(err, f),
+            ControlError::Rejected(reason) => write!(f, "trackr refused the command: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ControlError {
+    fn description(&self) -> &str {
+        match self {
+            ControlError::NotRunning => "trackr is not running",
+            ControlError::IOFailed(_) => "Failed to talk to trackr",
+            ControlError::Rejected(_) => "trackr refused the command",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+impl From<std::io::Error> for ControlError {
+    fn from(err: std::io::Error) -> Self {
+        ControlError::IOFailed(err)
+    }
+}
+
+/// Client that connected but has not been answered yet
+struct Client {
+    stream: UnixStream,
+    /// Bytes read so far, commands may arrive over several polls
+    line: Vec<u8>,
+    connected_at: Instant,
+}
+
+/// Listening end of the socket, owned by the tracker. The socket file is removed once dropped
+pub struct ControlSocket {
+    path: PathBuf,
+    listener: UnixListener,
+    clients: Vec<Client>,
+}
+
+impl ControlSocket {
+    /// Listens in a given data directory.
+    ///
+    /// Callers must hold the store lock, so a socket file that is already there was left by a crashed tracker
+    pub fn bind(data_dir: &Path) -> Result<Self, ControlError> {
+        let path = get_path_for_socket(data_dir);
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { path, listener, clients: vec![] })
+    }
+
+    /// Answers clients that have sent their command, without blocking on those that are still sending.
+    ///
+    /// `handle` runs a command and returns the answer, or the reason it failed. Connections that could not
+    /// be accepted or answered are dropped and their errors returned, a client that fails to talk must not
+    /// stop the tracker
+    pub fn poll<F>(&mut self, mut handle: F) -> Vec<ControlError>
+    where
+        F: FnMut(ControlCommand) -> Result<String, String>,
+    {
+        let mut errors = self.accept();
+
+        for mut client in std::mem::take(&mut self.clients) {
+            match Self::answer(&mut client, &mut handle) {
+                Ok(true) => {},
+                Ok(false) if client.connected_at.elapsed() < CLIENT_TIMEOUT => self.clients.push(client),
+                Ok(false) => errors.push(ControlError::from(std::io::Error::from(ErrorKind::TimedOut))),
+                Err(err) => errors.push(err),
+            }
+        }
+        errors
+    }
+
+    /// Accepts clients that have connected since the last poll, returns why others could not be accepted
+    fn accept(&mut self) -> Vec<ControlError> {
+        let mut errors = vec![];
+
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return errors,
+                Err(err) => {
+                    errors.push(ControlError::from(err));
+                    return errors;
+                }
+            };
+
+            match stream.set_nonblocking(true) {
+                Ok(()) => self.clients.push(Client { stream, line: vec![], connected_at: Instant::now() }),
+                Err(err) => errors.push(ControlError::from(err)),
+            }
+        }
+    }
+
+    /// Reads what a client has sent and answers once its line is complete, returns whether it was answered
+    fn answer<F>(client: &mut Client, handle: &mut F) -> Result<bool, ControlError>
+    where
+        F: FnMut(ControlCommand) -> Result<String, String>,
+    {
+        let mut chunk = [0u8; 256];
+
+        let end = loop {
+            if let Some(end) = client.line.iter().position(|&byte| byte == b'\n') {
+                break end;
+            }
+            if client.line.len() > MAX_LINE_LENGTH {
+                return Err(ControlError::Rejected(String::from("command is too long")));
+            }
+
+            match (&client.stream).read(&mut chunk) {
+                Ok(0) => return Err(ControlError::from(std::io::Error::from(ErrorKind::UnexpectedEof))),
+                Ok(length) => client.line.extend_from_slice(&chunk[..length]),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => {},
+                Err(err) => return Err(ControlError::from(err)),
+            }
+        };
+        let line = String::from_utf8_lossy(&client.line[..end]);
+
+        let answer = match line.parse::<ControlCommand>().map(handle) {
+            Ok(Ok(answer)) => answer,
+            Ok(Err(reason)) | Err(ControlError::Rejected(reason)) => format!("{}{}", ERROR_PREFIX, reason),
+            Err(err) => return Err(err),
+        };
+
+        // Answers are a short line that the socket buffer takes without blocking
+        writeln!(&client.stream, "{}", answer)?;
+        Ok(true)
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Answer to incognito commands for a given mode
+pub fn describe_mode(incognito: bool) -> String {
+    String::from(match incognito {
+        true => "incognito on",
+        false => "incognito off",
+    })
+}
+
+/// Sends a command to the tracker running on a given data directory and returns its answer
+pub fn send(data_dir: &Path, command: ControlCommand) -> Result<String, ControlError> {
+    let stream = match UnixStream::connect(get_path_for_socket(data_dir)) {
+        Ok(stream) => stream,
+        Err(ref err) if err.kind() == ErrorKind::NotFound || err.kind() == ErrorKind::ConnectionRefused => {
+            return Err(ControlError::NotRunning);
+        },
+        Err(err) => return Err(ControlError::from(err)),
+    };
+    let mut answer = String::new();
+
+    // A tracker that is stuck must not hang the client
+    stream.set_read_timeout(Some(ANSWER_TIMEOUT))?;
+    stream.set_write_timeout(Some(ANSWER_TIMEOUT))?;
+    writeln!(&stream, "{}", command)?;
+    BufReader::new(&stream).read_line(&mut answer)?;
+
+    let answer = answer.trim();
+    match answer.strip_prefix(ERROR_PREFIX) {
+        Some(reason) => Err(ControlError::Rejected(String::from(reason))),
+        None => Ok(String::from(answer)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::test_dir;
+    use std::thread;
+
+    #[test]
+    fn commands_are_answered() {
+        let dir = test_dir("control");
+        let mut socket = ControlSocket::bind(&dir).unwrap();
+        let client_dir = dir.clone();
+        let client = thread::spawn(move || {
+            vec![
+                send(&client_dir, ControlCommand::Incognito(Some(true))).unwrap(),
+                send(&client_dir, ControlCommand::Incognito(None)).unwrap(),
+                send(&client_dir, ControlCommand::Status).unwrap(),
+            ]
+        });
+        let mut incognito = false;
+        let mut answered = 0;
+
+        while answered < 3 {
+            socket.poll(|command| {
+                answered += 1;
+                match command {
+                    ControlCommand::Incognito(Some(on)) => incognito = on,
+                    ControlCommand::Incognito(None) => incognito = !incognito,
+                    ControlCommand::Status => {},
+                    ControlCommand::Forget(_, _) => return Err(String::from("nothing to forget")),
+                }
+                Ok(describe_mode(incognito))
+            });
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(client.join().unwrap(), vec!["incognito on", "incognito off", "incognito off"]);
+        assert_eq!("incognito maybe".parse::<ControlCommand>().is_err(), true);
+        assert_eq!(
+            "forget 5 anonymize".parse::<ControlCommand>().unwrap(),
+            ControlCommand::Forget(Duration::from_secs(300), ForgetMode::Anonymize)
+        );
+        assert_eq!("forget soon".parse::<ControlCommand>().is_err(), true);
+
+        drop(socket);
+        assert_eq!(send(&dir, ControlCommand::Status).map_err(|err| err.to_string()), Err(String::from("trackr is not running")));
+    }
+
+    #[test]
+    fn overflowing_minutes_are_rejected() {
+        let too_many = format!("forget {}", u64::MAX / 60 + 1);
+
+        assert_eq!(too_many.parse::<ControlCommand>().map_err(|err| err.to_string()), Err(format!("trackr refused the command: unknown command \"{}\"", too_many)));
+        assert_eq!(parse_minutes(&(u64::MAX / 60).to_string()), Some(Duration::from_secs(u64::MAX / 60 * 60)));
+    }
+
+    #[test]
+    fn slow_client_does_not_block() {
+        let dir = test_dir("control_slow");
+        let mut socket = ControlSocket::bind(&dir).unwrap();
+        let mut client = UnixStream::connect(get_path_for_socket(&dir)).unwrap();
+        let mut answer = String::new();
+
+        client.write_all(b"sta").unwrap();
+        let started = Instant::now();
+        assert_eq!(socket.poll(|_| Ok(describe_mode(false))).is_empty(), true);
+        assert_eq!(started.elapsed() < Duration::from_millis(100), true);
+
+        client.write_all(b"tus\n").unwrap();
+        socket.poll(|_| Ok(describe_mode(true)));
+        BufReader::new(&client).read_line(&mut answer).unwrap();
+        assert_eq!(answer, "incognito on\n");
+
+        // A client that hangs up before its command is complete is reported rather than printed
+        let mut hasty = UnixStream::connect(get_path_for_socket(&dir)).unwrap();
+        hasty.write_all(b"sta").unwrap();
+        drop(hasty);
+        assert_eq!(socket.poll(|_| Ok(describe_mode(true))).len(), 1);
+    }
+}
@@ -0,0 +1,46 @@
+use super::{ExportError, ExportRow};
+use std::io::Write;
+
+/// Writes rows with a header line, columns follow the fields of `ExportRow`
+pub fn write(rows: &[ExportRow], out: &mut dyn Write) -> Result<(), ExportError> {
+    let mut writer = ::csv::Writer::from_writer(out);
+
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_quoted() {
+        let row = ExportRow {
+            start: String::from("2020-06-01T10:00:00+00:00"),
+            end: String::from("2020-06-01T10:01:00+00:00"),
+            duration_secs: 60,
+            kind: "window",
+            title: String::from("main.rs, \"trackr\""),
+            app_name: String::from("code-oss"),
+            app_class: String::from("Code"),
+            productivity: "productive",
+            activity: String::from("coding"),
+            machine: String::from("laptop"),
+            tags: String::from("incident-482,on-call"),
+            note: String::new(),
+            project: String::from("client-x"),
+        };
+        let mut out: Vec<u8> = vec![];
+
+        write(&[row], &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), [
+            "start,end,duration_secs,kind,title,app_name,app_class,productivity,activity,machine,tags,note,project",
+            "2020-06-01T10:00:00+00:00,2020-06-01T10:01:00+00:00,60,window,\"main.rs, \"\"trackr\"\"\",code-oss,Code,productive,coding,laptop,\"incident-482,on-call\",,client-x",
+            "",
+        ].join("\n"));
+    }
+}
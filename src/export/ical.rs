@@ -0,0 +1,143 @@
+use super::{ExportError, ExportRow};
+use crate::{
+    constants::APP_NAME,
+    record_store::ActivityRecord,
+};
+use chrono::{DateTime, Utc};
+use std::{
+    io::Write,
+    time::SystemTime,
+};
+
+/// Longest line allowed by RFC 5545, in octets
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Writes a calendar with a VEVENT per record
+pub fn write(records: &[ActivityRecord], out: &mut dyn Write) -> Result<(), ExportError> {
+    let stamp = to_ical_time(&SystemTime::now());
+
+    write_line(out, "BEGIN:VCALENDAR")?;
+    write_line(out, "VERSION:2.0")?;
+    write_line(out, &format!("PRODID:-//{}//EN", APP_NAME))?;
+
+    for record in records {
+        let row = ExportRow::from(record);
+        let summary = match row.kind {
+            "afk" => String::from("AFK"),
+            _ => row.title.clone(),
+        };
+        let mut description = format!(
+            "App: {}\nClass: {}\nProductivity: {}\nActivity: {}\nMachine: {}",
+            row.app_name, row.app_class, row.productivity, row.activity, row.machine
+        );
+        if !row.project.is_empty() {
+            description.push_str(&format!("\nProject: {}", row.project));
+        }
+        if !row.note.is_empty() {
+            description.push_str(&format!("\nNote: {}", row.note));
+        }
+        let categories: Vec<String> = vec![&row.project, &row.activity].into_iter()
+            .filter(|category| !category.is_empty())
+            .chain(&record.tags)
+            .map(|category| escape(category))
+            .collect();
+
+        write_line(out, "BEGIN:VEVENT")?;
+        write_line(out, &format!(
+            "UID:{}-{}@{}",
+            to_ical_time(&record.time_range.0),
+            to_ical_time(&record.time_range.1),
+            APP_NAME.to_lowercase()
+        ))?;
+        write_line(out, &format!("DTSTAMP:{}", stamp))?;
+        write_line(out, &format!("DTSTART:{}", to_ical_time(&record.time_range.0)))?;
+        write_line(out, &format!("DTEND:{}", to_ical_time(&record.time_range.1)))?;
+        write_line(out, &format!("SUMMARY:{}", escape(&summary)))?;
+        write_line(out, &format!("DESCRIPTION:{}", escape(&description)))?;
+        if !categories.is_empty() {
+            write_line(out, &format!("CATEGORIES:{}", categories.join(",")))?;
+        }
+        write_line(out, "END:VEVENT")?;
+    }
+
+    write_line(out, "END:VCALENDAR")?;
+    Ok(())
+}
+
+fn to_ical_time(time: &SystemTime) -> String {
+    DateTime::<Utc>::from(*time).format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes characters that have a special meaning in TEXT values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {},
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a content line, folding it so that no line exceeds `MAX_LINE_LENGTH` octets
+fn write_line(out: &mut dyn Write, line: &str) -> Result<(), ExportError> {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut line_length = 0;
+
+    for c in line.chars() {
+        if line_length + c.len_utf8() > MAX_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            line_length = 1;
+        }
+        folded.push(c);
+        line_length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+
+    out.write_all(folded.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{Archetype, ProductivityStatus};
+    use std::{collections::BTreeSet, time::{Duration, UNIX_EPOCH}};
+
+    #[test]
+    fn event_per_record() {
+        let record = ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_secs(1_591_005_600),
+                UNIX_EPOCH + Duration::from_secs(1_591_005_660),
+            ),
+            productivity: ProductivityStatus::Productive(String::from("coding")),
+            archetype: Archetype::ActiveWindow(
+                String::from("main.rs; trackr"),
+                String::from("code-oss"),
+                String::from("Code"),
+            ),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        };
+        let mut out: Vec<u8> = vec![];
+
+        write(&[record], &mut out).unwrap();
+        let calendar = String::from_utf8(out).unwrap();
+
+        assert_eq!(calendar.starts_with("BEGIN:VCALENDAR\r\n"), true);
+        assert_eq!(calendar.contains("DTSTART:20200601T100000Z\r\n"), true);
+        assert_eq!(calendar.contains("DTEND:20200601T100100Z\r\n"), true);
+        assert_eq!(calendar.contains("SUMMARY:main.rs\\; trackr\r\n"), true);
+        assert_eq!(calendar.contains("CATEGORIES:coding\r\n"), true);
+        assert_eq!(calendar.lines().all(|line| line.len() <= MAX_LINE_LENGTH), true);
+    }
+}
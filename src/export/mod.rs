@@ -0,0 +1,195 @@
+mod csv;
+mod ical;
+mod ndjson;
+
+use crate::record_store::{
+    ActivityRecord, Archetype, ProductivityStatus, RecordFilter, RecordStore, RecordStoreError,
+};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat};
+use std::{
+    io::Write,
+    str::FromStr,
+    time::SystemTime,
+};
+
+/// File formats records can be exported to
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    /// JSON Lines, one record per line
+    Ndjson,
+    /// iCalendar with a VEVENT per record
+    Ical,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            "ics" | "ical" => Ok(ExportFormat::Ical),
+            _ => Err(ExportError::UnknownFormat(String::from(name))),
+        }
+    }
+}
+
+/// Flat representation of an `ActivityRecord` shared by all formats
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ExportRow {
+    /// RFC 3339 timestamp in local time
+    pub start: String,
+    pub end: String,
+    pub duration_secs: u64,
+    /// `window`, `afk`, `private` or `incognito`
+    pub kind: &'static str,
+    pub title: String,
+    pub app_name: String,
+    pub app_class: String,
+    /// `productive`, `neutral` or `leisure`
+    pub productivity: &'static str,
+    pub activity: String,
+    /// Empty for records tracked before machines were named
+    pub machine: String,
+    /// Comma-separated, sorted
+    pub tags: String,
+    pub note: String,
+    /// Empty for records that match no project
+    pub project: String,
+}
+
+impl From<&ActivityRecord> for ExportRow {
+    fn from(record: &ActivityRecord) -> Self {
+        let (kind, title, app_name, app_class) = match &record.archetype {
+            Archetype::ActiveWindow(title, name, class) => ("window", title.clone(), name.clone(), class.clone()),
+            Archetype::AFK => ("afk", String::new(), String::new(), String::new()),
+            Archetype::Private => ("private", String::new(), String::new(), String::new()),
+            Archetype::Incognito(name, class) => ("incognito", String::new(), name.clone(), class.clone()),
+        };
+        let (productivity, activity) = match &record.productivity {
+            ProductivityStatus::Productive(name) => ("productive", name.clone()),
+            ProductivityStatus::Leisure(name) => ("leisure", name.clone()),
+            ProductivityStatus::Neutral => ("neutral", String::new()),
+        };
+
+        Self {
+            start: to_rfc3339(&record.time_range.0),
+            end: to_rfc3339(&record.time_range.1),
+            duration_secs: record.duration().as_secs(),
+            kind,
+            title,
+            app_name,
+            app_class,
+            productivity,
+            activity,
+            machine: record.machine.clone().unwrap_or_default(),
+            tags: record.tags.iter().cloned().collect::<Vec<String>>().join(","),
+            note: record.note.clone().unwrap_or_default(),
+            project: record.project.clone().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    UnknownFormat(String),
+    StoreFailed(RecordStoreError),
+    IOFailed(std::io::Error),
+    CsvFailed(::csv::Error),
+    JsonFailed(serde_json::Error),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::UnknownFormat(name) => {
+                write!(f, "Unknown export format \"{}\", expected one of: csv, ndjson, ics", name)
+            }
+            ExportError::StoreFailed(err) => std::fmt::Display::fmt(err, f),
+            ExportError::IOFailed(err) => std::fmt::Display::fmt(err, f),
+            ExportError::CsvFailed(err) => std::fmt::Display::fmt(err, f),
+            ExportError::JsonFailed(err) => std::fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn description(&self) -> &str {
+        match self {
+            ExportError::UnknownFormat(_) => "Unknown export format",
+            ExportError::StoreFailed(_) => "Failed to read records",
+            ExportError::IOFailed(_) => "Failed to write exported records",
+            ExportError::CsvFailed(_) => "Failed to encode records as CSV",
+            ExportError::JsonFailed(_) => "Failed to encode records as JSON",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+impl From<RecordStoreError> for ExportError {
+    fn from(err: RecordStoreError) -> Self {
+        ExportError::StoreFailed(err)
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(err: std::io::Error) -> Self {
+        ExportError::IOFailed(err)
+    }
+}
+
+impl From<::csv::Error> for ExportError {
+    fn from(err: ::csv::Error) -> Self {
+        ExportError::CsvFailed(err)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(err: serde_json::Error) -> Self {
+        ExportError::JsonFailed(err)
+    }
+}
+
+/// Writes records of tracking dates from `from` to `to` inclusive that `filter` keeps.
+///
+/// Returns the number of exported records
+pub fn export_records(
+    store: &RecordStore,
+    from: &NaiveDate,
+    to: &NaiveDate,
+    filter: &RecordFilter,
+    format: ExportFormat,
+    out: &mut dyn Write,
+) -> Result<usize, ExportError> {
+    let records: Vec<ActivityRecord> = store.query_records_for_dates(from, to)?
+        .into_iter()
+        .filter(|record| filter.matches(record))
+        .collect();
+
+    write_records(&records, format, out)?;
+    Ok(records.len())
+}
+
+pub fn write_records(
+    records: &[ActivityRecord],
+    format: ExportFormat,
+    out: &mut dyn Write,
+) -> Result<(), ExportError> {
+    let rows: Vec<ExportRow> = records.iter().map(ExportRow::from).collect();
+
+    match format {
+        ExportFormat::Csv => csv::write(&rows, out)?,
+        ExportFormat::Ndjson => ndjson::write(&rows, out)?,
+        ExportFormat::Ical => ical::write(records, out)?,
+    };
+    out.flush()?;
+    Ok(())
+}
+
+fn to_rfc3339(time: &SystemTime) -> String {
+    DateTime::<Local>::from(*time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
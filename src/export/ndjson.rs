@@ -0,0 +1,11 @@
+use super::{ExportError, ExportRow};
+use std::io::Write;
+
+/// Writes every row as a JSON object on its own line
+pub fn write(rows: &[ExportRow], out: &mut dyn Write) -> Result<(), ExportError> {
+    for row in rows {
+        serde_json::to_writer(&mut *out, row)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
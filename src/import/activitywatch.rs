@@ -0,0 +1,188 @@
+use super::ImportError;
+use crate::record_store::{ActivityRecord, Archetype, ProductivityStatus};
+use chrono::DateTime;
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::{Duration, SystemTime},
+};
+
+const BUCKET_WINDOW: &'static str = "currentwindow";
+const BUCKET_AFK: &'static str = "afkstatus";
+const STATUS_AFK: &'static str = "afk";
+
+/// Export of one or several buckets as produced by ActivityWatch
+#[derive(Debug, Deserialize)]
+struct Export {
+    buckets: HashMap<String, Bucket>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Bucket {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    timestamp: String,
+    /// Length of the event in seconds
+    duration: f64,
+    data: EventData,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct EventData {
+    app: Option<String>,
+    title: Option<String>,
+    status: Option<String>,
+}
+
+type TimeRange = (SystemTime, SystemTime);
+
+/// Converts window and AFK buckets to records, other buckets are ignored.
+///
+/// ActivityWatch keeps window events running while the user is away,
+/// so AFK periods take precedence and are cut out of window events
+pub fn parse(contents: &str) -> Result<Vec<ActivityRecord>, ImportError> {
+    let export: Export = serde_json::from_str(contents)?;
+    let mut afk_ranges: Vec<TimeRange> = vec![];
+    let mut windows: Vec<(TimeRange, Archetype)> = vec![];
+
+    for bucket in export.buckets.values() {
+        for event in &bucket.events {
+            let range = match event_range(event) {
+                Some(range) => range,
+                None => continue,
+            };
+
+            match bucket.kind.as_str() {
+                BUCKET_AFK if event.data.status.as_deref() == Some(STATUS_AFK) => afk_ranges.push(range),
+                BUCKET_WINDOW => {
+                    let app = event.data.app.clone().unwrap_or_default();
+                    let title = event.data.title.clone().unwrap_or_default();
+
+                    // ActivityWatch doesn't report window class, app name stands in for it
+                    windows.push((range, Archetype::ActiveWindow(title, app.clone(), app)));
+                },
+                _ => {},
+            }
+        }
+    }
+
+    let mut records: Vec<ActivityRecord> = afk_ranges
+        .iter()
+        .map(|range| record(*range, Archetype::AFK))
+        .collect();
+
+    for (range, archetype) in windows {
+        for part in subtract(range, &afk_ranges) {
+            records.push(record(part, archetype.clone()));
+        }
+    }
+
+    records.sort_by_key(|record| record.time_range.0);
+    Ok(records)
+}
+
+fn event_range(event: &Event) -> Option<TimeRange> {
+    let start = SystemTime::from(DateTime::parse_from_rfc3339(&event.timestamp).ok()?);
+
+    if !(event.duration > 0.0) {
+        return None;
+    }
+    Some((start, start + Duration::from_secs_f64(event.duration)))
+}
+
+/// Gets parts of a range that are not covered by any of given ranges
+fn subtract(range: TimeRange, cut_out: &[TimeRange]) -> Vec<TimeRange> {
+    let mut parts = vec![range];
+
+    for cut in cut_out {
+        parts = parts
+            .into_iter()
+            .flat_map(|(start, end)| {
+                let mut rest: Vec<TimeRange> = vec![];
+
+                if cut.1 <= start || end <= cut.0 {
+                    rest.push((start, end));
+                    return rest;
+                }
+                if start < cut.0 {
+                    rest.push((start, cut.0));
+                }
+                if cut.1 < end {
+                    rest.push((cut.1, end));
+                }
+                rest
+            })
+            .collect();
+    }
+
+    parts
+}
+
+fn record(time_range: TimeRange, archetype: Archetype) -> ActivityRecord {
+    ActivityRecord {
+        time_range,
+        productivity: ProductivityStatus::Neutral,
+        archetype,
+        machine: None,
+        tags: BTreeSet::new(),
+        note: None,
+        project: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn afk_is_cut_out_of_window_events() {
+        let export = r#"{
+            "buckets": {
+                "aw-watcher-window_host": {
+                    "id": "aw-watcher-window_host",
+                    "type": "currentwindow",
+                    "events": [
+                        { "timestamp": "2020-06-01T10:00:00.000000+00:00", "duration": 600.0,
+                          "data": { "app": "code-oss", "title": "main.rs" } }
+                    ]
+                },
+                "aw-watcher-afk_host": {
+                    "id": "aw-watcher-afk_host",
+                    "type": "afkstatus",
+                    "events": [
+                        { "timestamp": "2020-06-01T10:02:00+00:00", "duration": 120.0, "data": { "status": "afk" } },
+                        { "timestamp": "2020-06-01T10:04:00+00:00", "duration": 360.0, "data": { "status": "not-afk" } }
+                    ]
+                },
+                "aw-watcher-web-firefox": {
+                    "id": "aw-watcher-web-firefox",
+                    "type": "web.tab.current",
+                    "events": []
+                }
+            }
+        }"#;
+        let window = Archetype::ActiveWindow(
+            String::from("main.rs"),
+            String::from("code-oss"),
+            String::from("code-oss"),
+        );
+        let start = 1_591_005_600;
+
+        assert_eq!(parse(export).unwrap(), vec![
+            record((at(start), at(start + 120)), window.clone()),
+            record((at(start + 120), at(start + 240)), Archetype::AFK),
+            record((at(start + 240), at(start + 600)), window),
+        ]);
+    }
+}
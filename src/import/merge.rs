@@ -0,0 +1,200 @@
+use super::ImportError;
+use crate::record_store::{
+    ActivityRecord, Archetype, ProductivityStatus, RecordStore, RecordStoreConfig, StoreBackend,
+};
+use chrono::DateTime;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// History of another machine, e.g. found in a shared folder
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MergeSource {
+    /// Data directory of another store using the given backend
+    Store(PathBuf, StoreBackend),
+    /// Records exported as NDJSON
+    Export(PathBuf),
+}
+
+impl MergeSource {
+    /// Tells the kind of source by its path: a data directory, its `records.db`
+    /// or `records.sqlite3`, or any other file taken for an NDJSON export
+    pub fn detect(path: &Path) -> Self {
+        let parent = || path.parent().map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+
+        if path.is_dir() {
+            return MergeSource::Store(path.to_path_buf(), StoreBackend::Rustbreak);
+        }
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("db") => MergeSource::Store(parent(), StoreBackend::Rustbreak),
+            Some("sqlite3") => MergeSource::Store(parent(), StoreBackend::Sqlite),
+            _ => MergeSource::Export(path.to_path_buf()),
+        }
+    }
+
+    /// Reads all records of the source, asking for the passphrase of encrypted stores
+    pub fn read_records(&self) -> Result<Vec<ActivityRecord>, ImportError> {
+        match self {
+            MergeSource::Store(data_dir, backend) => {
+                let config = RecordStoreConfig {
+                    backend: *backend,
+                    ..RecordStoreConfig::in_dir(data_dir.clone())
+                };
+                let cipher = RecordStore::unlock(&config)?;
+                let store = RecordStore::open_read_only(config, cipher)?;
+                let mut records = vec![];
+
+                for date in store.query_dates()? {
+                    records.extend(store.query_records_by_date(&date)?);
+                }
+                Ok(records)
+            },
+            MergeSource::Export(path) => parse_export(&std::fs::read_to_string(path)?),
+        }
+    }
+}
+
+/// Row of an NDJSON export, see `ExportRow`
+#[derive(Debug, Deserialize)]
+struct ExportedRow {
+    start: String,
+    end: String,
+    kind: String,
+    title: String,
+    app_name: String,
+    app_class: String,
+    productivity: String,
+    activity: String,
+    /// Absent in exports made before machines were named
+    #[serde(default)]
+    machine: String,
+    /// Absent in exports made before records were tagged
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    note: String,
+    #[serde(default)]
+    project: String,
+}
+
+/// Converts records exported as NDJSON back to records
+pub fn parse_export(contents: &str) -> Result<Vec<ActivityRecord>, ImportError> {
+    let mut records: Vec<ActivityRecord> = vec![];
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        let invalid_line = || ImportError::InvalidLine(index + 1, String::from(line));
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let row: ExportedRow = serde_json::from_str(line).map_err(|_| invalid_line())?;
+        let parse_time = |time: &str| DateTime::parse_from_rfc3339(time).map(SystemTime::from).map_err(|_| invalid_line());
+
+        records.push(ActivityRecord {
+            time_range: (parse_time(&row.start)?, parse_time(&row.end)?),
+            productivity: match row.productivity.as_str() {
+                "productive" => ProductivityStatus::Productive(row.activity),
+                "leisure" => ProductivityStatus::Leisure(row.activity),
+                "neutral" => ProductivityStatus::Neutral,
+                _ => return Err(invalid_line()),
+            },
+            archetype: match row.kind.as_str() {
+                "window" => Archetype::ActiveWindow(row.title, row.app_name, row.app_class),
+                "afk" => Archetype::AFK,
+                "private" => Archetype::Private,
+                "incognito" => Archetype::Incognito(row.app_name, row.app_class),
+                _ => return Err(invalid_line()),
+            },
+            machine: Some(row.machine).filter(|machine| !machine.is_empty()),
+            tags: row.tags.split(',').filter(|tag| !tag.is_empty()).map(String::from).collect(),
+            note: Some(row.note).filter(|note| !note.is_empty()),
+            project: Some(row.project).filter(|project| !project.is_empty()),
+        });
+    }
+
+    Ok(records)
+}
+
+/// Outcome of a merge
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct MergeSummary {
+    /// Number of records found in the source
+    pub read: usize,
+    /// Number of records of other machines, the rest were tracked on this one
+    pub merged: usize,
+    /// Number of tracking days whose records changed
+    pub days_changed: usize,
+}
+
+/// Merges records of other machines into the store.
+///
+/// Records without a machine name are taken for records of `machine`.
+/// Records of this machine, named `local_machine`, are already in the store and skipped
+pub fn merge_records(
+    records: Vec<ActivityRecord>,
+    machine: Option<&str>,
+    local_machine: &str,
+    store: &RecordStore,
+) -> Result<MergeSummary, ImportError> {
+    let read = records.len();
+    let unnamed = records.iter().filter(|record| record.machine.is_none()).count();
+
+    if unnamed > 0 && machine.is_none() {
+        return Err(ImportError::UnnamedRecords(unnamed));
+    }
+
+    let records: Vec<ActivityRecord> = records
+        .into_iter()
+        .map(|mut record| {
+            record.machine = record.machine.or_else(|| machine.map(String::from));
+            record
+        })
+        .filter(|record| record.machine.as_deref() != Some(local_machine))
+        .collect();
+
+    Ok(MergeSummary {
+        read,
+        merged: records.len(),
+        days_changed: store.merge_records(records)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportRow;
+    use std::{collections::BTreeSet, time::{Duration, UNIX_EPOCH}};
+
+    #[test]
+    fn reads_ndjson_export() {
+        let records = vec![
+            ActivityRecord {
+                time_range: (UNIX_EPOCH + Duration::from_secs(1_591_005_600), UNIX_EPOCH + Duration::from_secs(1_591_005_660)),
+                productivity: ProductivityStatus::Productive(String::from("coding")),
+                archetype: Archetype::ActiveWindow(String::from("main.rs"), String::from("code"), String::from("Code")),
+                machine: Some(String::from("desktop")),
+                tags: vec![String::from("incident-482"), String::from("on-call")].into_iter().collect(),
+                note: Some(String::from("pairing with Sam")),
+                project: Some(String::from("client-x")),
+            },
+            ActivityRecord {
+                time_range: (UNIX_EPOCH + Duration::from_secs(1_591_005_660), UNIX_EPOCH + Duration::from_secs(1_591_005_720)),
+                productivity: ProductivityStatus::Neutral,
+                archetype: Archetype::AFK,
+                machine: None,
+                tags: BTreeSet::new(),
+                note: None,
+                project: None,
+            },
+        ];
+        let contents: String = records.iter()
+            .map(|record| serde_json::to_string(&ExportRow::from(record)).unwrap() + "\n")
+            .collect();
+
+        assert_eq!(parse_export(&contents).unwrap(), records);
+        assert_eq!(parse_export("{\"start\": \"yesterday\"}").is_err(), true);
+    }
+}
@@ -0,0 +1,142 @@
+mod activitywatch;
+mod merge;
+mod timewarrior;
+
+pub use self::merge::{merge_records, MergeSource};
+
+use crate::{
+    classifier::Classifier,
+    record_store::{ActivityRecord, RecordStore, RecordStoreError},
+};
+use std::str::FromStr;
+
+/// Trackers history can be imported from
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ImportFormat {
+    /// Bucket export of ActivityWatch in JSON
+    ActivityWatch,
+    /// Timewarrior data file, e.g. `~/.timewarrior/data/2020-06.data`
+    Timewarrior,
+}
+
+impl FromStr for ImportFormat {
+    type Err = ImportError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "aw" | "activitywatch" => Ok(ImportFormat::ActivityWatch),
+            "timew" | "timewarrior" => Ok(ImportFormat::Timewarrior),
+            _ => Err(ImportError::UnknownFormat(String::from(name))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    UnknownFormat(String),
+    StoreFailed(RecordStoreError),
+    JsonFailed(serde_json::Error),
+    IOFailed(std::io::Error),
+    /// Number of merged records that don't say which machine tracked them
+    UnnamedRecords(usize),
+    /// Line number and contents of a line that could not be parsed
+    InvalidLine(usize, String),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::UnknownFormat(name) => {
+                write!(f, "Unknown import format \"{}\", expected one of: aw, timew", name)
+            }
+            ImportError::StoreFailed(err) => std::fmt::Display::fmt(err, f),
+            ImportError::JsonFailed(err) => std::fmt::Display::fmt(err, f),
+            ImportError::IOFailed(err) => std::fmt::Display::fmt(err, f),
+            ImportError::UnnamedRecords(count) => {
+                write!(f, "{} records have no machine name, give one with --machine", count)
+            }
+            ImportError::InvalidLine(number, line) => {
+                write!(f, "Could not parse line {}: {}", number, line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn description(&self) -> &str {
+        match self {
+            ImportError::UnknownFormat(_) => "Unknown import format",
+            ImportError::StoreFailed(_) => "Failed to store imported records",
+            ImportError::JsonFailed(_) => "Failed to parse JSON",
+            ImportError::IOFailed(_) => "Failed to read records",
+            ImportError::UnnamedRecords(_) => "Records have no machine name",
+            ImportError::InvalidLine(_, _) => "Could not parse a line",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+impl From<RecordStoreError> for ImportError {
+    fn from(err: RecordStoreError) -> Self {
+        ImportError::StoreFailed(err)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        ImportError::JsonFailed(err)
+    }
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(err: std::io::Error) -> Self {
+        ImportError::IOFailed(err)
+    }
+}
+
+/// Outcome of an import
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct ImportSummary {
+    /// Number of records found in the source
+    pub parsed: usize,
+    /// Number of records stored, the rest overlapped the existing history
+    pub inserted: usize,
+}
+
+/// Converts contents of an exported file to records
+pub fn parse_records(contents: &str, format: ImportFormat) -> Result<Vec<ActivityRecord>, ImportError> {
+    match format {
+        ImportFormat::ActivityWatch => activitywatch::parse(contents),
+        ImportFormat::Timewarrior => timewarrior::parse(contents),
+    }
+}
+
+/// Classifies records found in an exported file and adds them to the store.
+///
+/// Imported history is taken for history of this machine, privacy rules apply to it as to tracked windows
+pub fn import_records(
+    contents: &str,
+    format: ImportFormat,
+    classifier: &Classifier,
+    store: &RecordStore,
+) -> Result<ImportSummary, ImportError> {
+    let records = parse_records(contents, format)?;
+    let parsed = records.len();
+    let records: Vec<ActivityRecord> = records
+        .into_iter()
+        .filter_map(|mut record| {
+            record.archetype = classifier.apply_privacy(Some(record.archetype), store.hash_key())?;
+            classifier.classify(&mut record);
+            record.machine = Some(String::from(classifier.machine_name()));
+            Some(record)
+        })
+        .collect();
+
+    Ok(ImportSummary {
+        parsed,
+        inserted: store.insert_records(records)?,
+    })
+}
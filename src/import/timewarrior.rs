@@ -0,0 +1,123 @@
+use super::ImportError;
+use crate::record_store::{ActivityRecord, Archetype, ProductivityStatus};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::{collections::BTreeSet, time::SystemTime};
+
+const APP_NAME: &'static str = "timewarrior";
+const APP_CLASS: &'static str = "Timewarrior";
+
+/// Converts closed intervals of a Timewarrior data file to records.
+///
+/// Lines look like `inc 20200601T100000Z - 20200601T110000Z # coding "project x"`,
+/// tags become the window title so that classifier rules can match them.
+/// Intervals that are still open are skipped
+pub fn parse(contents: &str) -> Result<Vec<ActivityRecord>, ImportError> {
+    let mut records: Vec<ActivityRecord> = vec![];
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        let invalid_line = || ImportError::InvalidLine(index + 1, String::from(line));
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let body = line.strip_prefix("inc ").ok_or_else(invalid_line)?;
+        let (range, tags) = match body.find(" # ") {
+            Some(position) => (&body[..position], parse_tags(&body[position + 3..])),
+            None => (body, vec![]),
+        };
+        let times: Vec<&str> = range.split(" - ").map(str::trim).collect();
+
+        if times.len() == 1 {
+            continue;
+        }
+        if times.len() != 2 {
+            return Err(invalid_line());
+        }
+
+        let start = parse_time(times[0]).ok_or_else(invalid_line)?;
+        let end = parse_time(times[1]).ok_or_else(invalid_line)?;
+
+        records.push(ActivityRecord {
+            time_range: (start, end),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::ActiveWindow(tags.join(" "), String::from(APP_NAME), String::from(APP_CLASS)),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        });
+    }
+
+    Ok(records)
+}
+
+fn parse_time(time: &str) -> Option<SystemTime> {
+    let naive = NaiveDateTime::parse_from_str(time, "%Y%m%dT%H%M%SZ").ok()?;
+
+    Some(SystemTime::from(DateTime::<Utc>::from_utc(naive, Utc)))
+}
+
+/// Splits tags separated by spaces, tags with spaces in them are quoted
+fn parse_tags(tags: &str) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+    let mut current = String::new();
+    let mut is_quoted = false;
+    let mut chars = tags.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if is_quoted => current.extend(chars.next()),
+            '"' => is_quoted = !is_quoted,
+            ' ' if !is_quoted => {
+                if !current.is_empty() {
+                    result.push(current.clone());
+                    current.clear();
+                }
+            },
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn parses_closed_intervals() {
+        let data = "
+            inc 20200601T100000Z - 20200601T110000Z # coding \"project \\\"x\\\"\"
+            inc 20200601T120000Z - 20200601T121500Z
+            inc 20200601T130000Z # still running
+        ";
+        let start = UNIX_EPOCH + Duration::from_secs(1_591_005_600);
+        let records = parse(data).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].time_range, (start, start + Duration::from_secs(3600)));
+        assert_eq!(
+            records[0].archetype,
+            Archetype::ActiveWindow(String::from("coding project \"x\""), String::from(APP_NAME), String::from(APP_CLASS))
+        );
+        assert_eq!(
+            records[1].archetype,
+            Archetype::ActiveWindow(String::new(), String::from(APP_NAME), String::from(APP_CLASS))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_lines() {
+        match parse("inc 20200601T100000Z - 20200601T110000Z\nfoo") {
+            Err(ImportError::InvalidLine(number, _)) => assert_eq!(number, 2),
+            _ => panic!("line that is not an interval must be reported"),
+        }
+    }
+}
@@ -1,15 +1,29 @@
+mod cli;
+mod control;
 mod event;
+mod export;
+mod import;
 mod record_store;
+mod server;
 mod state;
+mod stats;
 mod tui;
 mod window_manager;
 mod xorg;
 mod classifier;
+mod config_file;
 mod constants;
 
 use event::*;
 use state::AppState;
-use std::time;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time,
+};
+use tokio::signal::unix::{signal, SignalKind};
 use window_manager::OSWindowManager;
 use xorg::XORGWindowManager;
 use crate::tui::*;
@@ -29,9 +43,25 @@ where
     Ok({})
 }
 
-async fn main_loop() -> Result<(), Box<dyn std::error::Error>> {
-    let mut state = AppState::new()?;
+/// Raises the returned flag once the app is asked to stop with SIGINT or SIGTERM
+fn listen_for_shutdown() -> Result<Arc<AtomicBool>, Box<dyn std::error::Error>> {
+    let shutdown_requested = Arc::new(AtomicBool::new(false));
+
+    for kind in vec![SignalKind::interrupt(), SignalKind::terminate()] {
+        let mut stream = signal(kind)?;
+        let flag = shutdown_requested.clone();
+
+        tokio::spawn(async move {
+            stream.recv().await;
+            flag.store(true, Ordering::SeqCst);
+        });
+    }
+
+    Ok(shutdown_requested)
+}
 
+/// Tracks windows and runs the TUI until the app is asked to stop
+fn run(state: &mut AppState, tui: &mut Tui, shutdown_requested: &AtomicBool) -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(any(target_os = "linux"))]
     let wm = XORGWindowManager::default();
 
@@ -41,25 +71,31 @@ async fn main_loop() -> Result<(), Box<dyn std::error::Error>> {
     let sleep_duration = time::Duration::new(1, 0);
 
     let events = Events::with_config(EventConfig::default());
-    let mut tui = tui::Tui::new(&state)?;
 
     tui.clear()?;
 
-    while is_running {
-        let keys = wm.query_keyboard();
-        let mouse = wm.query_mouse_pointer();
+    while is_running && !shutdown_requested.load(Ordering::SeqCst) {
         let current_time = time::SystemTime::now();
 
         time_elapsed = current_time
             .duration_since(cycle_start_time)
             .unwrap_or(time::Duration::new(0, 0));
 
-        state.update_keys(keys);
-        state.update_mouse_info(&mouse);
+        match wm.idle_time() {
+            Some(idle_time) => state.update_idle_time(idle_time),
+            // Polling misses input between polls, it's only used when the OS can't tell idle time
+            None => {
+                state.update_keys(wm.query_keyboard());
+                state.update_mouse_info(&wm.query_mouse_pointer());
+            },
+        }
+        if let Some(err) = state.handle_control_requests().pop() {
+            tui.set_message(format!("Could not answer a control connection: {}", err));
+        }
 
         if time_elapsed > sleep_duration {
-            update_window_info(&wm, &mut state)?;
-            tui.tick(&state);
+            update_window_info(&wm, state)?;
+            tui.tick(state);
             cycle_start_time = current_time;
         }
 
@@ -67,19 +103,46 @@ async fn main_loop() -> Result<(), Box<dyn std::error::Error>> {
             match event {
                 Event::Input(key) => match key {
                     Key::Ctrl('c') => is_running = false,
-                    _ => {}
+                    _ => tui.handle_key(key, state),
                 },
                 Event::Tick => {
-                    tui.draw(&state)?;
+                    tui.draw(state)?;
                 },
             }
         }
     }
+
+    Ok({})
+}
+
+async fn main_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = AppState::new()?;
+    let shutdown_requested = listen_for_shutdown()?;
+    let mut tui = tui::Tui::new(&state)?;
+
+    // The record being tracked is stored and the terminal brought back however the loop ends
+    let result = run(&mut state, &mut tui, &shutdown_requested);
+    let shutdown = state.shutdown();
+    let restore = tui.restore();
+
+    result?;
+    shutdown?;
+    restore?;
     Ok({})
 }
 
 #[tokio::main]
 async fn main() {
+    let matches = cli::app().get_matches();
+
+    if let Some(result) = cli::run(&matches) {
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     match main_loop().await {
         Err(err) => println!("FATAL ERROR: {}", err),
         Ok(_) => {}
@@ -0,0 +1,139 @@
+use super::{
+    crypto::{open_file, seal_file, Cipher},
+    db::TrackingDate,
+    migrations::{SchemaHeader, SCHEMA_VERSION},
+    retention::ArchivedDay,
+    utils::{get_path_for_archive, write_atomically, RecordStoreError},
+    ActivityRecord,
+};
+use chrono::{Datelike, NaiveDate};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Days past the retention window, kept in a file per month.
+///
+/// Month files hold totals of each archived day rather than its records,
+/// e.g. `archive/2020-06.db` holds the archived days of June 2020
+pub struct Archive {
+    dir: PathBuf,
+    cipher: Option<Cipher>,
+}
+
+/// Contents of a month file, keyed by tracking dates
+#[derive(Serialize, Deserialize)]
+pub struct Month {
+    pub header: SchemaHeader,
+    pub days: HashMap<String, ArchivedDay>,
+}
+
+impl Month {
+    pub fn new(days: HashMap<String, ArchivedDay>) -> Self {
+        Self {
+            header: SchemaHeader::current(),
+            days,
+        }
+    }
+}
+
+/// Writes a month file, sealed with the store key if there is one
+pub fn write_month(path: &Path, month: &Month, cipher: Option<&Cipher>) -> Result<(), RecordStoreError> {
+    let bytes = seal_file(bincode::serialize(month)?, cipher);
+
+    write_atomically(path, &bytes)?;
+    Ok(())
+}
+
+impl Archive {
+    pub fn in_dir(data_dir: &Path, cipher: Option<Cipher>) -> Self {
+        Self {
+            dir: get_path_for_archive(data_dir),
+            cipher,
+        }
+    }
+
+    fn month_path(&self, date: &NaiveDate) -> PathBuf {
+        self.dir.join(format!("{:04}-{:02}.db", date.year(), date.month()))
+    }
+
+    fn month_paths(&self) -> Result<Vec<PathBuf>, RecordStoreError> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut paths: Vec<PathBuf> = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+
+            if path.extension().map_or(false, |extension| extension == "db") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn read_month(&self, path: &Path) -> Result<HashMap<String, ArchivedDay>, RecordStoreError> {
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let bytes = open_file(fs::read(path)?, self.cipher.as_ref())?;
+        match SchemaHeader::detect(&bytes) {
+            Some(SCHEMA_VERSION) => Ok(bincode::deserialize::<Month>(&bytes)?.days),
+            Some(version) => Err(RecordStoreError::UnsupportedSchema(version)),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// Adds up records of a day into its month file.
+    ///
+    /// Time that is archived already is not counted again,
+    /// so archiving the same day twice keeps a single copy of it
+    pub fn store_day(&self, date: &NaiveDate, records: Vec<ActivityRecord>) -> Result<(), RecordStoreError> {
+        let path = self.month_path(date);
+        let mut days = self.read_month(&path)?;
+
+        days.entry(TrackingDate::from(date).0).or_insert_with(ArchivedDay::default).add_records(records);
+
+        fs::create_dir_all(&self.dir)?;
+        write_month(&path, &Month::new(days), self.cipher.as_ref())
+    }
+
+    /// Gets totals of an archived day, months that were never archived aren't read
+    pub fn query_day(&self, date: &NaiveDate) -> Result<ArchivedDay, RecordStoreError> {
+        let path = self.month_path(date);
+
+        if !path.exists() {
+            return Err(RecordStoreError::NoDataOnDate(*date));
+        }
+        match self.read_month(&path)?.remove(&TrackingDate::from(date).0) {
+            Some(day) if !day.totals.is_empty() => Ok(day),
+            _ => Err(RecordStoreError::NoDataOnDate(*date)),
+        }
+    }
+
+    pub fn query_dates(&self) -> Result<Vec<NaiveDate>, RecordStoreError> {
+        let mut dates: Vec<NaiveDate> = vec![];
+
+        for path in self.month_paths()? {
+            dates.extend(self.read_month(&path)?.keys().filter_map(|key| TrackingDate(key.clone()).parse()));
+        }
+        dates.sort();
+        Ok(dates)
+    }
+
+    /// Rewrites all month files with a new key
+    pub fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        for path in self.month_paths()? {
+            let days = self.read_month(&path)?;
+
+            write_month(&path, &Month::new(days), cipher.as_ref())?;
+        }
+
+        self.cipher = cipher;
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use super::{
+    crypto::Cipher,
+    utils::RecordStoreError,
+    ActivityRecord,
+};
+use chrono::NaiveDate;
+
+/// Storage engine behind `RecordStore`.
+///
+/// Records are grouped by tracking date, each backend is free
+/// to lay them out on disk however it sees fit
+pub trait RecordBackend {
+    /// Appends a record to the records of a given date
+    fn push_record(&self, date: &NaiveDate, record: ActivityRecord) -> Result<(), RecordStoreError>;
+
+    /// Replaces all records of a given date, e.g. to put records
+    /// in the middle of a day that has already been tracked
+    fn replace_records(&self, date: &NaiveDate, records: Vec<ActivityRecord>) -> Result<(), RecordStoreError>;
+
+    /// Drops a date with all its records, e.g. once it is archived
+    fn remove_records(&self, date: &NaiveDate) -> Result<(), RecordStoreError>;
+
+    /// Gets all records of a given date in the order they were pushed
+    fn query_records(&self, date: &NaiveDate) -> Result<Vec<ActivityRecord>, RecordStoreError>;
+
+    /// Gets records of a given date pushed after the first `index` ones,
+    /// so that a day being tracked can be followed without fetching it over and over
+    fn query_records_since(&self, date: &NaiveDate, index: usize) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        Ok(self.query_records(date)?.into_iter().skip(index).collect())
+    }
+
+    /// Lists all dates that have records registered
+    fn query_dates(&self) -> Result<Vec<NaiveDate>, RecordStoreError>;
+
+    /// Rewrites all records with a new key, `None` stores them in plain
+    fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError>;
+
+    /// Sends changes to the server records are mirrored to, if any.
+    ///
+    /// Returns the number of changes that are still to be sent
+    fn sync(&self) -> Result<usize, RecordStoreError> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,165 @@
+use super::{
+    crypto::{open_file, seal_file, Cipher},
+    edit::ForgetMode,
+    migrations::{SchemaHeader, SCHEMA_VERSION},
+    utils::{get_path_for_checkpoint, write_atomically, RecordStoreError},
+    ActivityRecord, Archetype, ProductivityStatus,
+};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Record the tracker is accumulating at the moment, saved so that it outlives a crash
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Checkpoint {
+    pub archetype: Archetype,
+    pub started_at: SystemTime,
+    /// Last moment the record was known to be still open
+    pub seen_at: SystemTime,
+}
+
+impl Checkpoint {
+    /// Closes the record at the last moment it was seen
+    pub fn into_record(self) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (self.started_at, std::cmp::max(self.started_at, self.seen_at)),
+            productivity: ProductivityStatus::Neutral,
+            archetype: self.archetype,
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    /// Forgets the part of the record within a time range, `None` if nothing is left.
+    ///
+    /// The record can't be split, so it keeps what was seen past the range if it started within it, is cut at
+    /// the start of the range otherwise, or is anonymized as a whole
+    pub fn forget(&self, from: &SystemTime, to: &SystemTime, mode: ForgetMode) -> Option<Checkpoint> {
+        if self.seen_at <= *from || self.started_at >= *to {
+            return Some(self.clone());
+        }
+
+        match mode {
+            ForgetMode::Remove if self.started_at >= *from && self.seen_at > *to => {
+                Some(Checkpoint { started_at: *to, ..self.clone() })
+            },
+            ForgetMode::Remove if self.started_at >= *from => None,
+            ForgetMode::Remove => Some(Checkpoint { seen_at: *from, ..self.clone() }),
+            ForgetMode::Anonymize => Some(Checkpoint { archetype: self.archetype.clone().without_title(), ..self.clone() }),
+        }
+    }
+}
+
+/// Oldest schema version whose checkpoints are laid out the same way as current ones
+const OLDEST_COMPATIBLE_VERSION: u32 = 1;
+
+/// `current.checkpoint` file in the data directory.
+///
+/// Contains a `SchemaHeader` followed by a bincode-encoded `Checkpoint`, sealed
+/// if the store is encrypted. The file only exists while there is an open record
+pub struct CheckpointFile {
+    path: PathBuf,
+    cipher: Option<Cipher>,
+}
+
+impl CheckpointFile {
+    pub fn in_dir(dir_path: &Path, cipher: Option<Cipher>) -> Self {
+        Self {
+            path: get_path_for_checkpoint(dir_path),
+            cipher,
+        }
+    }
+
+    pub fn save(&self, checkpoint: &Checkpoint) -> Result<(), RecordStoreError> {
+        let mut bytes = SchemaHeader::current().encode();
+        bytes.extend(bincode::serialize(checkpoint)?);
+
+        write_atomically(&self.path, &seal_file(bytes, self.cipher.as_ref()))?;
+        Ok(())
+    }
+
+    pub fn load(&self) -> Result<Option<Checkpoint>, RecordStoreError> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = open_file(fs::read(&self.path)?, self.cipher.as_ref())?;
+        match SchemaHeader::detect(&bytes) {
+            Some(version) if version >= OLDEST_COMPATIBLE_VERSION && version <= SCHEMA_VERSION => {},
+            Some(version) => return Err(RecordStoreError::UnsupportedSchema(version)),
+            None => return Ok(None),
+        };
+
+        Ok(Some(bincode::deserialize(&bytes[SchemaHeader::SIZE..])?))
+    }
+
+    /// Rewrites the checkpoint with a new key
+    pub fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        let checkpoint = self.load()?;
+
+        self.cipher = cipher;
+        match checkpoint {
+            Some(checkpoint) => self.save(&checkpoint),
+            None => Ok(()),
+        }
+    }
+
+    pub fn clear(&self) -> Result<(), RecordStoreError> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::utils::test_dir;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn save_load_and_clear() {
+        let file = CheckpointFile::in_dir(&test_dir("checkpoint"), None);
+        let checkpoint = Checkpoint {
+            archetype: Archetype::ActiveWindow(
+                String::from("trackr"),
+                String::from("code-oss"),
+                String::from("Code"),
+            ),
+            started_at: UNIX_EPOCH + Duration::from_secs(100),
+            seen_at: UNIX_EPOCH + Duration::from_secs(130),
+        };
+
+        assert_eq!(file.load().unwrap(), None);
+
+        file.save(&checkpoint).unwrap();
+        assert_eq!(file.load().unwrap(), Some(checkpoint.clone()));
+        assert_eq!(
+            checkpoint.into_record().time_range,
+            (UNIX_EPOCH + Duration::from_secs(100), UNIX_EPOCH + Duration::from_secs(130))
+        );
+
+        file.clear().unwrap();
+        assert_eq!(file.load().unwrap(), None);
+    }
+
+    #[test]
+    fn forget_keeps_what_was_seen_past_the_range() {
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let checkpoint = Checkpoint { archetype: Archetype::AFK, started_at: at(100), seen_at: at(130) };
+
+        let keep_tail = Checkpoint { started_at: at(120), ..checkpoint.clone() };
+        let keep_head = Checkpoint { seen_at: at(110), ..checkpoint.clone() };
+
+        assert_eq!(checkpoint.forget(&at(90), &at(120), ForgetMode::Remove), Some(keep_tail));
+        assert_eq!(checkpoint.forget(&at(110), &at(120), ForgetMode::Remove), Some(keep_head));
+        assert_eq!(checkpoint.forget(&at(90), &at(130), ForgetMode::Remove), None);
+        assert_eq!(checkpoint.forget(&at(130), &at(140), ForgetMode::Remove), Some(checkpoint.clone()));
+    }
+}
@@ -1,28 +1,174 @@
 use std::path::PathBuf;
-use crate::constants::*;
+use crate::{
+    constants::*,
+    config_file::{config_file_path, read_config_file},
+};
+use super::RecordStoreError;
 
+/// Storage engine used to persist activity records
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    /// Records kept by rustbreak in a file per tracking day under `days`
+    Rustbreak,
+    /// SQLite database in `records.sqlite3`
+    Sqlite,
+}
+
+impl Default for StoreBackend {
+    fn default() -> Self {
+        StoreBackend::Rustbreak
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RecordStoreConfig {
     pub data_dir: PathBuf,
+    pub backend: StoreBackend,
+    /// Local hour at which a tracking day begins, so that
+    /// late-night sessions are counted towards the previous day
+    pub day_start_hour: u32,
+    /// Refuse to open the store unless it is encrypted
+    pub encrypted: bool,
+    /// File the passphrase of an encrypted store is read from
+    pub keyfile: Option<PathBuf>,
+    /// Number of days records are kept in full, older days are archived
+    /// as time per activity. `None` keeps everything
+    pub retention_days: Option<u32>,
+    /// Server records are mirrored to, `None` keeps them on this machine only
+    pub remote: Option<RemoteConfig>,
+}
+
+/// trackr server that a store mirrors its records to, see `trackr serve`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RemoteConfig {
+    /// Base URL of the server, e.g. `http://192.168.1.2:8631`
+    pub url: String,
+    pub token: String,
+    /// Name records of this machine are kept under on the server
+    pub client: String,
+}
+
+impl RecordStoreConfig {
+    /// Configuration for a store in a given directory with default settings
+    pub fn in_dir(data_dir: PathBuf) -> Self {
+        Self {
+            data_dir,
+            backend: StoreBackend::default(),
+            day_start_hour: 0,
+            encrypted: false,
+            keyfile: None,
+            retention_days: None,
+            remote: None,
+        }
+    }
+}
+
+/// `[store]` section of the configuration file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StoreSection {
+    pub backend: Option<StoreBackend>,
+    pub day_starts_at: Option<u32>,
+    pub encrypted: Option<bool>,
+    pub keyfile: Option<String>,
+    pub retention_days: Option<u32>,
+    /// URL of a trackr server to mirror records to
+    pub remote: Option<String>,
+    /// Token the server was started with
+    pub remote_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    name: Option<String>,
+    store: Option<StoreSection>,
+}
+
+/// Parses the configuration file, a missing file gives default settings
+fn get_config_file() -> Result<ConfigFile, RecordStoreError> {
+    match read_config_file() {
+        Some(raw) => parse_config_file(&raw),
+        None => Ok(ConfigFile::default()),
+    }
+}
+
+fn parse_config_file(raw: &str) -> Result<ConfigFile, RecordStoreError> {
+    toml::from_str(raw)
+        .map_err(|err| RecordStoreError::InvalidConfig(format!("could not parse {:?}: {}", config_file_path(), err)))
 }
 
-fn get_config_from_dbg_file() -> RecordStoreConfig {
-    let data_dir = PathBuf::from(DEV_DB_PATH);
-    RecordStoreConfig { data_dir }
+fn get_data_dir_from_dbg_file() -> PathBuf {
+    PathBuf::from(DEV_DB_PATH)
 }
 
-fn get_global_config() -> RecordStoreConfig {
+fn get_global_data_dir() -> PathBuf {
     match directories::ProjectDirs::from(APP_CLASSIFIER, APP_CORP, APP_NAME) {
-        Some(dirs) => RecordStoreConfig { data_dir: dirs.data_dir().to_owned() },
-        None => get_config_from_dbg_file()
+        Some(dirs) => dirs.data_dir().to_owned(),
+        None => get_data_dir_from_dbg_file()
     }
 }
 
-impl Default for RecordStoreConfig {
-    fn default() -> Self {
+impl RecordStoreConfig {
+    /// Reads settings from the `[store]` section of the configuration file
+    pub fn load() -> Result<Self, RecordStoreError> {
+        Self::from_config_file(get_config_file()?)
+    }
+
+    fn from_config_file(file: ConfigFile) -> Result<Self, RecordStoreError> {
+        let section = file.store.unwrap_or_default();
+        let remote = match (section.remote, section.remote_token) {
+            (Some(url), Some(token)) => Some(RemoteConfig {
+                url: String::from(url.trim_end_matches('/')),
+                token,
+                client: file.name.unwrap_or_else(|| String::from(DEFAULT_MACHINE_NAME)),
+            }),
+            (None, None) => None,
+            // Syncing silently stays off otherwise
+            (Some(_), None) | (None, Some(_)) => {
+                return Err(RecordStoreError::InvalidConfig(String::from("remote and remote_token must be set together")));
+            },
+        };
+
         #[cfg(debug_assertions)]
-        { get_config_from_dbg_file() }
-        
+        let data_dir = get_data_dir_from_dbg_file();
+
         #[cfg(not(debug_assertions))]
-        { get_global_config() }
+        let data_dir = get_global_data_dir();
+
+        let day_start_hour = match section.day_starts_at {
+            Some(hour) if hour >= 24 => {
+                return Err(RecordStoreError::InvalidConfig(format!("day_starts_at must be an hour from 0 to 23, got {}", hour)));
+            },
+            hour => hour.unwrap_or(0),
+        };
+
+        Ok(Self {
+            data_dir,
+            backend: section.backend.unwrap_or_default(),
+            day_start_hour,
+            encrypted: section.encrypted.unwrap_or(false),
+            keyfile: section.keyfile.map(PathBuf::from),
+            retention_days: section.retention_days,
+            remote,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        let config = |raw: &str| parse_config_file(raw).and_then(RecordStoreConfig::from_config_file);
+
+        assert_eq!(config("[store]\nday_starts_at = 4").unwrap().day_start_hour, 4);
+        assert_eq!(config("[store]\nday_starts_at = 24").is_err(), true);
+        assert_eq!(config("[store]\nbackend = \"sqlite").is_err(), true);
+        assert_eq!(config("[store]\nbackend = \"mysql\"").is_err(), true);
+        assert_eq!(config("[store]\nremote = \"https://trackr.example.org\"").is_err(), true);
+        assert_eq!(config("[store]\nremote_token = \"secret\"").is_err(), true);
+        let remote = config("[store]\nremote = \"https://trackr.example.org/\"\nremote_token = \"secret\"").unwrap().remote;
+        assert_eq!(remote.unwrap().url, "https://trackr.example.org");
     }
 }
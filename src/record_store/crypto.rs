@@ -0,0 +1,335 @@
+//! Encryption of record store files at rest.
+//!
+//! Records are sealed with XChaCha20-Poly1305 using a key derived from
+//! the user's passphrase with Argon2id. Salt, cost parameters and a verifier
+//! that tells a wrong passphrase from corrupted data are kept in
+//! `encryption.params` next to the store, so every backend shares them.
+use super::{
+    migrations::SchemaHeader,
+    utils::{get_path_for_hash_key, get_path_for_key_params, write_atomically, RecordStoreError},
+};
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use hmac::{Hmac, Mac, NewMac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable the passphrase is taken from before trying a keyfile or a prompt
+pub const PASSPHRASE_ENV: &'static str = "TRACKR_PASSPHRASE";
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+const SALT_SIZE: usize = 16;
+
+/// Plain text sealed into the verifier of `encryption.params`
+const VERIFIER_TEXT: &'static [u8] = b"trackr";
+
+/// Key used to seal and open records
+#[derive(Clone)]
+pub struct Cipher {
+    key: [u8; KEY_SIZE],
+}
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cipher {{ .. }}")
+    }
+}
+
+impl Cipher {
+    pub fn from_key(key: [u8; KEY_SIZE]) -> Self {
+        Self { key }
+    }
+
+    /// Encrypts a message, the random nonce is prepended to the result
+    pub fn seal(&self, plain: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let sealed = self.aead()
+            .encrypt(&XNonce::from(nonce), plain)
+            .expect("Encryption does not fail for messages that fit in memory");
+
+        let mut result = nonce.to_vec();
+        result.extend(sealed);
+        result
+    }
+
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, RecordStoreError> {
+        if sealed.len() < NONCE_SIZE {
+            return Err(RecordStoreError::DecryptionFailed);
+        }
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce.copy_from_slice(&sealed[..NONCE_SIZE]);
+
+        self.aead()
+            .decrypt(&XNonce::from(nonce), &sealed[NONCE_SIZE..])
+            .map_err(|_| RecordStoreError::DecryptionFailed)
+    }
+
+    fn aead(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&Key::from(self.key))
+    }
+}
+
+/// Contents of `encryption.params`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyParams {
+    salt: [u8; SALT_SIZE],
+    /// Argon2 memory cost in KiB
+    mem_cost: u32,
+    time_cost: u32,
+    verifier: Vec<u8>,
+}
+
+impl KeyParams {
+    /// Derives a key from a new passphrase with a fresh salt
+    pub fn create(passphrase: &str) -> Result<(Self, Cipher), RecordStoreError> {
+        Self::create_with_costs(passphrase, 65536, 3)
+    }
+
+    fn create_with_costs(passphrase: &str, mem_cost: u32, time_cost: u32) -> Result<(Self, Cipher), RecordStoreError> {
+        let mut salt = [0u8; SALT_SIZE];
+        rand::thread_rng().fill_bytes(&mut salt);
+
+        let mut params = Self { salt, mem_cost, time_cost, verifier: vec![] };
+        let cipher = params.derive(passphrase)?;
+
+        params.verifier = cipher.seal(VERIFIER_TEXT);
+        Ok((params, cipher))
+    }
+
+    /// Derives the key from a passphrase, making sure it is the right one
+    pub fn unlock(&self, passphrase: &str) -> Result<Cipher, RecordStoreError> {
+        let cipher = self.derive(passphrase)?;
+
+        match cipher.open(&self.verifier) {
+            Ok(ref text) if text.as_slice() == VERIFIER_TEXT => Ok(cipher),
+            _ => Err(RecordStoreError::WrongPassphrase),
+        }
+    }
+
+    fn derive(&self, passphrase: &str) -> Result<Cipher, RecordStoreError> {
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+            hash_length: KEY_SIZE as u32,
+            ..argon2::Config::default()
+        };
+        let hash = argon2::hash_raw(passphrase.as_bytes(), &self.salt, &config)
+            .map_err(|_| RecordStoreError::WrongPassphrase)?;
+
+        let mut key = [0u8; KEY_SIZE];
+        key.copy_from_slice(&hash);
+        Ok(Cipher::from_key(key))
+    }
+
+    pub fn load(dir_path: &Path) -> Result<Option<Self>, RecordStoreError> {
+        let path = get_path_for_key_params(dir_path);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(bincode::deserialize(&fs::read(path)?)?))
+    }
+
+    pub fn save(&self, dir_path: &Path) -> Result<(), RecordStoreError> {
+        write_atomically(&get_path_for_key_params(dir_path), &bincode::serialize(self)?)?;
+        Ok(())
+    }
+}
+
+/// Random key of a store that privacy rules hash window titles with, kept in `hash.key`.
+///
+/// Titles are hashed with HMAC-SHA256, so a hashed title can't be guessed by hashing
+/// likely titles unless the key is known. Anyone who can read `hash.key` can still guess,
+/// in encrypted stores it is sealed with the store key like the records are
+#[derive(Clone)]
+pub struct HashKey {
+    key: [u8; KEY_SIZE],
+}
+
+impl std::fmt::Debug for HashKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HashKey {{ .. }}")
+    }
+}
+
+impl HashKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; KEY_SIZE];
+        rand::thread_rng().fill_bytes(&mut key);
+
+        Self { key }
+    }
+
+    /// Reads the key of a store, `None` if it has none yet
+    pub fn load(dir_path: &Path, cipher: Option<&Cipher>) -> Result<Option<Self>, RecordStoreError> {
+        let path = get_path_for_hash_key(dir_path);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+        let key = bincode::deserialize(&open_file(fs::read(path)?, cipher)?)?;
+        Ok(Some(Self { key }))
+    }
+
+    /// Generates a key for a store and saves it
+    pub fn create(dir_path: &Path, cipher: Option<&Cipher>) -> Result<Self, RecordStoreError> {
+        let key = Self::generate();
+
+        key.save(dir_path, cipher)?;
+        Ok(key)
+    }
+
+    pub fn save(&self, dir_path: &Path, cipher: Option<&Cipher>) -> Result<(), RecordStoreError> {
+        write_atomically(&get_path_for_hash_key(dir_path), &seal_file(bincode::serialize(&self.key)?, cipher))?;
+        Ok(())
+    }
+
+    /// Hex-encoded HMAC-SHA256 of a text
+    pub fn hash(&self, text: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.key).expect("HMAC takes keys of any size");
+        mac.update(text.as_bytes());
+
+        mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// Where the passphrase of an encrypted store comes from
+#[derive(Debug, Clone)]
+pub enum PassphraseSource {
+    /// `TRACKR_PASSPHRASE` environment variable
+    Env,
+    /// File that contains the passphrase, trailing line breaks are ignored
+    Keyfile(PathBuf),
+    Prompt,
+}
+
+impl PassphraseSource {
+    /// Uses the environment variable if it is set, then the keyfile if there is one, then a prompt
+    pub fn detect(keyfile: Option<&Path>) -> Self {
+        match (std::env::var_os(PASSPHRASE_ENV), keyfile) {
+            (Some(_), _) => PassphraseSource::Env,
+            (None, Some(path)) => PassphraseSource::Keyfile(path.to_owned()),
+            (None, None) => PassphraseSource::Prompt,
+        }
+    }
+
+    pub fn read(&self, prompt: &str) -> Result<String, RecordStoreError> {
+        match self {
+            PassphraseSource::Env => std::env::var(PASSPHRASE_ENV).map_err(|_| RecordStoreError::WrongPassphrase),
+            PassphraseSource::Keyfile(path) => {
+                let contents = fs::read_to_string(path)?;
+                Ok(String::from(contents.trim_end_matches(|c| c == '\n' || c == '\r')))
+            },
+            PassphraseSource::Prompt => Ok(rpassword::read_password_from_tty(Some(prompt))?),
+        }
+    }
+
+    /// Reads a passphrase that is about to be set, prompts are repeated to avoid typos
+    pub fn read_new(&self, prompt: &str) -> Result<String, RecordStoreError> {
+        let passphrase = self.read(prompt)?;
+
+        if let PassphraseSource::Prompt = self {
+            if rpassword::read_password_from_tty(Some("Repeat passphrase: "))? != passphrase {
+                return Err(RecordStoreError::PassphrasesDiffer);
+            }
+        }
+        Ok(passphrase)
+    }
+}
+
+/// Turns plain contents of a store file into what is written to disk.
+///
+/// Encrypted files begin with an encrypted `SchemaHeader` followed by sealed contents
+pub fn seal_file(plain: Vec<u8>, cipher: Option<&Cipher>) -> Vec<u8> {
+    match cipher {
+        Some(cipher) => {
+            let mut bytes = SchemaHeader::encrypted().encode();
+            bytes.extend(cipher.seal(&plain));
+            bytes
+        },
+        None => plain,
+    }
+}
+
+/// Gets plain contents of a store file
+pub fn open_file(bytes: Vec<u8>, cipher: Option<&Cipher>) -> Result<Vec<u8>, RecordStoreError> {
+    if !SchemaHeader::is_encrypted(&bytes) {
+        return Ok(bytes);
+    }
+
+    match cipher {
+        Some(cipher) => cipher.open(&bytes[SchemaHeader::SIZE..]),
+        None => Err(RecordStoreError::EncryptedStore),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Cheap parameters so that tests don't spend seconds deriving keys
+    pub fn test_params(passphrase: &str) -> (KeyParams, Cipher) {
+        KeyParams::create_with_costs(passphrase, 64, 1).unwrap()
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let (_, cipher) = test_params("secret");
+        let sealed = cipher.seal(b"window title");
+
+        assert_eq!(sealed.windows(6).any(|window| window == b"window"), false);
+        assert_eq!(cipher.open(&sealed).unwrap(), b"window title".to_vec());
+
+        let (_, other) = test_params("secret");
+        match other.open(&sealed) {
+            Err(RecordStoreError::DecryptionFailed) => {},
+            _ => panic!("key derived with another salt must not open records"),
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase_is_detected() {
+        let (params, _) = test_params("secret");
+
+        assert_eq!(params.unlock("secret").is_ok(), true);
+        match params.unlock("guess") {
+            Err(RecordStoreError::WrongPassphrase) => {},
+            _ => panic!("wrong passphrase must be rejected"),
+        }
+    }
+
+    #[test]
+    fn sealed_file_keeps_plain_files_readable() {
+        let (_, cipher) = test_params("secret");
+        let plain = SchemaHeader::current().encode();
+
+        assert_eq!(open_file(plain.clone(), Some(&cipher)).unwrap(), plain);
+        assert_eq!(open_file(seal_file(plain.clone(), Some(&cipher)), Some(&cipher)).unwrap(), plain);
+        match open_file(seal_file(plain, Some(&cipher)), None) {
+            Err(RecordStoreError::EncryptedStore) => {},
+            _ => panic!("encrypted file must not be read without a key"),
+        }
+    }
+
+    #[test]
+    fn hash_key_is_kept_sealed() {
+        let dir = crate::record_store::test_dir("hash_key");
+        let (_, cipher) = test_params("secret");
+        let key = HashKey::create(&dir, Some(&cipher)).unwrap();
+
+        assert_eq!(HashKey::load(&dir, Some(&cipher)).unwrap().unwrap().hash("Bank"), key.hash("Bank"));
+        assert_eq!(HashKey::load(&dir, None).is_err(), true);
+        assert_ne!(HashKey::generate().hash("Bank"), key.hash("Bank"));
+    }
+}
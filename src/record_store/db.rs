@@ -1,11 +1,57 @@
 use super::{
-    utils::{Database, RecordStoreError},
+    backend::RecordBackend,
+    crypto::Cipher,
+    journal::{read_journal, Journal, JournalEntry},
+    migrations::SCHEMA_VERSION,
+    utils::{
+        create_memory_db, get_path_for_day, get_path_for_days, get_path_for_db, get_path_for_journal,
+        read_snapshot, write_snapshot, Database, RecordStoreError, Snapshot,
+    },
     ActivityRecord,
 };
-use chrono::{Local, NaiveDate};
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, TimeZone};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
-struct TrackingDate(String);
+pub(super) struct TrackingDate(pub(super) String);
+
+impl TrackingDate {
+    /// Gets tracking date a moment in time belongs to.
+    ///
+    /// Tracking days begin at `day_start_hour` local time, so with
+    /// the day starting at 4 the night of 2020-06-01 lasts until 04:00 of 2020-06-02
+    pub(super) fn from_time(time: &SystemTime, day_start_hour: u32) -> Self {
+        let local_time = DateTime::<Local>::from(*time) - Duration::hours(day_start_hour as i64);
+
+        Self::from(local_time.date().naive_local())
+    }
+
+    /// Gets the date of a key, `None` if the key is damaged
+    pub(super) fn parse(&self) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(&self.0, "%Y-%m-%d").ok()
+    }
+
+    /// Gets the moment this tracking date begins
+    pub(super) fn start(&self, day_start_hour: u32) -> SystemTime {
+        let date = NaiveDate::from(self.clone());
+
+        local_to_system_time(date.and_hms(day_start_hour, 0, 0))
+    }
+}
+
+fn local_to_system_time(time: NaiveDateTime) -> SystemTime {
+    match Local.from_local_datetime(&time) {
+        LocalResult::Single(local) | LocalResult::Ambiguous(local, _) => SystemTime::from(local),
+        // Clocks were set forward over this moment, begin once they're through
+        LocalResult::None => local_to_system_time(time + Duration::hours(1)),
+    }
+}
 impl From<NaiveDate> for TrackingDate {
     fn from(date: NaiveDate) -> Self {
         Self(date.format("%Y-%m-%d").to_string())
@@ -24,71 +70,525 @@ impl From<TrackingDate> for NaiveDate {
     }
 }
 
+/// Number of journal entries after which the journal is folded into day files
+const COMPACTION_THRESHOLD: usize = 256;
+
+/// Days changed since the journal was last folded into day files
+#[derive(Default)]
+struct ShardState {
+    dirty: HashSet<String>,
+    /// Day records were last pushed to, kept in memory between compactions
+    last_pushed: Option<String>,
+}
+
+/// Rustbreak-backed record database.
+///
+/// File databases keep a snapshot of every tracking day in `days/YYYY-MM-DD.db` and
+/// append new records to a journal, so pushing a record never rewrites a snapshot.
+/// The journal is folded into the day files it touched on startup and every
+/// `COMPACTION_THRESHOLD` records. Memory databases have no files at all.
+///
+/// Rustbreak only holds the days that are being written to, others are read from
+/// their files when queried, so the history is never loaded into memory as a whole
 pub struct DB {
     db: Box<Database>,
+    dir_path: Option<PathBuf>,
+    journal: Option<Journal>,
+    cipher: Option<Cipher>,
+    shards: Mutex<ShardState>,
 }
 
 impl DB {
-    pub fn new(db: Database) -> Result<Self, RecordStoreError> {
-        let me = Self { db: Box::new(db) };
-        me.db.load()?;
-        me.db.write(|map| {
-            let today_record_date = TrackingDate::from(Local::today().naive_local());
-            let has_today_records = map.contains_key(&today_record_date.0);
-
-            if !has_today_records {
-                map.insert(today_record_date.0, vec![]);
+    /// Opens the database stored in a given directory, creating it if there is none
+    pub fn open(dir_path: &Path, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        let journal = Journal::open(get_path_for_journal(dir_path).as_path(), cipher.clone())?;
+        let me = Self {
+            db: Box::new(create_memory_db()?),
+            dir_path: Some(dir_path.to_owned()),
+            journal: Some(journal),
+            cipher,
+            shards: Mutex::new(ShardState::default()),
+        };
+
+        me.init_files()?;
+        me.replay_journal()?;
+        Ok(me)
+    }
+
+    /// Opens the database for reading while another process might be writing to it.
+    ///
+    /// The journal is read before any of the day files: days that get compacted
+    /// in the meantime already hold the records of the journal, so none are missed
+    pub fn open_read_only(dir_path: &Path, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        let entries = read_journal(&get_path_for_journal(dir_path), cipher.as_ref())?;
+        let me = Self {
+            db: Box::new(create_memory_db()?),
+            dir_path: Some(dir_path.to_owned()),
+            journal: None,
+            cipher,
+            shards: Mutex::new(ShardState::default()),
+        };
+
+        if let Some(snapshot) = read_snapshot(&get_path_for_db(dir_path), me.cipher.as_ref())? {
+            if snapshot.header.version != SCHEMA_VERSION {
+                return Err(RecordStoreError::UnsupportedSchema(snapshot.header.version));
             }
-        })?;
+        }
+        me.apply_entries(entries)?;
         Ok(me)
     }
 
-    pub fn read_records<F>(&self, date: &NaiveDate, f: F) -> Result<(), RecordStoreError>
+    pub fn memory() -> Result<Self, RecordStoreError> {
+        Ok(Self {
+            db: Box::new(create_memory_db()?),
+            dir_path: None,
+            journal: None,
+            cipher: None,
+            shards: Mutex::new(ShardState::default()),
+        })
+    }
+
+    /// Checks the schema of `records.db`, creating it along with the directory of days
+    fn init_files(&self) -> Result<(), RecordStoreError> {
+        let dir_path = match &self.dir_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let path = get_path_for_db(dir_path);
+
+        fs::create_dir_all(get_path_for_days(dir_path))?;
+        match read_snapshot(&path, self.cipher.as_ref())? {
+            Some(snapshot) if snapshot.header.version != SCHEMA_VERSION => {
+                Err(RecordStoreError::UnsupportedSchema(snapshot.header.version))
+            },
+            Some(_) => Ok(()),
+            // Save right away so that older builds refuse to open the store instead of starting over
+            None => write_snapshot(&path, &Snapshot::default(), self.cipher.as_ref()),
+        }
+    }
+
+    /// Reads a day from its file, `None` if it has no file
+    fn read_day(&self, key: &str) -> Result<Option<Vec<ActivityRecord>>, RecordStoreError> {
+        let path = match &self.dir_path {
+            Some(dir_path) => get_path_for_day(dir_path, key),
+            None => return Ok(None),
+        };
+
+        // Days are only read once queried, so a damaged file shows up here rather than on opening
+        match read_snapshot(&path, self.cipher.as_ref()).map_err(RecordStoreError::into_corrupted)? {
+            Some(snapshot) if snapshot.header.version != SCHEMA_VERSION => {
+                Err(RecordStoreError::UnsupportedSchema(snapshot.header.version))
+            },
+            Some(mut snapshot) => Ok(Some(snapshot.days.remove(key).unwrap_or_default())),
+            None => Ok(None),
+        }
+    }
+
+    /// Brings a day into memory so that records can be added to it
+    fn load_day(&self, key: &str) -> Result<(), RecordStoreError> {
+        if self.db.read(|store| store.days.contains_key(key))? {
+            return Ok(());
+        }
+
+        let records = self.read_day(key)?.unwrap_or_default();
+        self.db.write(|store| {
+            store.days.insert(String::from(key), records);
+        })?;
+        Ok(())
+    }
+
+    /// Applies records left in the journal since the last compaction
+    /// and folds them into day files
+    fn replay_journal(&self) -> Result<(), RecordStoreError> {
+        let entries = match &self.journal {
+            Some(journal) => journal.replay()?,
+            None => return Ok(()),
+        };
+
+        if entries.is_empty() {
+            return match &self.journal {
+                // Journal has to be rewritten when encryption is switched on or off
+                Some(journal) if journal.is_encrypted() != self.cipher.is_some() => self.compact(),
+                _ => Ok(()),
+            };
+        }
+
+        for (date, _) in &entries {
+            self.lock_shards().dirty.insert(date.clone());
+        }
+        self.apply_entries(entries)?;
+        self.compact()
+    }
+
+    /// Adds journal entries to the days they belong to, keeping the days in memory
+    fn apply_entries(&self, entries: Vec<JournalEntry>) -> Result<(), RecordStoreError> {
+        for (date, record) in entries {
+            self.load_day(&date)?;
+            self.db.write(|store| {
+                let records = store.days.entry(date).or_insert_with(Vec::new);
+
+                // Day might've been saved right before the journal got truncated
+                if !records.iter().rev().any(|existing| existing == &record) {
+                    records.push(record);
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Persists changed days into their files and empties the journal.
+    ///
+    /// Only the day records were last pushed to stays in memory afterwards
+    pub fn compact(&self) -> Result<(), RecordStoreError> {
+        let dir_path = match &self.dir_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut shards = self.lock_shards();
+
+        for key in shards.dirty.iter() {
+            let path = get_path_for_day(dir_path, key);
+
+            self.db.read(|store| match store.days.get(key) {
+                Some(records) => {
+                    let mut days = HashMap::new();
+                    days.insert(key.clone(), records.clone());
+                    write_snapshot(&path, &Snapshot::new(days), self.cipher.as_ref())
+                },
+                None => Ok({}),
+            })??;
+        }
+        if let Some(journal) = &self.journal {
+            journal.truncate()?;
+        }
+
+        shards.dirty.clear();
+        let last_pushed = shards.last_pushed.clone();
+        self.db.write(|store| store.days.retain(|key, _| Some(key) == last_pushed.as_ref()))?;
+        Ok(())
+    }
+
+    /// Rewrites day files and the journal with a new key
+    pub fn write_with_key(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        if let Some(dir_path) = &self.dir_path {
+            let mut paths = vec![get_path_for_db(dir_path)];
+            paths.extend(list_day_files(dir_path)?.into_iter().map(|(_, path)| path));
+
+            for path in paths {
+                if let Some(snapshot) = read_snapshot(&path, self.cipher.as_ref())? {
+                    write_snapshot(&path, &snapshot, cipher.as_ref())?;
+                }
+            }
+        }
+
+        if let Some(journal) = &mut self.journal {
+            journal.set_cipher(cipher.clone());
+        }
+        self.cipher = cipher;
+        self.compact()
+    }
+
+    pub fn read_records<F>(&self, date: &NaiveDate, mut f: F) -> Result<(), RecordStoreError>
     where
-        F: FnOnce(&Vec<ActivityRecord>),
+        F: FnMut(&Vec<ActivityRecord>),
     {
         let date_record = TrackingDate::from(date);
 
-        self.db.read(|store| match store.get(&date_record.0) {
+        if let Some(result) = self.db.read(|store| store.days.get(&date_record.0).map(&mut f))? {
+            return Ok(result);
+        }
+
+        match self.read_day(&date_record.0)? {
             Some(data) => {
-                f(data);
+                f(&data);
                 Ok({})
-            }
+            },
             None => return Err(RecordStoreError::NoDataOnDate(date.clone())),
-        })??;
-        self.db.save().map_err(RecordStoreError::from)
+        }
     }
 
-    pub fn write_records<F>(&self, date: &NaiveDate, f: F) -> Result<(), RecordStoreError>
-    where
-        F: FnOnce(&mut Vec<ActivityRecord>),
-    {
+    pub fn append_record(&self, date: &NaiveDate, record: ActivityRecord) -> Result<(), RecordStoreError> {
         let date_record = TrackingDate::from(date);
 
-        self.db
-            .write(|store| {
-                if !store.contains_key(&date_record.0) {
-                    store.insert(date_record.0.clone(), vec![]);
-                }
+        if let Some(journal) = &self.journal {
+            journal.append(&date_record.0, &record)?;
+        }
+
+        self.load_day(&date_record.0)?;
+        self.db.write(|store| {
+            store.days.entry(date_record.0.clone()).or_insert_with(Vec::new).push(record)
+        })?;
+        {
+            let mut shards = self.lock_shards();
+
+            shards.dirty.insert(date_record.0.clone());
+            shards.last_pushed = Some(date_record.0);
+        }
+
+        match &self.journal {
+            Some(journal) if journal.len() >= COMPACTION_THRESHOLD => self.compact(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Replaces records of a date and persists the change in its file right away
+    pub fn write_records(&self, date: &NaiveDate, records: Vec<ActivityRecord>) -> Result<(), RecordStoreError> {
+        let date_record = TrackingDate::from(date);
+
+        self.db.write(|store| {
+            store.days.insert(date_record.0.clone(), records);
+        })?;
+        self.lock_shards().dirty.insert(date_record.0);
+        self.compact()
+    }
 
-                match store.get_mut(&date_record.0) {
-                    Some(records) => f(records),
-                    None => unreachable!(),
-                };
-            })
-            .map_err(RecordStoreError::from)
+    /// Drops a date with all its records and removes its file right away
+    pub fn drop_records(&self, date: &NaiveDate) -> Result<(), RecordStoreError> {
+        let date_record = TrackingDate::from(date);
+
+        self.db.write(|store| {
+            store.days.remove(&date_record.0);
+        })?;
+        self.lock_shards().dirty.remove(&date_record.0);
+
+        if let Some(dir_path) = &self.dir_path {
+            let path = get_path_for_day(dir_path, &date_record.0);
+
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        self.compact()
     }
 
     pub fn get_available_dates(&self) -> Result<Vec<NaiveDate>, RecordStoreError> {
-        self.db.read(|store| {
-            let foo: Vec<NaiveDate> = store
-                .keys()
-                .into_iter()
-                .map(|key| TrackingDate(key.clone()))
-                .map(NaiveDate::from)
-                .collect();
-
-            Ok(foo)
-        })?
+        let mut dates: Vec<NaiveDate> = self.db.read(|store| {
+            store.days
+                .iter()
+                .filter(|(_, records)| !records.is_empty())
+                .filter_map(|(key, _)| TrackingDate(key.clone()).parse())
+                .collect()
+        })?;
+
+        if let Some(dir_path) = &self.dir_path {
+            dates.extend(list_day_files(dir_path)?.into_iter().map(|(date, _)| date));
+        }
+        dates.sort();
+        dates.dedup();
+        Ok(dates)
+    }
+
+    fn lock_shards(&self) -> MutexGuard<'_, ShardState> {
+        self.shards.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Lists files of the directory of days along with the dates they hold
+pub(super) fn list_day_files(dir_path: &Path) -> Result<Vec<(NaiveDate, PathBuf)>, RecordStoreError> {
+    let days_path = get_path_for_days(dir_path);
+    let mut files: Vec<(NaiveDate, PathBuf)> = vec![];
+
+    if !days_path.exists() {
+        return Ok(files);
+    }
+
+    for entry in fs::read_dir(&days_path)? {
+        let path = entry?.path();
+
+        if path.extension().map_or(true, |extension| extension != "db") {
+            continue;
+        }
+
+        let key = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        if let Some(date) = TrackingDate(key).parse() {
+            files.push((date, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+impl RecordBackend for DB {
+    fn push_record(&self, date: &NaiveDate, record: ActivityRecord) -> Result<(), RecordStoreError> {
+        self.append_record(date, record)
+    }
+
+    fn replace_records(&self, date: &NaiveDate, records: Vec<ActivityRecord>) -> Result<(), RecordStoreError> {
+        self.write_records(date, records)
+    }
+
+    fn remove_records(&self, date: &NaiveDate) -> Result<(), RecordStoreError> {
+        self.drop_records(date)
+    }
+
+    fn query_records(&self, date: &NaiveDate) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        self.query_records_since(date, 0)
+    }
+
+    fn query_records_since(&self, date: &NaiveDate, index: usize) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        let mut result: Vec<ActivityRecord> = vec![];
+
+        self.read_records(date, |records| {
+            result = records.iter().skip(index).cloned().collect();
+        })?;
+        Ok(result)
+    }
+
+    fn query_dates(&self) -> Result<Vec<NaiveDate>, RecordStoreError> {
+        self.get_available_dates()
+    }
+
+    fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        self.write_with_key(cipher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{
+        utils::{get_path_for_day, get_path_for_journal, test_dir},
+        Archetype, ProductivityStatus,
+    };
+    use std::{
+        collections::BTreeSet,
+        io::Write,
+        path::Path,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    fn open(dir: &Path) -> DB {
+        DB::open(dir, None).unwrap()
+    }
+
+    fn record(start_secs: u64) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_secs(start_secs),
+                UNIX_EPOCH + Duration::from_secs(start_secs + 30),
+            ),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::AFK,
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn tracking_date_respects_day_start() {
+        let late_night = SystemTime::from(Local.ymd(2020, 6, 2).and_hms(3, 30, 0));
+        let morning = SystemTime::from(Local.ymd(2020, 6, 2).and_hms(4, 30, 0));
+
+        assert_eq!(TrackingDate::from_time(&late_night, 0), TrackingDate(String::from("2020-06-02")));
+        assert_eq!(TrackingDate::from_time(&late_night, 4), TrackingDate(String::from("2020-06-01")));
+        assert_eq!(TrackingDate::from_time(&morning, 4), TrackingDate(String::from("2020-06-02")));
+        assert_eq!(
+            TrackingDate(String::from("2020-06-02")).start(4),
+            SystemTime::from(Local.ymd(2020, 6, 2).and_hms(4, 0, 0))
+        );
+    }
+
+    #[test]
+    fn push_does_not_rewrite_snapshot() {
+        let dir = test_dir("db_append");
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let db = open(&dir);
+
+        db.push_record(&date, record(10)).unwrap();
+        db.push_record(&date, record(40)).unwrap();
+        db.query_records(&date).unwrap();
+
+        assert_eq!(get_path_for_day(&dir, "2020-06-01").exists(), false);
+
+        drop(db);
+        let reopened = open(&dir);
+        assert_eq!(reopened.query_records(&date).unwrap(), vec![record(10), record(40)]);
+        assert_eq!(reopened.journal.as_ref().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn replaced_records_survive_reopening() {
+        let dir = test_dir("db_replace");
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let db = open(&dir);
+
+        db.push_record(&date, record(40)).unwrap();
+        db.replace_records(&date, vec![record(10), record(40)]).unwrap();
+        db.push_record(&date, record(70)).unwrap();
+
+        drop(db);
+        let reopened = open(&dir);
+        assert_eq!(reopened.query_records(&date).unwrap(), vec![record(10), record(40), record(70)]);
+    }
+
+    #[test]
+    fn days_are_read_once_queried() {
+        let dir = test_dir("db_lazy");
+        let old_date = NaiveDate::from_ymd(2020, 6, 1);
+        let date = NaiveDate::from_ymd(2020, 6, 2);
+        let db = open(&dir);
+
+        db.replace_records(&old_date, vec![record(10), record(40)]).unwrap();
+        db.push_record(&date, record(100_000)).unwrap();
+        db.compact().unwrap();
+
+        assert_eq!(db.db.read(|store| store.days.keys().cloned().collect::<Vec<String>>()).unwrap(), vec![
+            String::from("2020-06-02"),
+        ]);
+        assert_eq!(db.query_dates().unwrap(), vec![old_date, date]);
+        assert_eq!(db.query_records_since(&old_date, 1).unwrap(), vec![record(40)]);
+
+        db.push_record(&date, record(100_030)).unwrap();
+        assert_eq!(db.query_records_since(&date, 1).unwrap(), vec![record(100_030)]);
+        assert_eq!(db.query_records_since(&date, 2).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn encrypted_store_needs_key() {
+        let dir = test_dir("db_encrypted");
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let (_, cipher) = crate::record_store::crypto::tests::test_params("secret");
+        let window = ActivityRecord {
+            archetype: Archetype::ActiveWindow(String::from("inbox"), String::from("mail"), String::from("Mail")),
+            ..record(70)
+        };
+        let mut db = open(&dir);
+
+        db.push_record(&date, record(10)).unwrap();
+        db.rekey(Some(cipher.clone())).unwrap();
+        db.push_record(&date, window.clone()).unwrap();
+        drop(db);
+
+        for path in &[get_path_for_day(&dir, "2020-06-01"), get_path_for_journal(&dir)] {
+            let bytes = std::fs::read(path).unwrap();
+            assert_eq!(bytes.windows(5).any(|part| part == b"inbox"), false);
+        }
+        match DB::open(&dir, None) {
+            Err(RecordStoreError::EncryptedStore) => {},
+            _ => panic!("encrypted store must not be opened without a key"),
+        }
+
+        let reopened = DB::open(&dir, Some(cipher)).unwrap();
+        assert_eq!(reopened.query_records(&date).unwrap(), vec![record(10), window]);
+    }
+
+    #[test]
+    fn records_after_torn_entry_survive() {
+        let dir = test_dir("db_torn");
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        drop(open(&dir));
+
+        // Crash in the middle of the first entry after a truncation
+        let mut journal = fs::OpenOptions::new().append(true).open(get_path_for_journal(&dir)).unwrap();
+        journal.write_all(&[64, 0, 0, 0, 1, 2, 3]).unwrap();
+        drop(journal);
+
+        let db = open(&dir);
+        db.push_record(&date, record(40)).unwrap();
+
+        drop(db);
+        assert_eq!(open(&dir).query_records(&date).unwrap(), vec![record(40)]);
     }
 }
@@ -0,0 +1,343 @@
+use super::{
+    crypto::{open_file, seal_file, Cipher},
+    utils::{get_path_for_audit_log, write_atomically, RecordStoreError},
+    ActivityRecord, ProductivityStatus,
+};
+use chrono::{DateTime, Local};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// App name and class of records made by hand
+pub const MANUAL_APP_NAME: &'static str = "manual";
+pub const MANUAL_APP_CLASS: &'static str = "Manual";
+
+/// Change made to stored records by hand
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Edit {
+    /// Sets productivity and activity of the record tracked at a moment
+    SetProductivity(SystemTime, ProductivityStatus),
+    /// Cuts the record tracked at a moment in two at that moment
+    Split(SystemTime),
+    /// Joins the record tracked at a moment with the one right after it,
+    /// the joined record keeps the window and productivity of the first one
+    MergeNext(SystemTime),
+    /// Drops records within a time range, clipping those that cross its bounds
+    Delete(SystemTime, SystemTime),
+    /// Adds a record made by hand in place of the records it overlaps
+    Insert(ActivityRecord),
+    /// Adds tags to records within a time range, splitting those that cross its bounds
+    AddTags(SystemTime, SystemTime, BTreeSet<String>),
+    /// Removes tags from records within a time range, splitting those that cross its bounds
+    RemoveTags(SystemTime, SystemTime, BTreeSet<String>),
+    /// Sets or clears the note of records within a time range, splitting those that cross its bounds
+    SetNote(SystemTime, SystemTime, Option<String>),
+    /// Undoes the audit entry with a given number
+    Revert(usize),
+}
+
+fn format_time(time: &SystemTime) -> String {
+    DateTime::<Local>::from(*time).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+impl std::fmt::Display for Edit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Edit::SetProductivity(at, productivity) => {
+                let productivity = match productivity {
+                    ProductivityStatus::Productive(activity) => format!("productive ({})", activity),
+                    ProductivityStatus::Leisure(activity) => format!("leisure ({})", activity),
+                    ProductivityStatus::Neutral => String::from("neutral"),
+                };
+
+                write!(f, "set productivity of record at {} to {}", format_time(at), productivity)
+            }
+            Edit::Split(at) => write!(f, "split record at {}", format_time(at)),
+            Edit::MergeNext(at) => write!(f, "merge record at {} with the next one", format_time(at)),
+            Edit::Delete(from, to) => write!(f, "delete records from {} to {}", format_time(from), format_time(to)),
+            Edit::Insert(record) => write!(
+                f, "insert record from {} to {}",
+                format_time(&record.time_range.0), format_time(&record.time_range.1)
+            ),
+            Edit::AddTags(from, to, tags) => write!(
+                f, "tag records from {} to {} with {}", format_time(from), format_time(to), join_tags(tags)
+            ),
+            Edit::RemoveTags(from, to, tags) => write!(
+                f, "remove tags {} from records from {} to {}", join_tags(tags), format_time(from), format_time(to)
+            ),
+            Edit::SetNote(from, to, Some(note)) => write!(
+                f, "note {:?} on records from {} to {}", note, format_time(from), format_time(to)
+            ),
+            Edit::SetNote(from, to, None) => write!(
+                f, "clear notes of records from {} to {}", format_time(from), format_time(to)
+            ),
+            Edit::Revert(number) => write!(f, "revert edit #{}", number),
+        }
+    }
+}
+
+fn join_tags(tags: &BTreeSet<String>) -> String {
+    tags.iter().cloned().collect::<Vec<String>>().join(", ")
+}
+
+/// Finds the record tracked at a moment
+fn position_at(records: &[ActivityRecord], at: &SystemTime) -> Result<usize, RecordStoreError> {
+    records.iter()
+        .position(|record| record.time_range.0 <= *at && *at < record.time_range.1)
+        .ok_or_else(|| RecordStoreError::InvalidEdit(format!("No record at {}", format_time(at))))
+}
+
+/// Applies an edit to records of a tracking date ordered by start time.
+///
+/// `Revert` is applied by the store, which holds the audit log
+pub fn apply_edit(records: &[ActivityRecord], edit: &Edit) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+    let mut edited = records.to_vec();
+
+    match edit {
+        Edit::SetProductivity(at, productivity) => {
+            let position = position_at(records, at)?;
+
+            edited[position].productivity = productivity.clone();
+        },
+        Edit::Split(at) => {
+            let position = position_at(records, at)?;
+            let record = &records[position];
+
+            if record.time_range.0 == *at {
+                return Err(RecordStoreError::InvalidEdit(String::from("Records can't be split at their start")));
+            }
+            edited.splice(position..=position, record.clip(record.time_range.0, *at).into_iter()
+                .chain(record.clip(*at, record.time_range.1)));
+        },
+        Edit::MergeNext(at) => {
+            let position = position_at(records, at)?;
+            let next = records.get(position + 1)
+                .filter(|next| next.time_range.0 == records[position].time_range.1)
+                .ok_or_else(|| RecordStoreError::InvalidEdit(String::from("Record is not followed right away by another one")))?;
+
+            edited[position].time_range.1 = next.time_range.1;
+            edited.remove(position + 1);
+        },
+        Edit::Delete(from, to) => {
+            edited = forget_range(records, from, to, ForgetMode::Remove);
+        },
+        Edit::Insert(record) => {
+            edited = apply_edit(records, &Edit::Delete(record.time_range.0, record.time_range.1))?;
+            edited.push(record.clone());
+            edited.sort_by_key(|record| record.time_range.0);
+        },
+        Edit::AddTags(from, to, tags) => {
+            if tags.iter().any(|tag| !is_valid_tag(tag)) {
+                return Err(RecordStoreError::InvalidEdit(String::from("Tags must not be empty or contain commas")));
+            }
+            edited = edit_range(records, from, to, |record| record.tags.extend(tags.iter().cloned()));
+        },
+        Edit::RemoveTags(from, to, tags) => {
+            edited = edit_range(records, from, to, |record| record.tags.retain(|tag| !tags.contains(tag)));
+        },
+        Edit::SetNote(from, to, note) => {
+            edited = edit_range(records, from, to, |record| record.note = note.clone());
+        },
+        Edit::Revert(_) => return Err(RecordStoreError::InvalidEdit(String::from("Reverts are applied by the store"))),
+    }
+
+    Ok(edited)
+}
+
+/// What happens to forgotten records, see `RecordStore::forget`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ForgetMode {
+    /// Records are dropped, as if nothing was tracked
+    Remove,
+    /// Window titles are dropped, the time still counts towards the apps and activities
+    Anonymize,
+}
+
+/// Forgets the parts of records within a time range, clipping those that cross its bounds
+pub fn forget_range(records: &[ActivityRecord], from: &SystemTime, to: &SystemTime, mode: ForgetMode) -> Vec<ActivityRecord> {
+    match mode {
+        ForgetMode::Remove => records.iter()
+            .flat_map(|record| record.clip(record.time_range.0, *from).into_iter()
+                .chain(record.clip(*to, record.time_range.1)))
+            .collect(),
+        ForgetMode::Anonymize => edit_range(records, from, to, |record| {
+            record.archetype = record.archetype.clone().without_title();
+        }),
+    }
+}
+
+/// Tags are listed comma-separated in exports, so they can't hold commas
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.trim().is_empty() && !tag.contains(',')
+}
+
+/// Changes the parts of records within a time range, records the change leaves as they are stay whole
+fn edit_range<F>(records: &[ActivityRecord], from: &SystemTime, to: &SystemTime, change: F) -> Vec<ActivityRecord>
+where
+    F: Fn(&mut ActivityRecord),
+{
+    records.iter()
+        .flat_map(|record| {
+            let within = match record.clip(*from, *to) {
+                Some(within) => within,
+                None => return vec![record.clone()],
+            };
+            let mut changed = within.clone();
+
+            change(&mut changed);
+            if changed == within {
+                return vec![record.clone()];
+            }
+            record.clip(record.time_range.0, *from).into_iter()
+                .chain(Some(changed))
+                .chain(record.clip(*to, record.time_range.1))
+                .collect()
+        })
+        .collect()
+}
+
+/// Records that are in `records` but not in `others`, counting duplicates
+pub fn subtract(records: &[ActivityRecord], others: &[ActivityRecord]) -> Vec<ActivityRecord> {
+    let mut others = others.to_vec();
+
+    records.iter()
+        .filter(|record| match others.iter().position(|other| other == *record) {
+            Some(position) => {
+                others.remove(position);
+                false
+            },
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Single edit of the records of a tracking date
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the edit was made
+    pub made_at: SystemTime,
+    /// Tracking date as `YYYY-MM-DD`
+    pub date: String,
+    pub edit: Edit,
+    /// Records as they were before the edit
+    pub removed: Vec<ActivityRecord>,
+    /// Records the edit put in their place
+    pub added: Vec<ActivityRecord>,
+}
+
+/// Every edit made by hand, so that records as they were tracked can be brought back.
+///
+/// Kept as JSON in `audit.log` of the data directory, sealed like other store files
+/// when the store is encrypted. Memory stores keep no log
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    cipher: Option<Cipher>,
+}
+
+impl AuditLog {
+    pub fn in_dir(dir_path: &Path, cipher: Option<Cipher>) -> Self {
+        Self {
+            path: Some(get_path_for_audit_log(dir_path)),
+            cipher,
+        }
+    }
+
+    pub fn memory() -> Self {
+        Self {
+            path: None,
+            cipher: None,
+        }
+    }
+
+    /// Gets all entries, oldest first. Entries are numbered from 1 in this order
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, RecordStoreError> {
+        match &self.path {
+            Some(path) if path.exists() => {
+                Ok(serde_json::from_slice(&open_file(fs::read(path)?, self.cipher.as_ref())?)?)
+            },
+            _ => Ok(vec![]),
+        }
+    }
+
+    pub fn append(&self, new_entries: Vec<AuditEntry>) -> Result<(), RecordStoreError> {
+        let mut entries = self.entries()?;
+
+        entries.extend(new_entries);
+        self.save(&entries)
+    }
+
+    fn save(&self, entries: &[AuditEntry]) -> Result<(), RecordStoreError> {
+        if let Some(path) = &self.path {
+            write_atomically(path, &seal_file(serde_json::to_vec(entries)?, self.cipher.as_ref()))?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the log with a new key
+    pub fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        let entries = self.entries()?;
+
+        self.cipher = cipher;
+        self.save(&entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::Archetype;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn record(start: u64, end: u64, title: &str) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (at(start), at(end)),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::ActiveWindow(String::from(title), String::from("code"), String::from("Code")),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn edits_records_of_a_day() {
+        let records = vec![record(0, 100, "a"), record(100, 200, "b"), record(300, 400, "c")];
+        let coding = ProductivityStatus::Productive(String::from("coding"));
+
+        let split = apply_edit(&records, &Edit::Split(at(50))).unwrap();
+        assert_eq!(split[..2], [record(0, 50, "a"), record(50, 100, "a")]);
+        assert_eq!(apply_edit(&split, &Edit::MergeNext(at(0))).unwrap(), records);
+        assert_eq!(apply_edit(&records, &Edit::MergeNext(at(150))).is_err(), true);
+
+        let classified = apply_edit(&records, &Edit::SetProductivity(at(150), coding.clone())).unwrap();
+        assert_eq!(classified[1].productivity, coding);
+        assert_eq!(apply_edit(&records, &Edit::SetProductivity(at(250), coding)).is_err(), true);
+
+        assert_eq!(
+            apply_edit(&records, &Edit::Delete(at(50), at(350))).unwrap(),
+            vec![record(0, 50, "a"), record(350, 400, "c")]
+        );
+        assert_eq!(
+            apply_edit(&records, &Edit::Insert(record(150, 320, "meeting"))).unwrap(),
+            vec![record(0, 100, "a"), record(100, 150, "b"), record(150, 320, "meeting"), record(320, 400, "c")]
+        );
+        assert_eq!(subtract(&split, &records), vec![record(0, 50, "a"), record(50, 100, "a")]);
+
+        let tags: BTreeSet<String> = vec![String::from("incident-482")].into_iter().collect();
+        let tagged = apply_edit(&records, &Edit::AddTags(at(50), at(150), tags.clone())).unwrap();
+        assert_eq!(tagged.len(), 5);
+        assert_eq!(tagged[1], ActivityRecord { tags: tags.clone(), ..record(50, 100, "a") });
+        assert_eq!(tagged[2], ActivityRecord { tags: tags.clone(), ..record(100, 150, "b") });
+        assert_eq!(apply_edit(&tagged, &Edit::RemoveTags(at(0), at(400), tags)).unwrap()[1], record(50, 100, "a"));
+        assert_eq!(apply_edit(&records, &Edit::SetNote(at(0), at(400), None)).unwrap(), records);
+    }
+}
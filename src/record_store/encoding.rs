@@ -0,0 +1,451 @@
+//! Compact encoding of snapshots.
+//!
+//! Strings are interned into a dictionary written once before the records, which refer
+//! to them by index, so app names and titles repeated all day long are stored once.
+//! Numbers are LEB128 varints and every record start is stored relative to the end
+//! of the previous record, which is zero most of the time.
+//!
+//! Layout after the `SchemaHeader`: number of strings followed by every string
+//! as its length and UTF-8 bytes, then number of days followed by every day as
+//! the index of its date key, number of records and the records. A record is a tag
+//! byte holding its kind and productivity, start and duration in nanoseconds as
+//! zigzag varints, indexes of title, app name and class for windows, indexes
+//! of app name and class for incognito windows, which are tagged as both AFK and private, the index
+//! of the activity unless the record is neutral, the index of the machine name
+//! if the record has one, the number of tags followed by their indexes if there are any,
+//! the index of the note if there is one and the index of the project if there is one.
+//! Snapshots of v2 to v5 are read as they are, their records lack the newer fields.
+use super::{
+    migrations::SchemaHeader,
+    utils::{RecordStoreError, Snapshot},
+    ActivityRecord, Archetype, ProductivityStatus,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const TAG_AFK: u8 = 0b001;
+const TAG_PRODUCTIVE: u8 = 0b010;
+const TAG_LEISURE: u8 = 0b100;
+const TAG_MACHINE: u8 = 0b1000;
+const TAG_TAGS: u8 = 0b1_0000;
+const TAG_NOTE: u8 = 0b10_0000;
+const TAG_PROJECT: u8 = 0b100_0000;
+const TAG_PRIVATE: u8 = 0b1000_0000;
+
+/// Strings of a snapshot in the order they're first seen
+#[derive(Default)]
+struct Dictionary<'a> {
+    indexes: HashMap<&'a str, u64>,
+    strings: Vec<&'a str>,
+}
+
+impl<'a> Dictionary<'a> {
+    fn intern(&mut self, string: &'a str) -> u64 {
+        let strings = &mut self.strings;
+
+        *self.indexes.entry(string).or_insert_with(|| {
+            strings.push(string);
+            (strings.len() - 1) as u64
+        })
+    }
+}
+
+pub fn encode_snapshot(snapshot: &Snapshot) -> Vec<u8> {
+    let mut dictionary = Dictionary::default();
+    let mut body: Vec<u8> = vec![];
+
+    // Days are sorted so that encoding the same snapshot twice gives the same bytes
+    let mut days: Vec<(&String, &Vec<ActivityRecord>)> = snapshot.days.iter().collect();
+    days.sort_by_key(|(date, _)| *date);
+
+    put_varint(&mut body, days.len() as u64);
+    for (date, records) in days {
+        put_varint(&mut body, dictionary.intern(date));
+        put_varint(&mut body, records.len() as u64);
+
+        let mut previous_end = 0;
+        for record in records {
+            let start = to_nanos(&record.time_range.0);
+            let end = to_nanos(&record.time_range.1);
+
+            body.push(tag(record));
+            put_signed_varint(&mut body, start - previous_end);
+            put_signed_varint(&mut body, end - start);
+            previous_end = end;
+
+            match &record.archetype {
+                Archetype::ActiveWindow(title, name, class) => {
+                    put_varint(&mut body, dictionary.intern(title));
+                    put_varint(&mut body, dictionary.intern(name));
+                    put_varint(&mut body, dictionary.intern(class));
+                },
+                Archetype::Incognito(name, class) => {
+                    put_varint(&mut body, dictionary.intern(name));
+                    put_varint(&mut body, dictionary.intern(class));
+                },
+                Archetype::AFK | Archetype::Private => {},
+            }
+            match &record.productivity {
+                ProductivityStatus::Productive(name) | ProductivityStatus::Leisure(name) => {
+                    put_varint(&mut body, dictionary.intern(name))
+                },
+                ProductivityStatus::Neutral => {},
+            }
+            if let Some(machine) = &record.machine {
+                put_varint(&mut body, dictionary.intern(machine));
+            }
+            if !record.tags.is_empty() {
+                put_varint(&mut body, record.tags.len() as u64);
+                for tag in &record.tags {
+                    put_varint(&mut body, dictionary.intern(tag));
+                }
+            }
+            if let Some(note) = &record.note {
+                put_varint(&mut body, dictionary.intern(note));
+            }
+            if let Some(project) = &record.project {
+                put_varint(&mut body, dictionary.intern(project));
+            }
+        }
+    }
+
+    let mut bytes = snapshot.header.encode();
+    put_varint(&mut bytes, dictionary.strings.len() as u64);
+    for string in &dictionary.strings {
+        put_varint(&mut bytes, string.len() as u64);
+        bytes.extend(string.as_bytes());
+    }
+    bytes.extend(body);
+    bytes
+}
+
+pub fn decode_snapshot(bytes: &[u8]) -> Result<Snapshot, RecordStoreError> {
+    let mut days: HashMap<String, Vec<ActivityRecord>> = HashMap::new();
+    let header = decode_days_with(bytes, |date, records| {
+        days.insert(date, records);
+    })?;
+
+    Ok(Snapshot { header, days })
+}
+
+/// Decodes a snapshot one day at a time, so that days read before an error are kept
+pub fn decode_days_with<F>(bytes: &[u8], mut on_day: F) -> Result<SchemaHeader, RecordStoreError>
+where
+    F: FnMut(String, Vec<ActivityRecord>),
+{
+    if bytes.len() < SchemaHeader::SIZE {
+        return Err(malformed("header is incomplete"));
+    }
+
+    let header: SchemaHeader = bincode::deserialize(&bytes[..SchemaHeader::SIZE])?;
+    let mut reader = Reader { bytes, offset: SchemaHeader::SIZE };
+    let mut strings: Vec<String> = vec![];
+
+    for _ in 0..reader.varint()? {
+        strings.push(reader.string()?);
+    }
+    let string = |index: u64| -> Result<String, RecordStoreError> {
+        strings.get(index as usize).cloned().ok_or_else(|| malformed("string index is out of range"))
+    };
+
+    let day_count = reader.varint()?;
+    for day_index in 0..day_count {
+        let day = (|| -> Result<(String, Vec<ActivityRecord>), RecordStoreError> {
+            let date = string(reader.varint()?)?;
+            let mut records: Vec<ActivityRecord> = vec![];
+            let mut previous_end = 0;
+
+            for _ in 0..reader.varint()? {
+                let tag = reader.byte()?;
+                let start = add_nanos(previous_end, reader.signed_varint()?)?;
+                let end = add_nanos(start, reader.signed_varint()?)?;
+                previous_end = end;
+
+                let archetype = match tag & (TAG_AFK | TAG_PRIVATE) {
+                    TAG_AFK => Archetype::AFK,
+                    TAG_PRIVATE => Archetype::Private,
+                    0 => {
+                        let title = string(reader.varint()?)?;
+                        let name = string(reader.varint()?)?;
+
+                        Archetype::ActiveWindow(title, name, string(reader.varint()?)?)
+                    },
+                    _ => {
+                        let name = string(reader.varint()?)?;
+
+                        Archetype::Incognito(name, string(reader.varint()?)?)
+                    },
+                };
+                let productivity = match tag & (TAG_PRODUCTIVE | TAG_LEISURE) {
+                    0 => ProductivityStatus::Neutral,
+                    TAG_PRODUCTIVE => ProductivityStatus::Productive(string(reader.varint()?)?),
+                    TAG_LEISURE => ProductivityStatus::Leisure(string(reader.varint()?)?),
+                    _ => return Err(malformed("record tag is invalid")),
+                };
+                let machine = match tag & TAG_MACHINE {
+                    0 => None,
+                    _ => Some(string(reader.varint()?)?),
+                };
+                let mut tags = BTreeSet::new();
+                if tag & TAG_TAGS != 0 {
+                    for _ in 0..reader.varint()? {
+                        tags.insert(string(reader.varint()?)?);
+                    }
+                }
+                let note = match tag & TAG_NOTE {
+                    0 => None,
+                    _ => Some(string(reader.varint()?)?),
+                };
+                let project = match tag & TAG_PROJECT {
+                    0 => None,
+                    _ => Some(string(reader.varint()?)?),
+                };
+
+                records.push(ActivityRecord {
+                    time_range: (from_nanos(start), from_nanos(end)),
+                    productivity,
+                    archetype,
+                    machine,
+                    tags,
+                    note,
+                    project,
+                });
+            }
+            Ok((date, records))
+        })();
+
+        match day {
+            Ok((date, records)) => on_day(date, records),
+            Err(err) => {
+                let reason = format!("{} of {} days could not be read: {}", day_count - day_index, day_count, err);
+                return Err(malformed(&reason));
+            }
+        }
+    }
+
+    Ok(header)
+}
+
+fn tag(record: &ActivityRecord) -> u8 {
+    let kind = match record.archetype {
+        Archetype::AFK => TAG_AFK,
+        Archetype::Private => TAG_PRIVATE,
+        Archetype::Incognito(_, _) => TAG_AFK | TAG_PRIVATE,
+        Archetype::ActiveWindow(_, _, _) => 0,
+    };
+    let productivity = match record.productivity {
+        ProductivityStatus::Productive(_) => TAG_PRODUCTIVE,
+        ProductivityStatus::Leisure(_) => TAG_LEISURE,
+        ProductivityStatus::Neutral => 0,
+    };
+    let machine = match record.machine {
+        Some(_) => TAG_MACHINE,
+        None => 0,
+    };
+    let tags = match record.tags.is_empty() {
+        true => 0,
+        false => TAG_TAGS,
+    };
+    let note = match record.note {
+        Some(_) => TAG_NOTE,
+        None => 0,
+    };
+    let project = match record.project {
+        Some(_) => TAG_PROJECT,
+        None => 0,
+    };
+
+    kind | productivity | machine | tags | note | project
+}
+
+pub fn malformed(reason: &str) -> RecordStoreError {
+    RecordStoreError::EncodingFailed(Box::new(bincode::ErrorKind::Custom(String::from(reason))))
+}
+
+fn to_nanos(time: &SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+fn add_nanos(time: i64, delta: i64) -> Result<i64, RecordStoreError> {
+    time.checked_add(delta).ok_or_else(|| malformed("time is out of range"))
+}
+
+fn from_nanos(nanos: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_nanos(std::cmp::max(nanos, 0) as u64)
+}
+
+fn put_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn put_signed_varint(bytes: &mut Vec<u8>, value: i64) {
+    put_varint(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, RecordStoreError> {
+        let byte = *self.bytes.get(self.offset).ok_or_else(|| malformed("unexpected end of data"))?;
+
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self) -> Result<u64, RecordStoreError> {
+        let mut value: u64 = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(malformed("number is too long"))
+    }
+
+    fn signed_varint(&mut self) -> Result<i64, RecordStoreError> {
+        let value = self.varint()?;
+
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn string(&mut self) -> Result<String, RecordStoreError> {
+        let length = self.varint()? as usize;
+
+        if length > self.bytes.len() - self.offset {
+            return Err(malformed("unexpected end of data"));
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + length];
+        self.offset += length;
+        String::from_utf8(bytes.to_vec()).map_err(|_| malformed("string is not valid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn record(start_secs: u64, archetype: Archetype, productivity: ProductivityStatus) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_nanos(start_secs * 1_000_000_000 + 123),
+                UNIX_EPOCH + Duration::from_secs(start_secs + 30),
+            ),
+            productivity,
+            archetype,
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    fn window(title: &str, name: &str) -> Archetype {
+        Archetype::ActiveWindow(String::from(title), String::from(name), name.to_uppercase())
+    }
+
+    #[test]
+    fn snapshot_survives_encoding() {
+        let mut days: HashMap<String, Vec<ActivityRecord>> = HashMap::new();
+        let tagged = ActivityRecord {
+            tags: vec![String::from("incident-482"), String::from("on-call")].into_iter().collect(),
+            note: Some(String::from("pairing with Sam")),
+            project: Some(String::from("client-x")),
+            ..record(140, window("incident-482", "Navigator"), ProductivityStatus::Neutral)
+        };
+        days.insert(String::from("2020-06-01"), vec![
+            record(100, window("main.rs", "code-oss"), ProductivityStatus::Productive(String::from("coding"))),
+            tagged,
+            record(130, Archetype::AFK, ProductivityStatus::Neutral),
+            record(135, Archetype::Private, ProductivityStatus::Neutral),
+            record(138, Archetype::Incognito(String::from("code-oss"), String::from("Code")), ProductivityStatus::Productive(String::from("coding"))),
+            record(120, window("Inbox", "Navigator"), ProductivityStatus::Leisure(String::from("mail"))),
+        ]);
+        days.insert(String::from("2020-06-02"), vec![]);
+        let snapshot = Snapshot::new(days);
+        let bytes = encode_snapshot(&snapshot);
+
+        assert_eq!(decode_snapshot(&bytes).unwrap().days, snapshot.days);
+
+        let mut read_days: Vec<String> = vec![];
+        let result = decode_days_with(&bytes[..bytes.len() - 1], |date, _| read_days.push(date));
+        assert_eq!(result.is_err(), true);
+        assert_eq!(read_days, vec![String::from("2020-06-01")]);
+    }
+
+    /// Synthetic days of tracking: a record every 30 seconds for 10 hours a day,
+    /// switching between a few apps with a few hundred distinct titles
+    fn synthetic_days(count: u64) -> Snapshot {
+        let apps = ["code-oss", "Navigator", "Alacritty", "Slack", "evince"];
+        let mut days: HashMap<String, Vec<ActivityRecord>> = HashMap::new();
+
+        for day in 0..count {
+            let day_start = 1_577_869_200 + day * 86_400;
+            let records = (0..1200u64)
+                .map(|index| {
+                    let app = apps[(index / 7 % apps.len() as u64) as usize];
+                    let title = format!("{} - document {}", app, (day * 7 + index / 40) % 300);
+                    let productivity = match app {
+                        "Slack" => ProductivityStatus::Leisure(String::from("chat")),
+                        _ => ProductivityStatus::Productive(String::from("work")),
+                    };
+
+                    record(day_start + index * 30, window(&title, app), productivity)
+                })
+                .collect();
+
+            days.insert(format!("day-{}", day), records);
+        }
+        Snapshot::new(days)
+    }
+
+    #[test]
+    fn compact_encoding_is_smaller() {
+        let snapshot = synthetic_days(30);
+        let plain = bincode::serialize(&snapshot).unwrap();
+        let compact = encode_snapshot(&snapshot);
+
+        assert_eq!(compact.len() * 5 < plain.len(), true);
+        assert_eq!(decode_snapshot(&compact).unwrap().days, snapshot.days);
+    }
+
+    #[test]
+    #[ignore]
+    fn encoding_benchmark() {
+        let snapshot = synthetic_days(365);
+
+        let started = Instant::now();
+        let plain = bincode::serialize(&snapshot).unwrap();
+        let plain_encoded_in = started.elapsed();
+        let started = Instant::now();
+        bincode::deserialize::<Snapshot>(&plain).unwrap();
+        let plain_loaded_in = started.elapsed();
+
+        let started = Instant::now();
+        let compact = encode_snapshot(&snapshot);
+        let compact_encoded_in = started.elapsed();
+        let started = Instant::now();
+        decode_snapshot(&compact).unwrap();
+        let compact_loaded_in = started.elapsed();
+
+        println!("bincode: {} bytes, encoded in {:?}, loaded in {:?}", plain.len(), plain_encoded_in, plain_loaded_in);
+        println!("compact: {} bytes, encoded in {:?}, loaded in {:?}", compact.len(), compact_encoded_in, compact_loaded_in);
+        assert_eq!(compact.len() * 5 < plain.len(), true);
+    }
+}
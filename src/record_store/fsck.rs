@@ -0,0 +1,434 @@
+//! Consistency checks and recovery of damaged record stores.
+//!
+//! Store files are read directly rather than through a backend,
+//! so that days stored before the damaged part of a file can still be recovered.
+use super::{
+    backend::RecordBackend,
+    config::{RecordStoreConfig, StoreBackend},
+    crypto::{open_file, Cipher},
+    db::{list_day_files, TrackingDate},
+    encoding::decode_days_with,
+    journal::{decode_entries_until, JournalEntry},
+    lock::StoreLock,
+    migrations::{upgrade_file_store, write_days, SchemaHeader, SCHEMA_VERSION},
+    search::SearchIndex,
+    sqlite::SqliteDB,
+    utils::{get_path_for_days, get_path_for_db, get_path_for_journal, get_path_for_sqlite, RecordStoreError},
+    ActivityRecord,
+};
+use chrono::NaiveDate;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Something wrong found in a record store
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Problem {
+    /// File or a part of it could not be read
+    Unreadable(PathBuf, String),
+    /// Key of a day that is not a `YYYY-MM-DD` date
+    InvalidDate(String),
+    /// Record that ends before it starts, with its position in the day
+    ReversedRange(NaiveDate, usize),
+    /// Record that starts before the previous one
+    Unordered(NaiveDate, usize),
+    /// Record that starts before the previous one ends
+    Overlapping(NaiveDate, usize),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Unreadable(path, reason) => write!(f, "{}: {}", path.display(), reason),
+            Problem::InvalidDate(key) => write!(f, "Day \"{}\" is not a valid date", key),
+            Problem::ReversedRange(date, index) => write!(f, "{} record #{} ends before it starts", date, index + 1),
+            Problem::Unordered(date, index) => {
+                write!(f, "{} record #{} starts before the previous one", date, index + 1)
+            }
+            Problem::Overlapping(date, index) => {
+                write!(f, "{} record #{} overlaps the previous one", date, index + 1)
+            }
+        }
+    }
+}
+
+/// Outcome of `FsckReport::salvage`
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct SalvageSummary {
+    pub days: usize,
+    pub records: usize,
+    /// Records left out for overlapping others or ending before they start
+    pub dropped: usize,
+    /// Where the damaged files were moved
+    pub backups: Vec<PathBuf>,
+}
+
+/// Readable contents of a record store along with everything wrong with it
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub days: BTreeMap<NaiveDate, Vec<ActivityRecord>>,
+    pub problems: Vec<Problem>,
+}
+
+impl FsckReport {
+    /// Reads as much of the configured store as possible and validates its records.
+    ///
+    /// Stores of older schema versions are upgraded first, the same way opening them would,
+    /// unless the store is being written to by another process
+    pub fn check(config: &RecordStoreConfig, cipher: Option<&Cipher>) -> Result<Self, RecordStoreError> {
+        let mut report = Self::default();
+        let mut raw_days: BTreeMap<String, Vec<ActivityRecord>> = BTreeMap::new();
+        let data_path = config.data_dir.as_path();
+        // Files are only upgraded when no other process writes to the store, otherwise they're read as is
+        let lock = if data_path.is_dir() { StoreLock::try_acquire(data_path)? } else { None };
+        let writable = lock.is_some();
+
+        match config.backend {
+            StoreBackend::Rustbreak => report.read_file_store(data_path, cipher, writable, &mut raw_days)?,
+            StoreBackend::Sqlite => {
+                report.read_sqlite(&get_path_for_sqlite(data_path), cipher, writable, &mut raw_days)?
+            },
+        };
+
+        for (key, records) in raw_days {
+            match TrackingDate(key.clone()).parse() {
+                Some(date) => {
+                    report.problems.extend(validate(&date, &records));
+                    report.days.insert(date, records);
+                },
+                None => report.problems.push(Problem::InvalidDate(key)),
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.days.values().map(Vec::len).sum()
+    }
+
+    /// Replaces the store with its readable days, dropping records that overlap others.
+    ///
+    /// Damaged files are kept next to the new ones as `*.corrupt-{timestamp}`
+    pub fn salvage(&self, config: &RecordStoreConfig, cipher: Option<&Cipher>) -> Result<SalvageSummary, RecordStoreError> {
+        let _lock = StoreLock::acquire(&config.data_dir)?;
+        let mut summary = SalvageSummary::default();
+        let mut days: Vec<(NaiveDate, Vec<ActivityRecord>)> = vec![];
+
+        for (date, records) in &self.days {
+            let (kept, dropped) = repair(records.clone());
+
+            summary.dropped += dropped;
+            summary.records += kept.len();
+            if !kept.is_empty() {
+                days.push((*date, kept));
+            }
+        }
+        summary.days = days.len();
+
+        let data_path = config.data_dir.as_path();
+        match config.backend {
+            StoreBackend::Rustbreak => {
+                let days = days.into_iter()
+                    .map(|(date, records)| (TrackingDate::from(date).0, records))
+                    .collect::<HashMap<String, Vec<ActivityRecord>>>();
+
+                summary.backups.extend(move_aside(&get_path_for_db(data_path))?);
+                summary.backups.extend(move_aside(&get_path_for_journal(data_path))?);
+                summary.backups.extend(move_aside(&get_path_for_days(data_path))?);
+                write_days(data_path, days, cipher)?;
+            },
+            StoreBackend::Sqlite => {
+                let path = get_path_for_sqlite(data_path);
+                let fresh_path = path.with_extension("sqlite3.salvage");
+
+                if fresh_path.exists() {
+                    fs::remove_file(&fresh_path)?;
+                }
+                {
+                    let fresh = SqliteDB::open(&fresh_path, cipher.cloned())?;
+
+                    for (date, records) in days {
+                        fresh.replace_records(&date, records)?;
+                    }
+                }
+                summary.backups.extend(move_aside(&path)?);
+                fs::rename(&fresh_path, &path)?;
+            },
+        };
+        // Records have moved within their days, so the search index no longer points at them
+        SearchIndex::in_dir(data_path, None).clear()?;
+        Ok(summary)
+    }
+
+    /// Reads `records.db` and day files up to their first damaged day, then entries of the journal
+    fn read_file_store(
+        &mut self,
+        dir: &Path,
+        cipher: Option<&Cipher>,
+        writable: bool,
+        days: &mut BTreeMap<String, Vec<ActivityRecord>>,
+    ) -> Result<(), RecordStoreError> {
+        if let Err(err) = if writable { upgrade_file_store(dir, cipher) } else { Ok(None) } {
+            self.problems.push(Problem::Unreadable(dir.to_owned(), format!("could not upgrade the store: {}", err)));
+        }
+
+        self.read_snapshot_file(&get_path_for_db(dir), cipher, days)?;
+        for (_, path) in list_day_files(dir)? {
+            self.read_snapshot_file(&path, cipher, days)?;
+        }
+
+        let journal_path = get_path_for_journal(dir);
+        let bytes = if journal_path.exists() { fs::read(&journal_path)? } else { vec![] };
+        match SchemaHeader::detect(&bytes) {
+            // Journal that is being truncated by the writer has no entries yet
+            _ if bytes.len() < SchemaHeader::SIZE => return Ok(()),
+            None => return Ok(()),
+            Some(SCHEMA_VERSION) => {},
+            Some(version) => return Err(RecordStoreError::UnsupportedSchema(version)),
+        };
+
+        let journal_cipher = if SchemaHeader::is_encrypted(&bytes) {
+            Some(cipher.ok_or(RecordStoreError::EncryptedStore)?)
+        } else {
+            None
+        };
+        let body = &bytes[SchemaHeader::SIZE..];
+        let decoded = decode_entries_until::<JournalEntry>(body, journal_cipher);
+
+        if decoded.read_length < body.len() {
+            self.problems.push(Problem::Unreadable(journal_path, format!(
+                "{} bytes after entry #{} could not be read{}",
+                body.len() - decoded.read_length,
+                decoded.entries.len(),
+                decoded.damage.map_or(String::new(), |err| format!(": {}", err))
+            )));
+        }
+        for (key, record) in decoded.entries {
+            let records = days.entry(key).or_insert_with(Vec::new);
+
+            // Snapshot might've been saved right before the journal got truncated
+            if !records.iter().rev().any(|existing| existing == &record) {
+                records.push(record);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_snapshot_file(
+        &mut self,
+        path: &Path,
+        cipher: Option<&Cipher>,
+        days: &mut BTreeMap<String, Vec<ActivityRecord>>,
+    ) -> Result<(), RecordStoreError> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        match open_file(fs::read(path)?, cipher) {
+            Ok(bytes) => {
+                if let Err(reason) = decode_snapshot_days(&bytes, days) {
+                    self.problems.push(Problem::Unreadable(path.to_owned(), reason));
+                }
+            },
+            Err(RecordStoreError::EncryptedStore) => return Err(RecordStoreError::EncryptedStore),
+            Err(err) => self.problems.push(Problem::Unreadable(path.to_owned(), err.to_string())),
+        };
+        Ok(())
+    }
+
+    fn read_sqlite(
+        &mut self,
+        path: &Path,
+        cipher: Option<&Cipher>,
+        writable: bool,
+        days: &mut BTreeMap<String, Vec<ActivityRecord>>,
+    ) -> Result<(), RecordStoreError> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let open = if writable { SqliteDB::open } else { SqliteDB::open_read_only };
+        let db = match open(path, cipher.cloned()).and_then(|db| Ok((db.integrity_problems()?, db))) {
+            Ok((messages, db)) => {
+                for message in messages {
+                    self.problems.push(Problem::Unreadable(path.to_owned(), message));
+                }
+                db
+            },
+            Err(err) => {
+                self.problems.push(Problem::Unreadable(path.to_owned(), err.to_string()));
+                return Ok(());
+            }
+        };
+
+        for key in db.date_keys()? {
+            let records = match TrackingDate(key.clone()).parse() {
+                Some(date) => match db.query_records(&date) {
+                    Ok(records) => records,
+                    Err(RecordStoreError::EncryptedStore) => return Err(RecordStoreError::EncryptedStore),
+                    Err(err) => {
+                        self.problems.push(Problem::Unreadable(path.to_owned(), format!("{}: {}", key, err)));
+                        continue;
+                    }
+                },
+                None => vec![],
+            };
+            days.insert(key, records);
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a snapshot one day at a time, keeping the days read before an error
+fn decode_snapshot_days(bytes: &[u8], days: &mut BTreeMap<String, Vec<ActivityRecord>>) -> Result<(), String> {
+    match SchemaHeader::detect(bytes) {
+        Some(SCHEMA_VERSION) => {},
+        Some(version) => return Err(format!("schema v{} is not supported", version)),
+        None => return Ok(()),
+    };
+
+    decode_days_with(bytes, |key, records| days.entry(key).or_insert_with(Vec::new).extend(records))
+        .map(|_| {})
+        .map_err(|err| err.to_string())
+}
+
+/// Finds records of a day that are out of order or overlap
+fn validate(date: &NaiveDate, records: &[ActivityRecord]) -> Vec<Problem> {
+    let mut problems: Vec<Problem> = vec![];
+
+    for (index, record) in records.iter().enumerate() {
+        if record.time_range.1 < record.time_range.0 {
+            problems.push(Problem::ReversedRange(*date, index));
+        }
+        if index == 0 {
+            continue;
+        }
+
+        let previous = &records[index - 1];
+        if record.time_range.0 < previous.time_range.0 {
+            problems.push(Problem::Unordered(*date, index));
+        } else if record.time_range.0 < previous.time_range.1 {
+            problems.push(Problem::Overlapping(*date, index));
+        }
+    }
+    problems
+}
+
+/// Orders records of a day and drops the ones that can't be kept,
+/// returns kept records and the number of dropped ones
+fn repair(mut records: Vec<ActivityRecord>) -> (Vec<ActivityRecord>, usize) {
+    let count = records.len();
+    let mut kept: Vec<ActivityRecord> = vec![];
+
+    records.sort_by_key(|record| record.time_range.0);
+    for record in records {
+        let fits = record.time_range.0 <= record.time_range.1
+            && kept.last().map_or(true, |last| last.time_range.1 <= record.time_range.0);
+
+        if fits {
+            kept.push(record);
+        }
+    }
+
+    let dropped = count - kept.len();
+    (kept, dropped)
+}
+
+/// Renames a damaged file so that a fresh one can take its place
+fn move_aside(path: &Path) -> Result<Option<PathBuf>, RecordStoreError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".corrupt-{}", timestamp));
+
+    let backup_path = path.with_file_name(name);
+    fs::rename(path, &backup_path)?;
+    Ok(Some(backup_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{
+        db::DB,
+        utils::{get_path_for_day, test_dir},
+        Archetype, ProductivityStatus, RecordStore,
+    };
+    use std::{collections::BTreeSet, time::Duration};
+
+    fn record(start_secs: u64, end_secs: u64) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_secs(start_secs),
+                UNIX_EPOCH + Duration::from_secs(end_secs),
+            ),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::AFK,
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn finds_and_drops_bad_records() {
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let records = vec![record(10, 20), record(20, 40), record(30, 50), record(60, 55), record(0, 5)];
+
+        assert_eq!(validate(&date, &records), vec![
+            Problem::Overlapping(date, 2),
+            Problem::ReversedRange(date, 3),
+            Problem::Unordered(date, 4),
+        ]);
+        assert_eq!(repair(records), (vec![record(0, 5), record(10, 20), record(20, 40)], 2));
+    }
+
+    #[test]
+    fn salvages_days_of_truncated_snapshot() {
+        let dir = test_dir("fsck");
+        let config = RecordStoreConfig::in_dir(dir.clone());
+        let first = NaiveDate::from_ymd(2020, 6, 1);
+        let second = NaiveDate::from_ymd(2020, 6, 2);
+
+        {
+            let db = DB::open(&dir, None).unwrap();
+            db.replace_records(&first, vec![record(10, 20), record(20, 30)]).unwrap();
+            db.replace_records(&second, vec![record(100_000, 100_010)]).unwrap();
+        }
+        let damaged_path = get_path_for_day(&dir, "2020-06-02");
+        let snapshot = fs::read(&damaged_path).unwrap();
+        fs::write(&damaged_path, &snapshot[..snapshot.len() - 4]).unwrap();
+
+        match RecordStore::new(config.clone()).unwrap().query_records_by_date(&second) {
+            Err(RecordStoreError::Corrupted(_)) => {},
+            _ => panic!("damaged day must not be read"),
+        }
+
+        let report = FsckReport::check(&config, None).unwrap();
+        assert_eq!(report.days.len(), 1);
+        match report.problems.as_slice() {
+            [Problem::Unreadable(path, _)] => assert_eq!(path, &damaged_path),
+            problems => panic!("expected damaged snapshot, got {:?}", problems),
+        }
+
+        let summary = report.salvage(&config, None).unwrap();
+        assert_eq!((summary.days, summary.dropped), (1, 0));
+        assert_eq!(summary.backups.iter().all(|path| path.exists()), true);
+
+        let store = RecordStore::new(config.clone()).unwrap();
+        let (date, records) = report.days.into_iter().next().unwrap();
+        assert_eq!(store.query_records_by_date(&date).unwrap(), records);
+        assert_eq!(FsckReport::check(&config, None).unwrap().is_clean(), true);
+    }
+}
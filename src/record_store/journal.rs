@@ -0,0 +1,334 @@
+use super::{
+    crypto::Cipher,
+    migrations::{SchemaHeader, SCHEMA_VERSION},
+    utils::RecordStoreError,
+    ActivityRecord,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    convert::TryInto,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Size of the length prefix that precedes every journal entry
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Single journal entry: tracking date key and the record pushed on that date
+pub type JournalEntry = (String, ActivityRecord);
+
+struct JournalState {
+    file: File,
+    entries: usize,
+    /// Whether entries in the file are encrypted, which might differ
+    /// from `cipher` until the journal is truncated
+    is_encrypted: bool,
+}
+
+/// Append-only log of records pushed since the last snapshot of the database.
+///
+/// The file starts with a `SchemaHeader`, every entry after it is a little-endian
+/// `u32` length followed by a bincode-encoded `JournalEntry`, so pushing a record
+/// costs the same no matter how long the history is. A torn entry at the end
+/// of the file (e.g. after a crash) is cut off on replay, so that entries
+/// appended afterwards can be read back. Damaged entries are left for `fsck`.
+///
+/// Entries of encrypted journals are sealed one by one,
+/// the header is left as is so that the schema version can be read
+pub struct Journal {
+    path: PathBuf,
+    state: Mutex<JournalState>,
+    cipher: Option<Cipher>,
+}
+
+impl Journal {
+    pub fn open(path: &Path, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let mut header = vec![0u8; SchemaHeader::SIZE];
+        let header_length = (&file).read(&mut header)?;
+        let journal = Self {
+            path: path.to_owned(),
+            state: Mutex::new(JournalState {
+                file,
+                entries: 0,
+                is_encrypted: SchemaHeader::is_encrypted(&header),
+            }),
+            cipher,
+        };
+
+        if header_length < SchemaHeader::SIZE {
+            journal.truncate()?;
+        }
+        Ok(journal)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.lock().is_encrypted
+    }
+
+    /// Sets the key of entries written after the next truncation
+    pub fn set_cipher(&mut self, cipher: Option<Cipher>) {
+        self.cipher = cipher;
+    }
+
+    /// Reads all complete entries from the journal and cuts off a torn one that follows them.
+    ///
+    /// A complete entry that can't be read leaves the file as it is and fails with `Corrupted`
+    pub fn replay(&self) -> Result<Vec<JournalEntry>, RecordStoreError> {
+        let (entries, torn_at) = read_journal_until(&self.path, self.cipher.as_ref())?;
+        let mut state = self.lock();
+
+        if let Some(length) = torn_at {
+            state.file.set_len(length as u64)?;
+            state.file.sync_data()?;
+        }
+        state.entries = entries.len();
+        Ok(entries)
+    }
+
+    pub fn append(&self, date: &str, record: &ActivityRecord) -> Result<(), RecordStoreError> {
+        let mut state = self.lock();
+        let mut payload = bincode::serialize(&(date, record))?;
+
+        if state.is_encrypted {
+            payload = self.cipher.as_ref().ok_or(RecordStoreError::EncryptedStore)?.seal(&payload);
+        }
+
+        let mut entry = (payload.len() as u32).to_le_bytes().to_vec();
+        entry.extend(payload);
+
+        state.file.write_all(&entry)?;
+        state.file.sync_data()?;
+        state.entries += 1;
+        Ok(())
+    }
+
+    /// Number of entries written since the journal was last truncated
+    pub fn len(&self) -> usize {
+        self.lock().entries
+    }
+
+    /// Drops all entries, called once they're persisted in a snapshot
+    pub fn truncate(&self) -> Result<(), RecordStoreError> {
+        let mut state = self.lock();
+        let header = match self.cipher {
+            Some(_) => SchemaHeader::encrypted(),
+            None => SchemaHeader::current(),
+        };
+
+        state.file.set_len(0)?;
+        state.file.write_all(&header.encode())?;
+        state.file.sync_data()?;
+        state.entries = 0;
+        state.is_encrypted = self.cipher.is_some();
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JournalState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Reads all complete entries of a journal without opening it for writing,
+/// so that it can be read while another process appends to it
+pub fn read_journal(path: &Path, cipher: Option<&Cipher>) -> Result<Vec<JournalEntry>, RecordStoreError> {
+    read_journal_until(path, cipher).map(|(entries, _)| entries)
+}
+
+/// Same as `read_journal`, also gets the length of the file up to its first
+/// incomplete entry if anything follows the complete ones
+fn read_journal_until(path: &Path, cipher: Option<&Cipher>) -> Result<(Vec<JournalEntry>, Option<usize>), RecordStoreError> {
+    let mut bytes: Vec<u8> = vec![];
+
+    match File::open(path) {
+        Ok(mut file) => file.read_to_end(&mut bytes)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok((vec![], None)),
+        Err(err) => return Err(RecordStoreError::from(err)),
+    };
+
+    // Journal that is being truncated has no entries yet
+    if bytes.len() < SchemaHeader::SIZE {
+        return Ok((vec![], None));
+    }
+    match SchemaHeader::detect(&bytes) {
+        Some(SCHEMA_VERSION) => {},
+        Some(version) => return Err(RecordStoreError::UnsupportedSchema(version)),
+        None => return Ok((vec![], None)),
+    };
+
+    let cipher = if SchemaHeader::is_encrypted(&bytes) {
+        Some(cipher.ok_or(RecordStoreError::EncryptedStore)?)
+    } else {
+        None
+    };
+    let decoded = decode_entries_until::<JournalEntry>(&bytes[SchemaHeader::SIZE..], cipher);
+    if let Some(err) = decoded.damage {
+        return Err(RecordStoreError::Corrupted(Box::new(err)));
+    }
+    let length = SchemaHeader::SIZE + decoded.read_length;
+
+    Ok((decoded.entries, Some(length).filter(|&length| length < bytes.len())))
+}
+
+/// Entries read from the start of a journal
+pub(super) struct DecodedEntries<E> {
+    pub entries: Vec<E>,
+    /// Number of bytes taken by `entries`
+    pub read_length: usize,
+    /// Why the complete entry that follows `entries` could not be read, if there is one
+    pub damage: Option<RecordStoreError>,
+}
+
+/// Decodes length-prefixed entries until the first incomplete one,
+/// entries are opened with `cipher` if there is one
+pub(super) fn decode_entries<E: DeserializeOwned>(bytes: &[u8], cipher: Option<&Cipher>) -> Result<Vec<E>, RecordStoreError> {
+    let decoded = decode_entries_until(bytes, cipher);
+
+    match decoded.damage {
+        Some(err) => Err(RecordStoreError::Corrupted(Box::new(err))),
+        None => Ok(decoded.entries),
+    }
+}
+
+/// Same as `decode_entries`, keeps the entries read before a damaged one.
+///
+/// Only an entry running past the end of `bytes` is incomplete, e.g. torn by a crash,
+/// a complete entry that can't be opened or decoded is damage
+pub(super) fn decode_entries_until<E: DeserializeOwned>(bytes: &[u8], cipher: Option<&Cipher>) -> DecodedEntries<E> {
+    let mut entries: Vec<E> = vec![];
+    let mut offset = 0;
+    let mut damage: Option<RecordStoreError> = None;
+
+    while offset + LENGTH_PREFIX_SIZE <= bytes.len() {
+        let prefix: [u8; LENGTH_PREFIX_SIZE] = bytes[offset..offset + LENGTH_PREFIX_SIZE].try_into().unwrap();
+        let length = u32::from_le_bytes(prefix) as usize;
+        let start = offset + LENGTH_PREFIX_SIZE;
+
+        if start + length > bytes.len() {
+            break;
+        }
+        let payload = match cipher {
+            Some(cipher) => match cipher.open(&bytes[start..start + length]) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    damage = Some(err);
+                    break;
+                },
+            },
+            None => bytes[start..start + length].to_vec(),
+        };
+        match bincode::deserialize::<E>(&payload) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => {
+                damage = Some(RecordStoreError::from(err));
+                break;
+            },
+        }
+        offset = start + length;
+    }
+
+    DecodedEntries { entries, read_length: offset, damage }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{
+        crypto::tests::test_params,
+        utils::test_dir,
+        Archetype, ProductivityStatus,
+    };
+    use std::{collections::BTreeSet, time::{Duration, UNIX_EPOCH}};
+
+    fn record(start_secs: u64) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_secs(start_secs),
+                UNIX_EPOCH + Duration::from_secs(start_secs + 30),
+            ),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::AFK,
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn append_and_replay() {
+        let path = test_dir("journal").join("records.journal");
+        let journal = Journal::open(&path, None).unwrap();
+
+        journal.append("2020-06-01", &record(10)).unwrap();
+        journal.append("2020-06-02", &record(20)).unwrap();
+        assert_eq!(journal.len(), 2);
+
+        let reopened = Journal::open(&path, None).unwrap();
+        let entries = reopened.replay().unwrap();
+        assert_eq!(entries, vec![
+            (String::from("2020-06-01"), record(10)),
+            (String::from("2020-06-02"), record(20)),
+        ]);
+
+        reopened.truncate().unwrap();
+        assert_eq!(reopened.replay().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn torn_entry_is_ignored() {
+        let path = test_dir("journal_torn").join("records.journal");
+        let journal = Journal::open(&path, None).unwrap();
+
+        journal.append("2020-06-01", &record(10)).unwrap();
+        journal.append("2020-06-01", &record(20)).unwrap();
+
+        let full_length = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap()
+            .set_len(full_length - 3).unwrap();
+
+        assert_eq!(journal.replay().unwrap(), vec![(String::from("2020-06-01"), record(10))]);
+    }
+
+    #[test]
+    fn damaged_entry_is_kept() {
+        let path = test_dir("journal_damaged").join("records.journal");
+        let journal = Journal::open(&path, None).unwrap();
+
+        journal.append("2020-06-01", &record(10)).unwrap();
+        journal.append("2020-06-01", &record(20)).unwrap();
+
+        // Length prefix of the second entry claims less than it takes, so its payload doesn't decode
+        let mut bytes = std::fs::read(&path).unwrap();
+        let first_length = u32::from_le_bytes(bytes[SchemaHeader::SIZE..SchemaHeader::SIZE + LENGTH_PREFIX_SIZE].try_into().unwrap());
+        let second = SchemaHeader::SIZE + LENGTH_PREFIX_SIZE + first_length as usize;
+        bytes[second..second + LENGTH_PREFIX_SIZE].copy_from_slice(&3u32.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+
+        match journal.replay() {
+            Err(RecordStoreError::Corrupted(_)) => {},
+            _ => panic!("damaged entry must not be taken for a torn one"),
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn encrypted_entries() {
+        let path = test_dir("journal_encrypted").join("records.journal");
+        let (_, cipher) = test_params("secret");
+        let journal = Journal::open(&path, Some(cipher.clone())).unwrap();
+
+        journal.append("2020-06-01", &record(10)).unwrap();
+        assert_eq!(journal.is_encrypted(), true);
+        assert_eq!(std::fs::read(&path).unwrap().windows(10).any(|window| window == b"2020-06-01"), false);
+
+        assert_eq!(Journal::open(&path, Some(cipher)).unwrap().replay().unwrap().len(), 1);
+        assert_eq!(Journal::open(&path, None).unwrap().replay().is_err(), true);
+    }
+}
@@ -0,0 +1,74 @@
+use super::utils::{get_path_for_lock, RecordStoreError};
+use fs2::FileExt;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// Advisory lock that lets a single process write to a record store.
+///
+/// The lock is taken on `trackr.lock` in the data directory, which also holds
+/// the id of the process that took it. It is released once dropped,
+/// or by the OS if the process dies
+pub struct StoreLock {
+    file: File,
+}
+
+impl StoreLock {
+    /// Takes the lock, fails with `RecordStoreError::Locked` if another process holds it
+    pub fn acquire(data_dir: &Path) -> Result<Self, RecordStoreError> {
+        let path = get_path_for_lock(data_dir);
+        let mut file = OpenOptions::new().create(true).read(true).write(true).open(&path)?;
+
+        if let Err(err) = file.try_lock_exclusive() {
+            if err.kind() != fs2::lock_contended_error().kind() {
+                return Err(RecordStoreError::from(err));
+            }
+
+            let owner = fs::read_to_string(&path).ok().and_then(|pid| pid.trim().parse().ok());
+            return Err(RecordStoreError::Locked(owner));
+        }
+
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        file.sync_data()?;
+        Ok(Self { file })
+    }
+
+    /// Takes the lock unless another process holds it
+    pub fn try_acquire(data_dir: &Path) -> Result<Option<Self>, RecordStoreError> {
+        match Self::acquire(data_dir) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(RecordStoreError::Locked(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::utils::test_dir;
+
+    #[test]
+    fn single_writer() {
+        let dir = test_dir("lock");
+        let lock = StoreLock::acquire(&dir).unwrap();
+
+        match StoreLock::acquire(&dir) {
+            Err(RecordStoreError::Locked(owner)) => assert_eq!(owner, Some(std::process::id())),
+            _ => panic!("lock must be held by a single writer"),
+        }
+        assert_eq!(StoreLock::try_acquire(&dir).unwrap().is_none(), true);
+
+        drop(lock);
+        assert_eq!(StoreLock::try_acquire(&dir).unwrap().is_some(), true);
+    }
+}
@@ -0,0 +1,91 @@
+use super::{ActivityRecord, Archetype};
+use std::{cmp::Ordering, time::SystemTime};
+
+/// Order in which overlapping records claim their time: active windows before AFK,
+/// then machines by name, then earlier and shorter records first.
+/// The remaining fields only make the order total
+fn claim_order(a: &ActivityRecord, b: &ActivityRecord) -> Ordering {
+    let is_afk = |record: &ActivityRecord| record.archetype == Archetype::AFK;
+
+    is_afk(a).cmp(&is_afk(b))
+        .then_with(|| a.machine.cmp(&b.machine))
+        .then_with(|| a.time_range.cmp(&b.time_range))
+        .then_with(|| format!("{:?}", a.archetype).cmp(&format!("{:?}", b.archetype)))
+        .then_with(|| format!("{:?}", a.productivity).cmp(&format!("{:?}", b.productivity)))
+}
+
+/// Resolves overlaps between records of one tracking day, e.g. tracked on different machines.
+///
+/// Records claim their time in `claim_order` and keep only the parts no earlier
+/// record has claimed, so the outcome doesn't depend on the order records come in.
+/// Returns records that don't overlap ordered by start time
+pub fn resolve_overlaps(mut records: Vec<ActivityRecord>) -> Vec<ActivityRecord> {
+    records.sort_by(claim_order);
+    records.dedup();
+
+    let mut claimed: Vec<(SystemTime, SystemTime)> = vec![];
+    let mut resolved: Vec<ActivityRecord> = vec![];
+
+    for record in records {
+        let mut free = vec![record.time_range];
+
+        for (start, end) in &claimed {
+            let mut remaining = vec![];
+
+            for (free_start, free_end) in free {
+                if free_start < *start {
+                    remaining.push((free_start, std::cmp::min(free_end, *start)));
+                }
+                if *end < free_end {
+                    remaining.push((std::cmp::max(free_start, *end), free_end));
+                }
+            }
+            free = remaining;
+        }
+
+        claimed.push(record.time_range);
+        resolved.extend(free.into_iter().filter_map(|(start, end)| record.clip(start, end)));
+    }
+
+    resolved.sort_by_key(|record| record.time_range.0);
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::ProductivityStatus;
+    use std::{collections::BTreeSet, time::{Duration, UNIX_EPOCH}};
+
+    fn record(start_secs: u64, end_secs: u64, machine: &str, archetype: Archetype) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (UNIX_EPOCH + Duration::from_secs(start_secs), UNIX_EPOCH + Duration::from_secs(end_secs)),
+            productivity: ProductivityStatus::Neutral,
+            archetype,
+            machine: Some(String::from(machine)),
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    fn window(title: &str) -> Archetype {
+        Archetype::ActiveWindow(String::from(title), String::from("code"), String::from("Code"))
+    }
+
+    #[test]
+    fn overlaps_resolve_regardless_of_order() {
+        let desktop = record(100, 200, "desktop", window("main.rs"));
+        let laptop = record(150, 300, "laptop", window("notes.md"));
+        let laptop_afk = record(50, 150, "laptop", Archetype::AFK);
+        let expected = vec![
+            record(50, 100, "laptop", Archetype::AFK),
+            desktop.clone(),
+            record(200, 300, "laptop", window("notes.md")),
+        ];
+
+        assert_eq!(resolve_overlaps(vec![desktop.clone(), laptop.clone(), laptop_afk.clone()]), expected);
+        assert_eq!(resolve_overlaps(vec![laptop_afk, laptop, desktop.clone(), desktop]), expected);
+        assert_eq!(resolve_overlaps(expected.clone()), expected);
+    }
+}
@@ -0,0 +1,947 @@
+//! On-disk schema versioning.
+//!
+//! Every store file starts with a `SchemaHeader`, SQLite stores keep their
+//! version in `PRAGMA user_version`. Whenever a stored type changes:
+//! bump `SCHEMA_VERSION`, freeze the previous types in a `vN` module
+//! with a conversion to the next version and to the current one through it,
+//! and extend `decode_days`, `decode_journal` and `SQLITE_MIGRATIONS` to upgrade from it.
+//! Frozen types never refer to the current ones, which keep changing.
+//! Day files are laid out like `records.db` and need the same upgrade,
+//! month files of the archive held records up to v8 and are summed up when upgraded.
+mod v0;
+mod v1;
+mod v3;
+mod v4;
+mod v5;
+mod v6;
+mod v7;
+mod v8;
+
+use super::{
+    archive::{write_month, Month},
+    crypto::{open_file, Cipher},
+    db::TrackingDate,
+    encoding::decode_snapshot,
+    journal::{decode_entries, JournalEntry},
+    retention::ArchivedDay,
+    db::list_day_files,
+    utils::{
+        get_path_for_archive, get_path_for_day, get_path_for_days, get_path_for_db, get_path_for_journal,
+        read_snapshot, write_snapshot, RecordStoreError, Snapshot,
+    },
+    ActivityRecord,
+};
+use rusqlite::{Connection, NO_PARAMS};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Version of the on-disk schema written by this build
+pub const SCHEMA_VERSION: u32 = 9;
+
+const MAGIC: [u8; 4] = *b"TRKR";
+/// Marks files whose contents are encrypted, see `crypto`
+const ENCRYPTED_MAGIC: [u8; 4] = *b"TRKX";
+
+/// Statements upgrading an SQLite store from version `i` to `i + 1`
+const SQLITE_MIGRATIONS: [&'static str; SCHEMA_VERSION as usize] = [
+    // 0 -> 1: versioning introduced, tables are unchanged
+    "",
+    // 1 -> 2: strings are interned into a dictionary table
+    "
+    CREATE TABLE strings (
+        id    INTEGER PRIMARY KEY,
+        value NOT NULL
+    );
+    CREATE INDEX strings_by_value ON strings (value);
+    INSERT INTO strings (value)
+        SELECT title FROM records WHERE title IS NOT NULL
+        UNION SELECT app_name FROM records WHERE app_name IS NOT NULL
+        UNION SELECT app_class FROM records WHERE app_class IS NOT NULL
+        UNION SELECT activity FROM records WHERE activity IS NOT NULL;
+
+    CREATE TABLE records_v2 (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        date         TEXT NOT NULL,
+        start_ms     INTEGER NOT NULL,
+        end_ms       INTEGER NOT NULL,
+        kind         TEXT NOT NULL,
+        title_id     INTEGER REFERENCES strings (id),
+        app_name_id  INTEGER REFERENCES strings (id),
+        app_class_id INTEGER REFERENCES strings (id),
+        productivity INTEGER NOT NULL,
+        activity_id  INTEGER REFERENCES strings (id)
+    );
+    INSERT INTO records_v2 (id, date, start_ms, end_ms, kind, title_id, app_name_id, app_class_id, productivity, activity_id)
+        SELECT id, date, start_ms, end_ms, kind,
+            (SELECT id FROM strings WHERE value = title),
+            (SELECT id FROM strings WHERE value = app_name),
+            (SELECT id FROM strings WHERE value = app_class),
+            productivity,
+            (SELECT id FROM strings WHERE value = activity)
+        FROM records;
+    DROP TABLE records;
+    ALTER TABLE records_v2 RENAME TO records;
+    CREATE INDEX records_by_date ON records (date);
+
+    CREATE VIEW records_view AS
+        SELECT records.id, date, start_ms, end_ms, kind,
+            title.value AS title,
+            app_name.value AS app_name,
+            app_class.value AS app_class,
+            productivity,
+            activity.value AS activity
+        FROM records
+        LEFT JOIN strings AS title ON title.id = title_id
+        LEFT JOIN strings AS app_name ON app_name.id = app_name_id
+        LEFT JOIN strings AS app_class ON app_class.id = app_class_id
+        LEFT JOIN strings AS activity ON activity.id = activity_id;
+    ",
+    // 2 -> 3: file stores are split into a file per day, tables are unchanged
+    "",
+    // 3 -> 4: records are stamped with the name of the machine they were tracked on
+    "
+    ALTER TABLE records ADD COLUMN machine_id INTEGER REFERENCES strings (id);
+    DROP VIEW records_view;
+    CREATE VIEW records_view AS
+        SELECT records.id, date, start_ms, end_ms, kind,
+            title.value AS title,
+            app_name.value AS app_name,
+            app_class.value AS app_class,
+            productivity,
+            activity.value AS activity,
+            machine.value AS machine
+        FROM records
+        LEFT JOIN strings AS title ON title.id = title_id
+        LEFT JOIN strings AS app_name ON app_name.id = app_name_id
+        LEFT JOIN strings AS app_class ON app_class.id = app_class_id
+        LEFT JOIN strings AS activity ON activity.id = activity_id
+        LEFT JOIN strings AS machine ON machine.id = machine_id;
+    ",
+    // 4 -> 5: records get free-form tags and a note
+    "
+    ALTER TABLE records ADD COLUMN note_id INTEGER REFERENCES strings (id);
+    CREATE TABLE record_tags (
+        record_id INTEGER NOT NULL REFERENCES records (id),
+        tag_id    INTEGER NOT NULL REFERENCES strings (id)
+    );
+    CREATE INDEX record_tags_by_record ON record_tags (record_id);
+    DROP VIEW records_view;
+    CREATE VIEW records_view AS
+        SELECT records.id, date, start_ms, end_ms, kind,
+            title.value AS title,
+            app_name.value AS app_name,
+            app_class.value AS app_class,
+            productivity,
+            activity.value AS activity,
+            machine.value AS machine,
+            (SELECT group_concat(tag.value, ',') FROM record_tags
+                JOIN strings AS tag ON tag.id = tag_id
+                WHERE record_id = records.id) AS tags,
+            note.value AS note
+        FROM records
+        LEFT JOIN strings AS title ON title.id = title_id
+        LEFT JOIN strings AS app_name ON app_name.id = app_name_id
+        LEFT JOIN strings AS app_class ON app_class.id = app_class_id
+        LEFT JOIN strings AS activity ON activity.id = activity_id
+        LEFT JOIN strings AS machine ON machine.id = machine_id
+        LEFT JOIN strings AS note ON note.id = note_id;
+    ",
+    // 5 -> 6: records are assigned to projects
+    "
+    ALTER TABLE records ADD COLUMN project_id INTEGER REFERENCES strings (id);
+    DROP VIEW records_view;
+    CREATE VIEW records_view AS
+        SELECT records.id, date, start_ms, end_ms, kind,
+            title.value AS title,
+            app_name.value AS app_name,
+            app_class.value AS app_class,
+            productivity,
+            activity.value AS activity,
+            machine.value AS machine,
+            (SELECT group_concat(tag.value, ',') FROM record_tags
+                JOIN strings AS tag ON tag.id = tag_id
+                WHERE record_id = records.id) AS tags,
+            note.value AS note,
+            project.value AS project
+        FROM records
+        LEFT JOIN strings AS title ON title.id = title_id
+        LEFT JOIN strings AS app_name ON app_name.id = app_name_id
+        LEFT JOIN strings AS app_class ON app_class.id = app_class_id
+        LEFT JOIN strings AS activity ON activity.id = activity_id
+        LEFT JOIN strings AS machine ON machine.id = machine_id
+        LEFT JOIN strings AS note ON note.id = note_id
+        LEFT JOIN strings AS project ON project.id = project_id;
+    ",
+    // 6 -> 7: windows hidden by privacy rules are stored with the `private` kind, tables are unchanged
+    "",
+    // 7 -> 8: windows tracked in incognito mode are stored with the `incognito` kind and no title, tables are unchanged
+    "",
+    // 8 -> 9: month files of the archive hold totals of days instead of their records, tables are unchanged
+    "",
+];
+
+/// Header that precedes contents of `records.db`, `records.journal`, day files and month files
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SchemaHeader {
+    magic: [u8; 4],
+    pub version: u32,
+}
+
+impl SchemaHeader {
+    /// Size of an encoded header in bytes
+    pub const SIZE: usize = 8;
+
+    pub fn current() -> Self {
+        Self { magic: MAGIC, version: SCHEMA_VERSION }
+    }
+
+    /// Header of an encrypted file written by this build
+    pub fn encrypted() -> Self {
+        Self { magic: ENCRYPTED_MAGIC, version: SCHEMA_VERSION }
+    }
+
+    /// Encodes the header the same way bincode does as part of a snapshot
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.magic.to_vec();
+        bytes.extend(&self.version.to_le_bytes());
+        bytes
+    }
+
+    /// Gets schema version of store file contents.
+    ///
+    /// Files written before versioning have no header
+    /// and are reported as version 0, empty files have no version at all
+    pub fn detect(bytes: &[u8]) -> Option<u32> {
+        if bytes.is_empty() {
+            return None;
+        }
+
+        if bytes.len() >= Self::SIZE && (bytes[..MAGIC.len()] == MAGIC || Self::is_encrypted(bytes)) {
+            let mut version = [0u8; 4];
+            version.copy_from_slice(&bytes[MAGIC.len()..Self::SIZE]);
+            return Some(u32::from_le_bytes(version));
+        }
+
+        Some(0)
+    }
+
+    pub fn is_encrypted(bytes: &[u8]) -> bool {
+        bytes.len() >= Self::SIZE && bytes[..ENCRYPTED_MAGIC.len()] == ENCRYPTED_MAGIC
+    }
+}
+
+type Days = HashMap<String, Vec<ActivityRecord>>;
+
+/// Upgrades `records.db`, `records.journal` and day files in a given directory to the current schema.
+///
+/// Original files are copied to `*.v{N}.bak` before being rewritten.
+/// Returns the version the store was upgraded from
+pub fn upgrade_file_store(dir: &Path, cipher: Option<&Cipher>) -> Result<Option<u32>, RecordStoreError> {
+    let snapshot_path = get_path_for_db(dir);
+    let journal_path = get_path_for_journal(dir);
+    let snapshot_bytes = read_if_exists(&snapshot_path)?;
+    let journal_bytes = read_if_exists(&journal_path)?;
+    let snapshot_version = SchemaHeader::detect(&snapshot_bytes);
+    let journal_version = SchemaHeader::detect(&journal_bytes);
+
+    let versions: Vec<u32> = vec![snapshot_version, journal_version].into_iter().flatten().collect();
+    let oldest_version = match versions.iter().min() {
+        Some(version) => *version,
+        None => return Ok(None),
+    };
+
+    if let Some(version) = versions.iter().find(|version| **version > SCHEMA_VERSION) {
+        return Err(RecordStoreError::UnsupportedSchema(*version));
+    }
+
+    if oldest_version == SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    // Day files go first, `records.db` is rewritten last so that an interrupted upgrade is run again
+    for (_, path) in list_day_files(dir)? {
+        upgrade_snapshot_file(&path, cipher)?;
+    }
+
+    backup(&snapshot_path, snapshot_version)?;
+    backup(&journal_path, journal_version)?;
+
+    let mut days = match snapshot_version {
+        Some(version) => decode_days(version, &open_file(snapshot_bytes, cipher)?)?,
+        None => Days::new(),
+    };
+
+    if let Some(version) = journal_version {
+        let journal_cipher = if SchemaHeader::is_encrypted(&journal_bytes) {
+            Some(cipher.ok_or(RecordStoreError::EncryptedStore)?)
+        } else {
+            None
+        };
+
+        for (date, record) in decode_journal(version, &journal_bytes, journal_cipher)? {
+            let records = days.entry(date).or_insert_with(Vec::new);
+
+            if !records.contains(&record) {
+                records.push(record);
+            }
+        }
+    }
+
+    write_days(dir, days, cipher)?;
+    if journal_version.is_some() {
+        fs::remove_file(&journal_path)?;
+    }
+
+    Ok(Some(oldest_version))
+}
+
+/// Splits days into their files under `days`, days whose keys are not dates stay in `records.db`
+pub fn write_days(dir: &Path, days: Days, cipher: Option<&Cipher>) -> Result<(), RecordStoreError> {
+    let mut leftovers = Days::new();
+
+    fs::create_dir_all(get_path_for_days(dir))?;
+    for (key, records) in days {
+        if TrackingDate(key.clone()).parse().is_none() {
+            leftovers.insert(key, records);
+            continue;
+        }
+
+        let path = get_path_for_day(dir, &key);
+        // Day files already exist when only the journal is left from an older version
+        let mut day = match read_snapshot(&path, cipher)? {
+            Some(mut snapshot) => snapshot.days.remove(&key).unwrap_or_default(),
+            None => vec![],
+        };
+        for record in records {
+            if !day.contains(&record) {
+                day.push(record);
+            }
+        }
+
+        let mut shard = Days::new();
+        shard.insert(key, day);
+        write_snapshot(&path, &Snapshot::new(shard), cipher)?;
+    }
+
+    write_snapshot(&get_path_for_db(dir), &Snapshot::new(leftovers), cipher)
+}
+
+/// Upgrades month files of the archive in a given data directory to the current schema.
+///
+/// Returns the oldest version a month file was upgraded from
+pub fn upgrade_archive(dir: &Path, cipher: Option<&Cipher>) -> Result<Option<u32>, RecordStoreError> {
+    let archive_dir = get_path_for_archive(dir);
+    let mut oldest_version: Option<u32> = None;
+
+    if !archive_dir.exists() {
+        return Ok(None);
+    }
+
+    for entry in fs::read_dir(&archive_dir)? {
+        let path = entry?.path();
+
+        if path.extension().map_or(true, |extension| extension != "db") {
+            continue;
+        }
+
+        if let Some(version) = upgrade_month_file(&path, cipher)? {
+            oldest_version = Some(oldest_version.map_or(version, |oldest| std::cmp::min(oldest, version)));
+        }
+    }
+
+    Ok(oldest_version)
+}
+
+/// Sums up records of a month file written up to v8, returns the version it was upgraded from
+fn upgrade_month_file(path: &Path, cipher: Option<&Cipher>) -> Result<Option<u32>, RecordStoreError> {
+    let bytes = fs::read(path)?;
+    let version = match SchemaHeader::detect(&bytes) {
+        Some(version) if version > SCHEMA_VERSION => return Err(RecordStoreError::UnsupportedSchema(version)),
+        Some(version) if version < SCHEMA_VERSION => version,
+        _ => return Ok(None),
+    };
+
+    backup(path, Some(version))?;
+    let days = decode_days(version, &open_file(bytes, cipher)?)?
+        .into_iter()
+        .map(|(date, records)| {
+            let mut day = ArchivedDay::default();
+            day.add_records(records);
+            (date, day)
+        })
+        .collect();
+    write_month(path, &Month::new(days), cipher)?;
+    Ok(Some(version))
+}
+
+/// Upgrades a day file, returns the version it was upgraded from
+fn upgrade_snapshot_file(path: &Path, cipher: Option<&Cipher>) -> Result<Option<u32>, RecordStoreError> {
+    let bytes = fs::read(path)?;
+    let version = match SchemaHeader::detect(&bytes) {
+        Some(version) if version > SCHEMA_VERSION => return Err(RecordStoreError::UnsupportedSchema(version)),
+        Some(version) if version < SCHEMA_VERSION => version,
+        _ => return Ok(None),
+    };
+
+    backup(path, Some(version))?;
+    write_snapshot(path, &Snapshot::new(decode_days(version, &open_file(bytes, cipher)?)?), cipher)?;
+    Ok(Some(version))
+}
+
+/// Upgrades an SQLite store to the current schema, `schema` is used to set up an empty database.
+///
+/// Returns the version the store was upgraded from
+pub fn upgrade_sqlite(conn: &Connection, path: Option<&Path>, schema: &str) -> Result<Option<u32>, RecordStoreError> {
+    let version: u32 = conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+    let table_count: i64 = conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'records'",
+        NO_PARAMS,
+        |row| row.get(0),
+    )?;
+
+    if version > SCHEMA_VERSION {
+        return Err(RecordStoreError::UnsupportedSchema(version));
+    }
+
+    if table_count == 0 {
+        conn.execute_batch(schema)?;
+        conn.execute_batch(&format!("PRAGMA user_version = {};", SCHEMA_VERSION))?;
+        return Ok(None);
+    }
+
+    if version == SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    if let Some(path) = path {
+        backup(path, Some(version))?;
+    }
+
+    conn.execute_batch("BEGIN;")?;
+    let result = (|| -> rusqlite::Result<()> {
+        for step in &SQLITE_MIGRATIONS[version as usize..] {
+            conn.execute_batch(step)?;
+        }
+        conn.execute_batch(&format!("PRAGMA user_version = {};", SCHEMA_VERSION))
+    })();
+
+    match result {
+        Ok(_) => conn.execute_batch("COMMIT;")?,
+        Err(err) => {
+            conn.execute_batch("ROLLBACK;")?;
+            return Err(RecordStoreError::from(err));
+        }
+    };
+
+    Ok(Some(version))
+}
+
+/// Lets the user know their store was upgraded and where the originals are
+pub fn report_upgrade(path: &Path, from_version: u32) {
+    eprintln!(
+        "Record store at {:?} was upgraded from schema v{} to v{}, original files are kept with .v{}.bak suffix",
+        path, from_version, SCHEMA_VERSION, from_version
+    );
+}
+
+fn decode_days(version: u32, bytes: &[u8]) -> Result<Days, RecordStoreError> {
+    Ok(match version {
+        0 => upgrade_days(bincode::deserialize::<v0::Days>(bytes)?),
+        1 => upgrade_days(bincode::deserialize::<v1::Snapshot>(bytes)?.days),
+        _ => decode_snapshot(bytes)?.days,
+    })
+}
+
+fn decode_journal(version: u32, bytes: &[u8], cipher: Option<&Cipher>) -> Result<Vec<JournalEntry>, RecordStoreError> {
+    Ok(match version {
+        0 => upgrade_entries(decode_entries::<v0::JournalEntry>(bytes, None)?),
+        1..=3 => upgrade_entries(decode_entries::<v3::JournalEntry>(&bytes[SchemaHeader::SIZE..], cipher)?),
+        4 => upgrade_entries(decode_entries::<v4::JournalEntry>(&bytes[SchemaHeader::SIZE..], cipher)?),
+        5 => upgrade_entries(decode_entries::<v5::JournalEntry>(&bytes[SchemaHeader::SIZE..], cipher)?),
+        6 => upgrade_entries(decode_entries::<v6::JournalEntry>(&bytes[SchemaHeader::SIZE..], cipher)?),
+        7 => upgrade_entries(decode_entries::<v7::JournalEntry>(&bytes[SchemaHeader::SIZE..], cipher)?),
+        8 => upgrade_entries(decode_entries::<v8::JournalEntry>(&bytes[SchemaHeader::SIZE..], cipher)?),
+        _ => decode_entries::<JournalEntry>(&bytes[SchemaHeader::SIZE..], cipher)?,
+    })
+}
+
+fn upgrade_days<R>(days: HashMap<String, Vec<R>>) -> Days
+where
+    R: Into<ActivityRecord>,
+{
+    days.into_iter()
+        .map(|(date, records)| (date, records.into_iter().map(R::into).collect()))
+        .collect()
+}
+
+fn upgrade_entries<R>(entries: Vec<(String, R)>) -> Vec<JournalEntry>
+where
+    R: Into<ActivityRecord>,
+{
+    entries.into_iter()
+        .map(|(date, record)| (date, record.into()))
+        .collect()
+}
+
+fn read_if_exists(path: &Path) -> Result<Vec<u8>, RecordStoreError> {
+    if path.exists() {
+        Ok(fs::read(path)?)
+    } else {
+        Ok(vec![])
+    }
+}
+
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".v{}.bak", version));
+    path.with_file_name(file_name)
+}
+
+fn backup(path: &Path, version: Option<u32>) -> Result<(), RecordStoreError> {
+    if let Some(version) = version {
+        fs::copy(path, backup_path(path, version))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{
+        backend::RecordBackend,
+        db::DB,
+        encoding::encode_snapshot,
+        sqlite::SqliteDB,
+        utils::{get_path_for_sqlite, test_dir},
+        Archetype, ArchivedKind, ProductivityStatus,
+    };
+    use chrono::NaiveDate;
+    use serde::Serialize;
+    use std::{collections::BTreeSet, time::{Duration, UNIX_EPOCH}};
+
+    fn v0_record(start_secs: u64) -> v0::ActivityRecord {
+        v0::ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_secs(start_secs),
+                UNIX_EPOCH + Duration::from_secs(start_secs + 60),
+            ),
+            productivity: v0::ProductivityStatus::Productive(String::from("coding")),
+            archetype: v0::Archetype::ActiveWindow(
+                String::from("trackr"),
+                String::from("code-oss"),
+                String::from("Code"),
+            ),
+        }
+    }
+
+    fn record(start_secs: u64) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_secs(start_secs),
+                UNIX_EPOCH + Duration::from_secs(start_secs + 60),
+            ),
+            productivity: ProductivityStatus::Productive(String::from("coding")),
+            archetype: Archetype::ActiveWindow(
+                String::from("trackr"),
+                String::from("code-oss"),
+                String::from("Code"),
+            ),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    fn v3_record(start_secs: u64) -> v3::ActivityRecord {
+        v3::ActivityRecord::from(v0_record(start_secs))
+    }
+
+    fn v6_record(start_secs: u64) -> v6::ActivityRecord {
+        v6::ActivityRecord {
+            project: Some(String::from("client-x")),
+            ..v6::ActivityRecord::from(v5::ActivityRecord::from(v4::ActivityRecord::from(v3_record(start_secs))))
+        }
+    }
+
+    /// Journal of a given schema version holding a single entry
+    fn write_journal<E: Serialize>(dir: &Path, version: u32, entry: &E) {
+        let mut header = SchemaHeader::current();
+        header.version = version;
+        let entry = bincode::serialize(entry).unwrap();
+        let mut journal = header.encode();
+        journal.extend(&(entry.len() as u32).to_le_bytes());
+        journal.extend(entry);
+        fs::write(get_path_for_journal(dir), journal).unwrap();
+    }
+
+    fn write_v0_fixture(dir: &Path) {
+        let mut days = v0::Days::new();
+        days.insert(String::from("2020-06-01"), vec![v0_record(100)]);
+        fs::write(get_path_for_db(dir), bincode::serialize(&days).unwrap()).unwrap();
+
+        let entry = bincode::serialize(&(String::from("2020-06-01"), v0_record(200))).unwrap();
+        let mut journal = (entry.len() as u32).to_le_bytes().to_vec();
+        journal.extend(entry);
+        fs::write(get_path_for_journal(dir), journal).unwrap();
+    }
+
+    #[test]
+    fn upgrades_v0_file_store() {
+        let dir = test_dir("migrate_v0");
+        write_v0_fixture(&dir);
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(0));
+        assert_eq!(backup_path(&get_path_for_db(&dir), 0).exists(), true);
+        assert_eq!(backup_path(&get_path_for_journal(&dir), 0).exists(), true);
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), None);
+
+        let db = DB::open(&dir, None).unwrap();
+
+        assert_eq!(
+            db.query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![record(100), record(200)]
+        );
+    }
+
+    #[test]
+    fn upgrades_v1_file_store() {
+        let dir = test_dir("migrate_v1");
+        let mut header = SchemaHeader::current();
+        header.version = 1;
+        let mut days = HashMap::new();
+        days.insert(String::from("2020-06-01"), vec![v3_record(100)]);
+
+        fs::write(get_path_for_db(&dir), bincode::serialize(&v1::Snapshot { header, days }).unwrap()).unwrap();
+        write_journal(&dir, 1, &(String::from("2020-06-01"), v3_record(200)));
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(1));
+        assert_eq!(backup_path(&get_path_for_db(&dir), 1).exists(), true);
+        assert_eq!(get_path_for_day(&dir, "2020-06-01").exists(), true);
+        assert_eq!(
+            DB::open(&dir, None).unwrap().query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![record(100), record(200)]
+        );
+    }
+
+    #[test]
+    fn upgrades_v3_day_files() {
+        let dir = test_dir("migrate_v3");
+        let mut header = SchemaHeader::current();
+        header.version = 3;
+        let mut day = Snapshot::new(HashMap::new());
+        day.header = header;
+        day.days.insert(String::from("2020-06-01"), vec![record(100)]);
+
+        fs::create_dir_all(get_path_for_days(&dir)).unwrap();
+        fs::write(get_path_for_day(&dir, "2020-06-01"), encode_snapshot(&day)).unwrap();
+        day.days.clear();
+        fs::write(get_path_for_db(&dir), encode_snapshot(&day)).unwrap();
+        let entry = bincode::serialize(&(String::from("2020-06-01"), v3_record(200))).unwrap();
+        let mut journal = header.encode();
+        journal.extend(&(entry.len() as u32).to_le_bytes());
+        journal.extend(entry);
+        fs::write(get_path_for_journal(&dir), journal).unwrap();
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(3));
+        assert_eq!(backup_path(&get_path_for_day(&dir, "2020-06-01"), 3).exists(), true);
+        assert_eq!(
+            SchemaHeader::detect(&fs::read(get_path_for_day(&dir, "2020-06-01")).unwrap()),
+            Some(SCHEMA_VERSION)
+        );
+
+        let db = DB::open(&dir, None).unwrap();
+
+        assert_eq!(
+            db.query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![record(100), record(200)]
+        );
+    }
+
+    #[test]
+    fn upgrades_v2_file_store() {
+        let dir = test_dir("migrate_v2");
+        let mut snapshot = Snapshot::new(HashMap::new());
+        snapshot.header.version = 2;
+        snapshot.days.insert(String::from("2020-06-01"), vec![record(100)]);
+        snapshot.days.insert(String::from("2020-06-02"), vec![record(90_000)]);
+
+        fs::write(get_path_for_db(&dir), encode_snapshot(&snapshot)).unwrap();
+        write_journal(&dir, 2, &(String::from("2020-06-01"), v3_record(200)));
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(2));
+        assert_eq!(get_path_for_day(&dir, "2020-06-02").exists(), true);
+
+        let db = DB::open(&dir, None).unwrap();
+
+        assert_eq!(db.query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(), vec![record(100), record(200)]);
+        assert_eq!(db.query_records(&NaiveDate::from_ymd(2020, 6, 2)).unwrap(), vec![record(90_000)]);
+    }
+
+    #[test]
+    fn upgrades_v4_journal() {
+        let dir = test_dir("migrate_v4");
+        let v4_record = v4::ActivityRecord {
+            machine: Some(String::from("laptop")),
+            ..v4::ActivityRecord::from(v3_record(100))
+        };
+        write_journal(&dir, 4, &(String::from("2020-06-01"), v4_record));
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(4));
+        assert_eq!(
+            DB::open(&dir, None).unwrap().query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![ActivityRecord { machine: Some(String::from("laptop")), ..record(100) }]
+        );
+    }
+
+    #[test]
+    fn upgrades_v5_journal() {
+        let dir = test_dir("migrate_v5");
+        let v5_record = v5::ActivityRecord {
+            tags: vec![String::from("on-call")].into_iter().collect(),
+            note: Some(String::from("paged")),
+            ..v5::ActivityRecord::from(v4::ActivityRecord::from(v3_record(100)))
+        };
+        write_journal(&dir, 5, &(String::from("2020-06-01"), v5_record));
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(5));
+        assert_eq!(
+            DB::open(&dir, None).unwrap().query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![ActivityRecord {
+                tags: vec![String::from("on-call")].into_iter().collect(),
+                note: Some(String::from("paged")),
+                ..record(100)
+            }]
+        );
+    }
+
+    #[test]
+    fn upgrades_v6_file_store() {
+        let dir = test_dir("migrate_v6");
+        let mut day = Snapshot::new(HashMap::new());
+        day.header.version = 6;
+        day.days.insert(String::from("2020-06-01"), vec![ActivityRecord { project: Some(String::from("client-x")), ..record(100) }]);
+
+        fs::create_dir_all(get_path_for_days(&dir)).unwrap();
+        fs::write(get_path_for_day(&dir, "2020-06-01"), encode_snapshot(&day)).unwrap();
+        write_journal(&dir, 6, &(String::from("2020-06-01"), v6_record(200)));
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(6));
+        assert_eq!(
+            DB::open(&dir, None).unwrap().query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![
+                ActivityRecord { project: Some(String::from("client-x")), ..record(100) },
+                ActivityRecord { project: Some(String::from("client-x")), ..record(200) },
+            ]
+        );
+    }
+
+    #[test]
+    fn upgrades_v7_file_store() {
+        let dir = test_dir("migrate_v7");
+        let private = |start_secs| ActivityRecord {
+            archetype: Archetype::Private,
+            productivity: ProductivityStatus::Neutral,
+            ..record(start_secs)
+        };
+        let mut day = Snapshot::new(HashMap::new());
+        day.header.version = 7;
+        day.days.insert(String::from("2020-06-01"), vec![private(100)]);
+        let v7_record = v7::ActivityRecord {
+            archetype: v7::Archetype::Private,
+            productivity: v0::ProductivityStatus::Neutral,
+            project: None,
+            ..v7::ActivityRecord::from(v6_record(200))
+        };
+
+        fs::create_dir_all(get_path_for_days(&dir)).unwrap();
+        fs::write(get_path_for_day(&dir, "2020-06-01"), encode_snapshot(&day)).unwrap();
+        write_journal(&dir, 7, &(String::from("2020-06-01"), v7_record));
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(7));
+        assert_eq!(
+            DB::open(&dir, None).unwrap().query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![private(100), private(200)]
+        );
+    }
+
+    #[test]
+    fn upgrades_v8_file_store() {
+        let dir = test_dir("migrate_v8");
+        let incognito = |start_secs| ActivityRecord {
+            archetype: Archetype::Incognito(String::from("code-oss"), String::from("Code")),
+            ..record(start_secs)
+        };
+        let mut day = Snapshot::new(HashMap::new());
+        day.header.version = 8;
+        day.days.insert(String::from("2020-06-01"), vec![incognito(100)]);
+        let v8_record = v8::ActivityRecord {
+            archetype: v8::Archetype::Incognito(String::from("code-oss"), String::from("Code")),
+            project: None,
+            ..v8::ActivityRecord::from(v7::ActivityRecord::from(v6_record(200)))
+        };
+
+        fs::create_dir_all(get_path_for_days(&dir)).unwrap();
+        fs::write(get_path_for_day(&dir, "2020-06-01"), encode_snapshot(&day)).unwrap();
+        write_journal(&dir, 8, &(String::from("2020-06-01"), v8_record));
+
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), Some(8));
+        assert_eq!(upgrade_file_store(&dir, None).unwrap(), None);
+        assert_eq!(
+            DB::open(&dir, None).unwrap().query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![incognito(100), incognito(200)]
+        );
+    }
+
+    #[test]
+    fn upgrades_v1_archive() {
+        let dir = test_dir("migrate_archive_v1");
+        let path = get_path_for_archive(&dir).join("2020-06.db");
+        let mut header = SchemaHeader::current();
+        header.version = 1;
+        let mut days = HashMap::new();
+        days.insert(String::from("2020-06-01"), vec![v3_record(100)]);
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, bincode::serialize(&v1::Snapshot { header, days }).unwrap()).unwrap();
+
+        assert_eq!(upgrade_archive(&dir, None).unwrap(), Some(1));
+        assert_eq!(backup_path(&path, 1).exists(), true);
+        assert_eq!(upgrade_archive(&dir, None).unwrap(), None);
+
+        let month: Month = bincode::deserialize(&fs::read(&path).unwrap()).unwrap();
+        let totals = &month.days["2020-06-01"].totals;
+
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].kind, ArchivedKind::Window(String::from("code-oss"), String::from("Code")));
+        assert_eq!(totals[0].secs, 60);
+    }
+
+    #[test]
+    fn refuses_newer_file_store() {
+        let dir = test_dir("migrate_newer");
+        let mut header = SchemaHeader::current();
+        header.version = SCHEMA_VERSION + 1;
+        fs::write(get_path_for_db(&dir), header.encode()).unwrap();
+
+        match upgrade_file_store(&dir, None) {
+            Err(RecordStoreError::UnsupportedSchema(version)) => assert_eq!(version, SCHEMA_VERSION + 1),
+            _ => panic!("store from a newer version must not be opened"),
+        }
+    }
+
+    /// Table of SQLite stores written before versioning
+    const SQLITE_V0_SCHEMA: &'static str = "
+        CREATE TABLE records (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            date         TEXT NOT NULL,
+            start_ms     INTEGER NOT NULL,
+            end_ms       INTEGER NOT NULL,
+            kind         TEXT NOT NULL,
+            title        TEXT,
+            app_name     TEXT,
+            app_class    TEXT,
+            productivity INTEGER NOT NULL,
+            activity     TEXT
+        );
+    ";
+
+    #[test]
+    fn upgrades_v0_sqlite_store() {
+        let dir = test_dir("migrate_sqlite_v0");
+        let path = get_path_for_sqlite(&dir);
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(SQLITE_V0_SCHEMA).unwrap();
+            conn.execute_batch("
+                INSERT INTO records (date, start_ms, end_ms, kind, title, app_name, app_class, productivity, activity)
+                VALUES ('2020-06-01', 100000, 160000, 'window', 'trackr', 'code-oss', 'Code', 1, 'coding');
+            ").unwrap();
+        }
+
+        let db = SqliteDB::open(&path, None).unwrap();
+
+        assert_eq!(backup_path(&path, 0).exists(), true);
+        assert_eq!(db.query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(), vec![record(100)]);
+    }
+
+    /// Writes an SQLite store of a given version with the fields and kinds of records that version knew,
+    /// returns the records it holds
+    fn write_sqlite_fixture(path: &Path, version: u32) -> Vec<ActivityRecord> {
+        let conn = Connection::open(path).unwrap();
+        let mut columns = vec!["date", "start_ms", "end_ms", "kind", "productivity", "title_id", "app_name_id", "app_class_id", "activity_id"];
+        let mut values = vec!["'2020-06-01'", "100000", "160000", "'window'", "1", "1", "2", "3", "4"];
+        let mut expected = record(100);
+
+        conn.execute_batch(SQLITE_V0_SCHEMA).unwrap();
+        for step in &SQLITE_MIGRATIONS[..version as usize] {
+            conn.execute_batch(step).unwrap();
+        }
+        conn.execute_batch(&format!("PRAGMA user_version = {};", version)).unwrap();
+
+        if version < 2 {
+            conn.execute_batch("
+                INSERT INTO records (date, start_ms, end_ms, kind, title, app_name, app_class, productivity, activity)
+                VALUES ('2020-06-01', 100000, 160000, 'window', 'trackr', 'code-oss', 'Code', 1, 'coding');
+            ").unwrap();
+            return vec![expected];
+        }
+
+        conn.execute_batch("
+            INSERT INTO strings (id, value) VALUES
+                (1, 'trackr'), (2, 'code-oss'), (3, 'Code'), (4, 'coding'),
+                (5, 'laptop'), (6, 'paged'), (7, 'on-call'), (8, 'client-x');
+        ").unwrap();
+        if version >= 4 {
+            columns.push("machine_id");
+            values.push("5");
+            expected.machine = Some(String::from("laptop"));
+        }
+        if version >= 5 {
+            columns.push("note_id");
+            values.push("6");
+            expected.note = Some(String::from("paged"));
+            expected.tags = vec![String::from("on-call")].into_iter().collect();
+        }
+        if version >= 6 {
+            columns.push("project_id");
+            values.push("8");
+            expected.project = Some(String::from("client-x"));
+        }
+        conn.execute_batch(&format!("INSERT INTO records ({}) VALUES ({});", columns.join(", "), values.join(", "))).unwrap();
+        if version >= 5 {
+            conn.execute_batch("INSERT INTO record_tags (record_id, tag_id) VALUES (1, 7);").unwrap();
+        }
+
+        let mut records = vec![expected];
+        if version >= 7 {
+            conn.execute_batch("
+                INSERT INTO records (date, start_ms, end_ms, kind, productivity)
+                VALUES ('2020-06-01', 160000, 220000, 'private', 0);
+            ").unwrap();
+            records.push(ActivityRecord {
+                archetype: Archetype::Private,
+                productivity: ProductivityStatus::Neutral,
+                ..record(160)
+            });
+        }
+        records
+    }
+
+    #[test]
+    fn upgrades_sqlite_stores_of_every_version() {
+        for version in 1..SCHEMA_VERSION {
+            let dir = test_dir(&format!("migrate_sqlite_v{}", version));
+            let path = get_path_for_sqlite(&dir);
+            let records = write_sqlite_fixture(&path, version);
+
+            let db = SqliteDB::open(&path, None).unwrap();
+
+            assert_eq!(backup_path(&path, version).exists(), true, "v{} is backed up", version);
+            assert_eq!(db.query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(), records, "v{} is upgraded", version);
+        }
+    }
+}
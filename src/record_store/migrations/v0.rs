@@ -0,0 +1,52 @@
+//! Schema of stores written before versioning was introduced.
+//!
+//! `records.db` is a bare bincode map of tracking dates to records
+//! and journal entries carry no header. Later schemas share the types defined here
+//! as long as they stayed the same: `Archetype` up to v6, `ProductivityStatus` up to v8.
+use super::{
+    super::ActivityRecord as CurrentActivityRecord,
+    v3::ActivityRecord as NextActivityRecord,
+};
+use std::{
+    collections::HashMap,
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ProductivityStatus {
+    Leisure(String),
+    Neutral,
+    Productive(String)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    pub time_range: (SystemTime, SystemTime),
+    pub productivity: ProductivityStatus,
+    pub archetype: Archetype,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Archetype {
+    ActiveWindow(String, String, String),
+    AFK,
+}
+
+pub type Days = HashMap<String, Vec<ActivityRecord>>;
+pub type JournalEntry = (String, ActivityRecord);
+
+impl From<ActivityRecord> for NextActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord {
+            time_range: record.time_range,
+            productivity: record.productivity,
+            archetype: record.archetype,
+        }
+    }
+}
+
+impl From<ActivityRecord> for CurrentActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord::from(record).into()
+    }
+}
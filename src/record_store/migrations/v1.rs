@@ -0,0 +1,13 @@
+//! Schema before the compact encoding of snapshots.
+//!
+//! `records.db` and archive month files are a bincode `Snapshot`
+//! with every record stored as is. Records themselves didn't change until v4, see `v3`.
+use super::SchemaHeader;
+use super::v3::ActivityRecord;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub header: SchemaHeader,
+    pub days: HashMap<String, Vec<ActivityRecord>>,
+}
@@ -0,0 +1,36 @@
+//! Records of schema v1 to v3, before they were stamped with a machine name.
+//!
+//! Snapshots of v2 and v3 are read by the compact encoding as they are,
+//! v1 snapshots and journal entries of v1 to v3 hold these records encoded with bincode.
+use super::{
+    super::ActivityRecord as CurrentActivityRecord,
+    v0::{Archetype, ProductivityStatus},
+    v4::ActivityRecord as NextActivityRecord,
+};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    pub time_range: (SystemTime, SystemTime),
+    pub productivity: ProductivityStatus,
+    pub archetype: Archetype,
+}
+
+pub type JournalEntry = (String, ActivityRecord);
+
+impl From<ActivityRecord> for NextActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord {
+            time_range: record.time_range,
+            productivity: record.productivity,
+            archetype: record.archetype,
+            machine: None,
+        }
+    }
+}
+
+impl From<ActivityRecord> for CurrentActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord::from(record).into()
+    }
+}
@@ -0,0 +1,45 @@
+//! Records of schema v5, with tags and a note but without a project.
+//!
+//! Snapshots of v5 are read by the compact encoding as they are,
+//! journal entries of v5 hold these records encoded with bincode.
+use super::{
+    super::ActivityRecord as CurrentActivityRecord,
+    v0::{Archetype, ProductivityStatus},
+    v6::ActivityRecord as NextActivityRecord,
+};
+use std::{
+    collections::BTreeSet,
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    pub time_range: (SystemTime, SystemTime),
+    pub productivity: ProductivityStatus,
+    pub archetype: Archetype,
+    pub machine: Option<String>,
+    pub tags: BTreeSet<String>,
+    pub note: Option<String>,
+}
+
+pub type JournalEntry = (String, ActivityRecord);
+
+impl From<ActivityRecord> for NextActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord {
+            time_range: record.time_range,
+            productivity: record.productivity,
+            archetype: record.archetype,
+            machine: record.machine,
+            tags: record.tags,
+            note: record.note,
+            project: None,
+        }
+    }
+}
+
+impl From<ActivityRecord> for CurrentActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord::from(record).into()
+    }
+}
@@ -0,0 +1,49 @@
+//! Records of schema v6, assigned to projects, before windows could be hidden by privacy rules.
+//!
+//! Snapshots of v6 are read by the compact encoding as they are,
+//! journal entries of v6 hold these records encoded with bincode.
+use super::{
+    super::ActivityRecord as CurrentActivityRecord,
+    v0::{Archetype, ProductivityStatus},
+    v7::{ActivityRecord as NextActivityRecord, Archetype as NextArchetype},
+};
+use std::{
+    collections::BTreeSet,
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    pub time_range: (SystemTime, SystemTime),
+    pub productivity: ProductivityStatus,
+    pub archetype: Archetype,
+    pub machine: Option<String>,
+    pub tags: BTreeSet<String>,
+    pub note: Option<String>,
+    pub project: Option<String>,
+}
+
+pub type JournalEntry = (String, ActivityRecord);
+
+impl From<ActivityRecord> for NextActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord {
+            time_range: record.time_range,
+            productivity: record.productivity,
+            archetype: match record.archetype {
+                Archetype::ActiveWindow(title, name, class) => NextArchetype::ActiveWindow(title, name, class),
+                Archetype::AFK => NextArchetype::AFK,
+            },
+            machine: record.machine,
+            tags: record.tags,
+            note: record.note,
+            project: record.project,
+        }
+    }
+}
+
+impl From<ActivityRecord> for CurrentActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord::from(record).into()
+    }
+}
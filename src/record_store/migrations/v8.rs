@@ -0,0 +1,61 @@
+//! Records of schema v8, with windows tracked in incognito mode, before archived days were summed up.
+//!
+//! Snapshots of v8 are read by the compact encoding as they are,
+//! journal entries of v8 hold these records encoded with bincode.
+//! Month files of the archive up to v8 hold records of archived days like day files do.
+use super::{
+    super::{
+        ActivityRecord as NextActivityRecord,
+        Archetype as NextArchetype,
+        ProductivityStatus as NextProductivityStatus,
+    },
+    v0::ProductivityStatus,
+};
+use std::{
+    collections::BTreeSet,
+    time::SystemTime,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Archetype {
+    ActiveWindow(String, String, String),
+    AFK,
+    Private,
+    Incognito(String, String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityRecord {
+    pub time_range: (SystemTime, SystemTime),
+    pub productivity: ProductivityStatus,
+    pub archetype: Archetype,
+    pub machine: Option<String>,
+    pub tags: BTreeSet<String>,
+    pub note: Option<String>,
+    pub project: Option<String>,
+}
+
+pub type JournalEntry = (String, ActivityRecord);
+
+impl From<ActivityRecord> for NextActivityRecord {
+    fn from(record: ActivityRecord) -> Self {
+        NextActivityRecord {
+            time_range: record.time_range,
+            productivity: match record.productivity {
+                ProductivityStatus::Leisure(name) => NextProductivityStatus::Leisure(name),
+                ProductivityStatus::Neutral => NextProductivityStatus::Neutral,
+                ProductivityStatus::Productive(name) => NextProductivityStatus::Productive(name),
+            },
+            archetype: match record.archetype {
+                Archetype::ActiveWindow(title, name, class) => NextArchetype::ActiveWindow(title, name, class),
+                Archetype::AFK => NextArchetype::AFK,
+                Archetype::Private => NextArchetype::Private,
+                Archetype::Incognito(name, class) => NextArchetype::Incognito(name, class),
+            },
+            machine: record.machine,
+            tags: record.tags,
+            note: record.note,
+            project: record.project,
+        }
+    }
+}
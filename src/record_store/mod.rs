@@ -1,18 +1,48 @@
+mod archive;
+mod backend;
+mod checkpoint;
 mod config;
+mod crypto;
 mod db;
+mod edit;
+mod encoding;
+mod fsck;
+mod journal;
+mod lock;
+mod merge;
+mod remote;
+mod migrations;
+mod retention;
+mod search;
+mod server_store;
+mod sqlite;
+mod stats_cache;
 mod store;
 mod tracker;
 mod utils;
 
-use std::time::SystemTime;
+use std::{
+    collections::BTreeSet,
+    time::SystemTime,
+};
 
 use crate::classifier::Classifiable;
 
 pub use self::config::*;
 pub use self::{
+    crypto::{HashKey, KeyParams, PassphraseSource},
+    edit::{is_valid_tag, AuditEntry, Edit, ForgetMode, MANUAL_APP_CLASS, MANUAL_APP_NAME},
+    fsck::FsckReport,
+    remote::{decode_client_name, Applied, OpBatch},
+    retention::{ArchivedKind, ArchivedTotal},
+    search::{format_duration, SearchHit, SearchQuery},
+    server_store::ServerStore,
     store::RecordStore,
     tracker::RecordTracker,
+    utils::RecordStoreError,
 };
+#[cfg(test)]
+pub use self::utils::test_dir;
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ProductivityStatus {
@@ -36,6 +66,19 @@ pub struct ActivityRecord {
     pub time_range: (SystemTime, SystemTime),
     pub productivity: ProductivityStatus,
     pub archetype: Archetype,
+    /// Name of the machine the record was tracked on,
+    /// absent for records tracked before machines were named
+    #[serde(default)]
+    pub machine: Option<String>,
+    /// Free-form labels, assigned by classifier rules or by hand
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Annotation added by hand
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Project the record is billed to, assigned by project rules of the config file
+    #[serde(default)]
+    pub project: Option<String>,
 }
 
 impl ActivityRecord {
@@ -45,6 +88,73 @@ impl ActivityRecord {
                 std::time::Duration::from_secs(0)
             )
     }
+
+    /// Checks whether two records share any moment in time
+    pub fn overlaps(&self, other: &ActivityRecord) -> bool {
+        self.time_range.0 < other.time_range.1 && other.time_range.0 < self.time_range.1
+    }
+
+    /// Checks whether a record picks up right where this one ends,
+    /// in the same window and with the same productivity
+    pub fn is_continued_by(&self, next: &ActivityRecord) -> bool {
+        self.time_range.1 == next.time_range.0
+            && self.archetype == next.archetype
+            && self.productivity == next.productivity
+            && self.machine == next.machine
+            && self.tags == next.tags
+            && self.note == next.note
+            && self.project == next.project
+    }
+
+    /// Gets the part of the record that falls within a given time range
+    pub fn clip(&self, start: SystemTime, end: SystemTime) -> Option<ActivityRecord> {
+        let clipped_start = std::cmp::max(self.time_range.0, start);
+        let clipped_end = std::cmp::min(self.time_range.1, end);
+
+        if clipped_start >= clipped_end {
+            return None;
+        }
+
+        let mut clipped = self.clone();
+        clipped.time_range = (clipped_start, clipped_end);
+        Some(clipped)
+    }
+}
+
+/// Narrows reports and exports down to records with a tag, of a project or both
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct RecordFilter {
+    pub tag: Option<String>,
+    pub project: Option<String>,
+}
+
+impl RecordFilter {
+    pub fn is_empty(&self) -> bool {
+        self.tag.is_none() && self.project.is_none()
+    }
+
+    pub fn matches(&self, record: &ActivityRecord) -> bool {
+        self.tag.as_ref().map_or(true, |tag| record.tags.contains(tag))
+            && self.project.as_ref().map_or(true, |project| record.project.as_ref() == Some(project))
+    }
+
+    /// Archived totals keep no tags, so filtering by a tag leaves them all out
+    pub fn matches_archived(&self, total: &ArchivedTotal) -> bool {
+        self.tag.is_none()
+            && self.project.as_ref().map_or(true, |project| total.project.as_ref() == Some(project))
+    }
+}
+
+impl std::fmt::Display for RecordFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(tag) = &self.tag {
+            write!(f, " tagged {:?}", tag)?;
+        }
+        if let Some(project) = &self.project {
+            write!(f, " of project {:?}", project)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -52,6 +162,20 @@ pub enum Archetype {
     /// Stores title, app name and app class in that order
     ActiveWindow(String, String, String),
     AFK,
+    /// Window hidden by a privacy rule of the config file
+    Private,
+    /// Stores app name and app class of a window tracked in incognito mode, its title is not recorded
+    Incognito(String, String),
+}
+
+impl Archetype {
+    /// Same window as if it was tracked in incognito mode, other archetypes have no title to drop
+    pub fn without_title(self) -> Archetype {
+        match self {
+            Archetype::ActiveWindow(_, name, class) => Archetype::Incognito(name, class),
+            other => other,
+        }
+    }
 }
 
 impl Classifiable for ActivityRecord {
@@ -62,4 +186,16 @@ impl Classifiable for ActivityRecord {
     fn assign_productivity(&mut self, productivity: ProductivityStatus) {
         self.productivity = productivity;
     }
+
+    fn assign_tags(&mut self, tags: BTreeSet<String>) {
+        self.tags.extend(tags);
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    fn assign_project(&mut self, project: Option<String>) {
+        self.project = project;
+    }
 }
@@ -0,0 +1,405 @@
+use super::{
+    backend::RecordBackend,
+    config::RemoteConfig,
+    crypto::{open_file, seal_file, Cipher},
+    db::TrackingDate,
+    utils::{get_path_for_outbox, write_atomically, RecordStoreError},
+    ActivityRecord,
+};
+use chrono::NaiveDate;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long writes stop trying to reach the server after it could not be reached
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT_MILLIS: u64 = 2_000;
+const READ_TIMEOUT_MILLIS: u64 = 10_000;
+/// Largest number of ops sent to the server in one request
+const BATCH_SIZE: usize = 500;
+
+/// Change made to the records of a tracking date
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    Push(ActivityRecord),
+    Replace(Vec<ActivityRecord>),
+    Remove,
+}
+
+/// Change numbered in the order a client made it, so that the server applies it exactly once
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RemoteOp {
+    pub seq: u64,
+    /// Tracking date as `YYYY-MM-DD`
+    pub date: String,
+    pub change: Change,
+}
+
+impl RemoteOp {
+    /// Gets the tracking date of the change, `None` if it is malformed
+    pub fn date(&self) -> Option<NaiveDate> {
+        TrackingDate(self.date.clone()).parse()
+    }
+}
+
+/// Ops a client sends to the server in one request
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OpBatch {
+    /// Changes whenever the client starts numbering its ops anew, e.g. after its outbox was lost
+    pub epoch: String,
+    pub ops: Vec<RemoteOp>,
+}
+
+/// Answer of the server to an `OpBatch`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Applied {
+    /// Number of the last op of the client that has been applied
+    pub applied: u64,
+}
+
+/// Encodes a client name so that it can be used as a URL path segment and a file name
+pub fn encode_client_name(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Decodes a client name encoded with `encode_client_name`, or any percent-encoded one
+pub fn decode_client_name(encoded: &str) -> Option<String> {
+    let mut bytes: Vec<u8> = vec![];
+    let mut rest = encoded.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok().filter(|name| !name.is_empty())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OutboxData {
+    epoch: String,
+    next_seq: u64,
+    ops: Vec<RemoteOp>,
+}
+
+/// Changes that are yet to reach the server, kept in `remote.outbox` until they do.
+///
+/// Stored as JSON, sealed like other store files when the store is encrypted
+struct Outbox {
+    path: PathBuf,
+    cipher: Option<Cipher>,
+    data: OutboxData,
+}
+
+impl Outbox {
+    fn open(dir_path: &Path, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        let path = get_path_for_outbox(dir_path);
+        let data = match path.exists() {
+            true => serde_json::from_slice(&open_file(fs::read(&path)?, cipher.as_ref())?)?,
+            false => OutboxData {
+                epoch: format!("{:016x}", rand::random::<u64>()),
+                next_seq: 1,
+                ops: vec![],
+            },
+        };
+
+        Ok(Self { path, cipher, data })
+    }
+
+    /// Numbers a change and adds it to the queue, see `save`
+    fn push(&mut self, date: &NaiveDate, change: Change) {
+        self.data.ops.push(RemoteOp {
+            seq: self.data.next_seq,
+            date: TrackingDate::from(date).0,
+            change,
+        });
+        self.data.next_seq += 1;
+    }
+
+    /// Drops ops the server has applied
+    fn acknowledge(&mut self, applied: u64) -> Result<(), RecordStoreError> {
+        self.data.ops.retain(|op| op.seq > applied);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), RecordStoreError> {
+        let bytes = seal_file(serde_json::to_vec(&self.data)?, self.cipher.as_ref());
+
+        write_atomically(&self.path, &bytes)?;
+        Ok(())
+    }
+}
+
+/// Sends changes to a trackr server over HTTP
+struct RemoteClient {
+    config: RemoteConfig,
+}
+
+impl RemoteClient {
+    /// Sends a batch of ops, returns the number of the last one the server applied
+    fn send(&self, batch: &OpBatch) -> Result<u64, RecordStoreError> {
+        let url = format!("{}/clients/{}/ops", self.config.url, encode_client_name(&self.config.client));
+        let response = ureq::post(&url)
+            .set("Authorization", &format!("Bearer {}", self.config.token))
+            .timeout_connect(CONNECT_TIMEOUT_MILLIS)
+            .timeout_read(READ_TIMEOUT_MILLIS)
+            .send_json(serde_json::to_value(batch)?);
+
+        if let Some(err) = response.synthetic_error() {
+            return Err(RecordStoreError::ServerUnreachable(err.to_string()));
+        }
+        if !response.ok() {
+            let status = response.status();
+
+            return Err(RecordStoreError::ServerRejected(status, response.into_string().unwrap_or_default()));
+        }
+
+        response.into_json_deserialize::<Applied>()
+            .map(|applied| applied.applied)
+            .map_err(|err| RecordStoreError::ServerUnreachable(err.to_string()))
+    }
+}
+
+/// Backend that keeps records in a local backend and mirrors every change to a trackr server.
+///
+/// Records are always read from the local backend. Changes are queued in an `Outbox`
+/// and sent right away; while the server can't be reached they pile up and are sent
+/// with the next change after `RETRY_INTERVAL`, or once the store is opened again
+pub struct RemoteBackend {
+    local: Box<dyn RecordBackend>,
+    client: RemoteClient,
+    outbox: Mutex<Outbox>,
+    retry_at: Mutex<Option<Instant>>,
+}
+
+impl RemoteBackend {
+    /// Wraps a local backend, queueing all its records if the store is mirrored for the first time.
+    ///
+    /// Fails if the server refuses the records, e.g. for a wrong token
+    pub fn open(
+        local: Box<dyn RecordBackend>,
+        config: RemoteConfig,
+        dir_path: &Path,
+        cipher: Option<Cipher>,
+    ) -> Result<Self, RecordStoreError> {
+        let is_new = !get_path_for_outbox(dir_path).exists();
+        let mut outbox = Outbox::open(dir_path, cipher)?;
+
+        if is_new {
+            for date in local.query_dates()? {
+                outbox.push(&date, Change::Replace(local.query_records(&date)?));
+            }
+            outbox.save()?;
+        }
+
+        let backend = Self {
+            local,
+            client: RemoteClient { config },
+            outbox: Mutex::new(outbox),
+            retry_at: Mutex::new(None),
+        };
+
+        match backend.sync() {
+            Err(RecordStoreError::ServerUnreachable(_)) | Ok(_) => Ok(backend),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Queues a change and sends the queue unless the server was unreachable a moment ago
+    fn queue(&self, date: &NaiveDate, change: Change) -> Result<(), RecordStoreError> {
+        let mut outbox = self.outbox.lock().unwrap();
+
+        outbox.push(date, change);
+        outbox.save()?;
+        drop(outbox);
+
+        let may_retry = self.retry_at.lock().unwrap().map_or(true, |retry_at| Instant::now() >= retry_at);
+        if may_retry {
+            // Changes that could not be sent stay queued
+            let _ = self.sync();
+        }
+        Ok(())
+    }
+}
+
+impl RecordBackend for RemoteBackend {
+    fn push_record(&self, date: &NaiveDate, record: ActivityRecord) -> Result<(), RecordStoreError> {
+        self.local.push_record(date, record.clone())?;
+        self.queue(date, Change::Push(record))
+    }
+
+    fn replace_records(&self, date: &NaiveDate, records: Vec<ActivityRecord>) -> Result<(), RecordStoreError> {
+        self.local.replace_records(date, records.clone())?;
+        self.queue(date, Change::Replace(records))
+    }
+
+    fn remove_records(&self, date: &NaiveDate) -> Result<(), RecordStoreError> {
+        self.local.remove_records(date)?;
+        self.queue(date, Change::Remove)
+    }
+
+    fn query_records(&self, date: &NaiveDate) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        self.local.query_records(date)
+    }
+
+    fn query_records_since(&self, date: &NaiveDate, index: usize) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        self.local.query_records_since(date, index)
+    }
+
+    fn query_dates(&self) -> Result<Vec<NaiveDate>, RecordStoreError> {
+        self.local.query_dates()
+    }
+
+    fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        self.local.rekey(cipher.clone())?;
+
+        let outbox = self.outbox.get_mut().unwrap();
+        outbox.cipher = cipher;
+        outbox.save()
+    }
+
+    fn sync(&self) -> Result<usize, RecordStoreError> {
+        let mut outbox = self.outbox.lock().unwrap();
+
+        // An empty batch is sent too, so that a wrong token shows up before there is anything to send
+        loop {
+            let pending = outbox.data.ops.len();
+            let batch = OpBatch {
+                epoch: outbox.data.epoch.clone(),
+                ops: outbox.data.ops.iter().take(BATCH_SIZE).cloned().collect(),
+            };
+
+            match self.client.send(&batch) {
+                Ok(applied) => outbox.acknowledge(applied)?,
+                Err(err) => {
+                    *self.retry_at.lock().unwrap() = Some(Instant::now() + RETRY_INTERVAL);
+                    return Err(err);
+                }
+            }
+
+            match outbox.data.ops.len() {
+                0 => break,
+                left if left == pending => {
+                    return Err(RecordStoreError::ServerRejected(200, String::from("No changes were applied")));
+                },
+                _ => {},
+            }
+        }
+
+        *self.retry_at.lock().unwrap() = None;
+        Ok(outbox.data.ops.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        record_store::{db::DB, utils::test_dir, Archetype, ProductivityStatus, ServerStore},
+        server::Server,
+    };
+    use std::{
+        collections::BTreeSet,
+        net::{SocketAddr, TcpListener},
+        sync::mpsc,
+        thread,
+        time::UNIX_EPOCH,
+    };
+
+    const TOKEN: &'static str = "secret";
+
+    fn start_server(address: SocketAddr, name: &'static str) -> SocketAddr {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let store = ServerStore::open(&test_dir(name)).unwrap();
+            let server = Server::bind(&address.to_string(), store, String::from(TOKEN)).unwrap();
+
+            sender.send(server.address()).unwrap();
+            server.run();
+        });
+        receiver.recv().unwrap()
+    }
+
+    fn config(address: SocketAddr, token: &str) -> RemoteConfig {
+        RemoteConfig {
+            url: format!("http://{}", address),
+            token: String::from(token),
+            client: String::from("Home computer"),
+        }
+    }
+
+    fn record(start_secs: u64) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (UNIX_EPOCH + Duration::from_secs(start_secs), UNIX_EPOCH + Duration::from_secs(start_secs + 60)),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::ActiveWindow(String::from("main.rs"), String::from("code"), String::from("Code")),
+            machine: Some(String::from("Home computer")),
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    fn server_records(address: SocketAddr, date: &str) -> Vec<ActivityRecord> {
+        ureq::get(&format!("http://{}/clients/Home%20computer/days/{}", address, date))
+            .set("Authorization", &format!("Bearer {}", TOKEN))
+            .call()
+            .into_json_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn mirrors_changes_to_server() {
+        let address = start_server("127.0.0.1:0".parse().unwrap(), "remote_server");
+        let dir = test_dir("remote_client");
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+
+        match RemoteBackend::open(Box::new(DB::memory().unwrap()), config(address, "wrong"), &dir, None) {
+            Err(RecordStoreError::ServerRejected(401, _)) => {},
+            _ => panic!("server must refuse a wrong token"),
+        }
+
+        let backend = RemoteBackend::open(Box::new(DB::memory().unwrap()), config(address, TOKEN), &dir, None).unwrap();
+        backend.push_record(&date, record(1_591_005_600)).unwrap();
+        backend.push_record(&date, record(1_591_005_660)).unwrap();
+        backend.replace_records(&date, vec![record(1_591_005_660)]).unwrap();
+
+        assert_eq!(server_records(address, "2020-06-01"), vec![record(1_591_005_660)]);
+        assert_eq!(backend.outbox.lock().unwrap().data.ops.len(), 0);
+    }
+
+    #[test]
+    fn buffers_changes_while_offline() {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let dir = test_dir("remote_offline");
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let open = || RemoteBackend::open(Box::new(DB::memory().unwrap()), config(address, TOKEN), &dir, None).unwrap();
+
+        let backend = open();
+        backend.push_record(&date, record(1_591_005_600)).unwrap();
+        backend.push_record(&date, record(1_591_005_660)).unwrap();
+        drop(backend);
+        assert_eq!(open().outbox.lock().unwrap().data.ops.len(), 2);
+
+        start_server(address, "remote_offline_server");
+        let backend = open();
+
+        assert_eq!(backend.sync().unwrap(), 0);
+        assert_eq!(server_records(address, "2020-06-01"), vec![record(1_591_005_600), record(1_591_005_660)]);
+    }
+}
@@ -0,0 +1,179 @@
+use super::{ActivityRecord, Archetype, ProductivityStatus};
+use std::time::SystemTime;
+
+/// Joins records that continue each other into one, e.g. after AFK status flapped.
+///
+/// Records are expected to be ordered by start time
+pub fn merge_adjacent(records: Vec<ActivityRecord>) -> Vec<ActivityRecord> {
+    let mut merged: Vec<ActivityRecord> = vec![];
+
+    for record in records {
+        match merged.last_mut() {
+            Some(last) if last.is_continued_by(&record) => last.time_range.1 = record.time_range.1,
+            _ => merged.push(record),
+        }
+    }
+
+    merged
+}
+
+/// Archetype of archived records, windows keep their app but not their title.
+///
+/// Variants other than `Window` are laid out as in `Archetype`
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ArchivedKind {
+    /// Stores app name and app class of a window that was tracked with its title
+    Window(String, String),
+    AFK,
+    Private,
+    /// Stores app name and app class of a window tracked in incognito mode
+    Incognito(String, String),
+}
+
+impl ArchivedKind {
+    /// App name and app class, `None` for time spent away or on private windows
+    pub fn app(&self) -> Option<(&String, &String)> {
+        match self {
+            ArchivedKind::Window(name, class) | ArchivedKind::Incognito(name, class) => Some((name, class)),
+            ArchivedKind::AFK | ArchivedKind::Private => None,
+        }
+    }
+}
+
+impl From<Archetype> for ArchivedKind {
+    fn from(archetype: Archetype) -> Self {
+        match archetype {
+            Archetype::ActiveWindow(_, name, class) => ArchivedKind::Window(name, class),
+            Archetype::AFK => ArchivedKind::AFK,
+            Archetype::Private => ArchivedKind::Private,
+            Archetype::Incognito(name, class) => ArchivedKind::Incognito(name, class),
+        }
+    }
+}
+
+/// Time spent on one activity during an archived day, all that is kept of its records.
+///
+/// Records of the same app, activity, machine and project are summed up,
+/// window titles and tags are dropped
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedTotal {
+    pub productivity: ProductivityStatus,
+    pub kind: ArchivedKind,
+    pub machine: Option<String>,
+    pub project: Option<String>,
+    pub secs: u64,
+}
+
+impl ArchivedTotal {
+    /// Whether time of both totals is spent on the same thing and adds up to a single total
+    fn is_same_activity(&self, other: &ArchivedTotal) -> bool {
+        self.kind == other.kind
+            && self.productivity == other.productivity
+            && self.machine == other.machine
+            && self.project == other.project
+    }
+}
+
+/// Day past the retention window
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedDay {
+    /// Time ranges whose records are summed up, so that archiving them again doesn't count them twice
+    pub spans: Vec<(SystemTime, SystemTime)>,
+    pub totals: Vec<ArchivedTotal>,
+}
+
+impl ArchivedDay {
+    /// Adds up records of the day, those overlapping time that was already archived are skipped
+    pub fn add_records(&mut self, records: Vec<ActivityRecord>) {
+        for record in records {
+            let (start, end) = record.time_range;
+
+            if self.spans.iter().any(|span| span.0 < end && start < span.1) {
+                continue;
+            }
+            self.spans.push(record.time_range);
+
+            let secs = record.duration().as_secs();
+            let summed = ArchivedTotal {
+                productivity: record.productivity,
+                kind: ArchivedKind::from(record.archetype),
+                machine: record.machine,
+                project: record.project,
+                secs: 0,
+            };
+
+            match self.totals.iter_mut().find(|total| total.is_same_activity(&summed)) {
+                Some(total) => total.secs += secs,
+                None => self.totals.push(ArchivedTotal { secs, ..summed }),
+            }
+        }
+
+        self.spans.sort();
+        let mut spans: Vec<(SystemTime, SystemTime)> = vec![];
+        for span in self.spans.drain(..) {
+            match spans.last_mut() {
+                Some(last) if last.1 >= span.0 => last.1 = std::cmp::max(last.1, span.1),
+                _ => spans.push(span),
+            }
+        }
+        self.spans = spans;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeSet, time::{Duration, UNIX_EPOCH}};
+
+    fn record(start_secs: u64, end_secs: u64, title: &str) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_secs(start_secs),
+                UNIX_EPOCH + Duration::from_secs(end_secs),
+            ),
+            productivity: ProductivityStatus::Productive(String::from("coding")),
+            archetype: Archetype::ActiveWindow(String::from(title), String::from("code-oss"), String::from("Code")),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn merges_only_continuous_records() {
+        let records = vec![
+            record(0, 10, "main.rs"),
+            record(10, 20, "main.rs"),
+            record(20, 30, "lib.rs"),
+            record(40, 50, "lib.rs"),
+        ];
+
+        assert_eq!(merge_adjacent(records.clone()), vec![
+            record(0, 20, "main.rs"),
+            record(20, 30, "lib.rs"),
+            record(40, 50, "lib.rs"),
+        ]);
+    }
+
+    #[test]
+    fn sums_up_archived_time_once() {
+        let mut day = ArchivedDay::default();
+
+        day.add_records(vec![record(0, 10, "main.rs"), record(10, 20, "lib.rs"), record(40, 50, "main.rs")]);
+        day.add_records(vec![record(40, 50, "main.rs"), record(60, 65, "main.rs")]);
+
+        assert_eq!(day.spans, vec![
+            (UNIX_EPOCH, UNIX_EPOCH + Duration::from_secs(20)),
+            (UNIX_EPOCH + Duration::from_secs(40), UNIX_EPOCH + Duration::from_secs(50)),
+            (UNIX_EPOCH + Duration::from_secs(60), UNIX_EPOCH + Duration::from_secs(65)),
+        ]);
+        assert_eq!(day.totals, vec![ArchivedTotal {
+            productivity: ProductivityStatus::Productive(String::from("coding")),
+            kind: ArchivedKind::Window(String::from("code-oss"), String::from("Code")),
+            machine: None,
+            project: None,
+            secs: 35,
+        }]);
+    }
+}
@@ -0,0 +1,401 @@
+use super::{
+    crypto::{open_file, seal_file, Cipher},
+    db::TrackingDate,
+    store::RecordStore,
+    utils::{
+        get_path_for_search_index, get_path_for_search_stale, note_changed_day, read_changed_days,
+        write_atomically, RecordStoreError,
+    },
+    ActivityRecord, Archetype,
+};
+use chrono::{DateTime, Local, NaiveDate};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Bumped whenever the layout of the index changes, indexes of other versions are rebuilt
+const INDEX_VERSION: u32 = 1;
+
+/// Splits text into lowercase words, e.g. `invoice-2291.pdf` into `invoice`, `2291` and `pdf`
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Texts of a record that are searched: window title, app name and app class
+fn searchable_fields(record: &ActivityRecord) -> Vec<&str> {
+    match &record.archetype {
+        Archetype::ActiveWindow(title, name, class) => vec![title, name, class],
+        Archetype::Incognito(name, class) => vec![name, class],
+        Archetype::AFK | Archetype::Private => vec![],
+    }
+}
+
+/// Words a record must contain and phrases that must appear in one of its fields as is
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SearchQuery {
+    terms: Vec<String>,
+    phrases: Vec<Vec<String>>,
+}
+
+impl SearchQuery {
+    /// Parses a query such as `invoice "q3 report"`, quoted parts are phrases
+    pub fn parse(query: &str) -> Self {
+        let mut result = Self::default();
+
+        for (index, part) in query.split('"').enumerate() {
+            let tokens = tokenize(part);
+
+            if index % 2 == 1 && tokens.len() > 1 {
+                result.phrases.push(tokens);
+            } else {
+                result.terms.extend(tokens);
+            }
+        }
+
+        result.terms.sort();
+        result.terms.dedup();
+        result
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.phrases.is_empty()
+    }
+
+    /// Every word a matching record contains
+    fn tokens(&self) -> HashSet<&String> {
+        self.terms.iter().chain(self.phrases.iter().flatten()).collect()
+    }
+
+    pub fn matches(&self, record: &ActivityRecord) -> bool {
+        let fields: Vec<Vec<String>> = searchable_fields(record).into_iter().map(tokenize).collect();
+
+        self.terms.iter().all(|term| fields.iter().any(|tokens| tokens.contains(term)))
+            && self.phrases.iter().all(|phrase| {
+                fields.iter().any(|tokens| tokens.windows(phrase.len()).any(|window| window == phrase.as_slice()))
+            })
+    }
+}
+
+/// Record found by a search along with the tracking date it is stored under
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SearchHit {
+    pub date: NaiveDate,
+    pub record: ActivityRecord,
+}
+
+impl fmt::Display for SearchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start, end) = self.record.time_range;
+        let (title, name) = match &self.record.archetype {
+            Archetype::ActiveWindow(title, name, _) => (title.as_str(), name.as_str()),
+            Archetype::AFK => ("", "AFK"),
+            Archetype::Private => ("", "Private"),
+            Archetype::Incognito(name, _) => ("(incognito)", name.as_str()),
+        };
+
+        write!(
+            f, "{} {}-{} {:>7}  {}  {}",
+            self.date,
+            DateTime::<Local>::from(start).format("%H:%M"),
+            DateTime::<Local>::from(end).format("%H:%M"),
+            format_duration(self.record.duration()),
+            name,
+            title,
+        )
+    }
+}
+
+/// Formats a duration as hours and minutes, e.g. `2h 05m`
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+
+    match minutes / 60 {
+        0 => format!("{}m", minutes),
+        hours => format!("{}h {:02}m", hours, minutes % 60),
+    }
+}
+
+/// Words of a tracking day mapped to positions of the records containing them
+#[derive(Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+struct DayIndex {
+    tokens: HashMap<String, Vec<u32>>,
+}
+
+impl DayIndex {
+    fn of(records: &[ActivityRecord]) -> Self {
+        let mut tokens: HashMap<String, Vec<u32>> = HashMap::new();
+
+        for (position, record) in records.iter().enumerate() {
+            let words: HashSet<String> = searchable_fields(record).into_iter().flat_map(tokenize).collect();
+
+            for word in words {
+                tokens.entry(word).or_insert_with(Vec::new).push(position as u32);
+            }
+        }
+        Self { tokens }
+    }
+
+    /// Positions of records that contain all given words
+    fn candidates(&self, words: &HashSet<&String>) -> Vec<u32> {
+        let mut result: Option<Vec<u32>> = None;
+
+        for word in words {
+            let positions = match self.tokens.get(*word) {
+                Some(positions) => positions,
+                None => return vec![],
+            };
+
+            result = Some(match result {
+                Some(found) => found.into_iter().filter(|position| positions.contains(position)).collect(),
+                None => positions.clone(),
+            });
+        }
+        result.unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexData {
+    version: u32,
+    /// Length of `search.stale` that was applied to the index
+    stale_offset: u64,
+    days: BTreeMap<String, DayIndex>,
+}
+
+/// Full-text index of window titles, app names and classes, kept in `search.index`.
+///
+/// Days are indexed once and indexed again only when they change. Pushing records
+/// doesn't touch the index, the latest indexed days are refreshed on every search instead.
+/// Other changes are noted in `search.stale` by `RecordStore`, so the index can be
+/// refreshed by any process reading the store. The index is sealed if the store is encrypted
+pub struct SearchIndex {
+    dir: Option<PathBuf>,
+    cipher: Option<Cipher>,
+}
+
+impl SearchIndex {
+    pub fn in_dir(dir_path: &Path, cipher: Option<Cipher>) -> Self {
+        Self {
+            dir: Some(dir_path.to_owned()),
+            cipher,
+        }
+    }
+
+    /// Index of a store kept in memory, built anew for every search
+    pub fn memory() -> Self {
+        Self { dir: None, cipher: None }
+    }
+
+    /// Notes that records of a day have changed
+    pub fn invalidate(&self, date: &NaiveDate) -> Result<(), RecordStoreError> {
+        match &self.dir {
+            Some(dir) => note_changed_day(&get_path_for_search_stale(dir), date),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops the index, it is built from scratch on the next search
+    pub fn clear(&self) -> Result<(), RecordStoreError> {
+        if let Some(dir) = &self.dir {
+            for path in &[get_path_for_search_index(dir), get_path_for_search_stale(dir)] {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the index, it is built again with a new key on the next search
+    pub fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        self.clear()?;
+        self.cipher = cipher;
+        Ok(())
+    }
+
+    /// Finds records matching a query within an optional range of tracking dates, latest first
+    pub fn search(
+        &self,
+        store: &RecordStore,
+        query: &SearchQuery,
+        from: Option<&NaiveDate>,
+        to: Option<&NaiveDate>,
+    ) -> Result<Vec<SearchHit>, RecordStoreError> {
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let data = self.refresh(store)?;
+        let words = query.tokens();
+        let first_key = from.map(|date| TrackingDate::from(date).0).unwrap_or_default();
+        let mut hits: Vec<SearchHit> = vec![];
+
+        for (key, day) in data.days.range(first_key..) {
+            let date = match TrackingDate(key.clone()).parse() {
+                Some(date) if to.map_or(true, |to| date <= *to) => date,
+                _ => continue,
+            };
+            let candidates = day.candidates(&words);
+
+            if candidates.is_empty() {
+                continue;
+            }
+
+            let records = store.query_records_by_date(&date)?;
+            for position in candidates {
+                match records.get(position as usize) {
+                    Some(record) if query.matches(record) => hits.push(SearchHit { date, record: record.clone() }),
+                    _ => {},
+                }
+            }
+        }
+
+        hits.sort_by(|a, b| b.record.time_range.0.cmp(&a.record.time_range.0));
+        Ok(hits)
+    }
+
+    /// Indexes days that are new or changed since the last search and saves the index
+    fn refresh(&self, store: &RecordStore) -> Result<IndexData, RecordStoreError> {
+        let mut data = self.load();
+        let stale_offset = data.stale_offset;
+        let stale = self.read_stale(&mut data)?;
+        let dates = store.query_dates()?;
+        let keys: HashSet<String> = dates.iter().map(|date| TrackingDate::from(date).0).collect();
+        // Records are pushed to the latest days, so those are indexed again every time
+        let open_since = data.days.keys().next_back()
+            .and_then(|key| TrackingDate(key.clone()).parse())
+            .map(|date| date.pred());
+        let day_count = data.days.len();
+
+        data.days.retain(|key, _| keys.contains(key) && !stale.contains(key));
+        let mut is_changed = data.days.len() != day_count || data.stale_offset != stale_offset;
+
+        for date in dates {
+            let key = TrackingDate::from(&date).0;
+
+            if data.days.contains_key(&key) && open_since.map_or(false, |open_since| date < open_since) {
+                continue;
+            }
+
+            let records = match store.query_records_by_date(&date) {
+                Err(RecordStoreError::NoDataOnDate(_)) => vec![],
+                records => records?,
+            };
+            let day = DayIndex::of(&records);
+
+            if data.days.get(&key) != Some(&day) {
+                data.days.insert(key, day);
+                is_changed = true;
+            }
+        }
+
+        if is_changed {
+            self.save(&data)?;
+        }
+        Ok(data)
+    }
+
+    /// Reads the index, a missing or outdated one is built from scratch
+    fn load(&self) -> IndexData {
+        let path = match &self.dir {
+            Some(dir) => get_path_for_search_index(dir),
+            None => return IndexData::default(),
+        };
+
+        fs::read(&path).ok()
+            .and_then(|bytes| open_file(bytes, self.cipher.as_ref()).ok())
+            .and_then(|bytes| bincode::deserialize::<IndexData>(&bytes).ok())
+            .filter(|data| data.version == INDEX_VERSION)
+            .unwrap_or_default()
+    }
+
+    fn save(&self, data: &IndexData) -> Result<(), RecordStoreError> {
+        if let Some(dir) = &self.dir {
+            let bytes = seal_file(bincode::serialize(data)?, self.cipher.as_ref());
+
+            write_atomically(&get_path_for_search_index(dir), &bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Gets days noted as changed since the index was last refreshed
+    fn read_stale(&self, data: &mut IndexData) -> Result<HashSet<String>, RecordStoreError> {
+        let path = match &self.dir {
+            Some(dir) => get_path_for_search_stale(dir),
+            None => return Ok(HashSet::new()),
+        };
+        let mut offset = data.stale_offset;
+
+        let stale = read_changed_days(&path, &mut offset)?;
+        // Notes were dropped since the index was saved, nothing tells which days changed
+        if stale.is_none() {
+            *data = IndexData::default();
+        }
+        data.version = INDEX_VERSION;
+        data.stale_offset = offset;
+        Ok(stale.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{utils::test_dir, ProductivityStatus, RecordStoreConfig};
+    use chrono::{Local, TimeZone};
+    use std::{collections::BTreeSet, time::SystemTime};
+
+    fn window(day: u32, hour: u32, title: &str) -> ActivityRecord {
+        let start = SystemTime::from(Local.ymd(2020, 6, day).and_hms(hour, 0, 0));
+
+        ActivityRecord {
+            time_range: (start, start + std::time::Duration::from_secs(600)),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::ActiveWindow(String::from(title), String::from("evince"), String::from("Evince")),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn parses_terms_and_phrases() {
+        let query = SearchQuery::parse(r#"Evince "invoice-2291.pdf" "q3""#);
+
+        assert_eq!(query.terms, vec![String::from("evince"), String::from("q3")]);
+        assert_eq!(query.phrases, vec![vec![String::from("invoice"), String::from("2291"), String::from("pdf")]]);
+        assert_eq!(query.matches(&window(1, 9, "q3 - invoice-2291.pdf")), true);
+        assert_eq!(query.matches(&window(1, 9, "q3 - invoice-2291 draft.pdf")), false);
+    }
+
+    #[test]
+    fn finds_records_of_changed_days() {
+        let store = RecordStore::new(RecordStoreConfig::in_dir(test_dir("search"))).unwrap();
+        let query = SearchQuery::parse("\"invoice-2291.pdf\"");
+        let first = NaiveDate::from_ymd(2020, 6, 1);
+
+        store.push_record(window(1, 9, "invoice-2291.pdf")).unwrap();
+        store.push_record(window(1, 10, "invoice-2292.pdf")).unwrap();
+        store.push_record(window(3, 9, "invoice-2291.pdf - Evince")).unwrap();
+
+        let hits = store.search(&query, None, None).unwrap();
+        assert_eq!(hits.iter().map(|hit| hit.record.clone()).collect::<Vec<_>>(), vec![
+            window(3, 9, "invoice-2291.pdf - Evince"),
+            window(1, 9, "invoice-2291.pdf"),
+        ]);
+        assert_eq!(store.search(&query, Some(&first), Some(&first)).unwrap().len(), 1);
+
+        store.replace_records(&first, vec![window(1, 10, "invoice-2292.pdf")]).unwrap();
+        store.push_record(window(4, 9, "invoice-2291.pdf")).unwrap();
+
+        let dates: Vec<NaiveDate> = store.search(&query, None, None).unwrap().into_iter().map(|hit| hit.date).collect();
+        assert_eq!(dates, vec![NaiveDate::from_ymd(2020, 6, 4), NaiveDate::from_ymd(2020, 6, 3)]);
+    }
+}
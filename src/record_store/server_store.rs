@@ -0,0 +1,150 @@
+use super::{
+    backend::RecordBackend,
+    db::DB,
+    lock::StoreLock,
+    remote::{decode_client_name, encode_client_name, Change, OpBatch},
+    utils::{get_dir, get_path_for_clients, write_atomically, RecordStoreError},
+    ActivityRecord,
+};
+use chrono::NaiveDate;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Which ops of a client have been applied, kept in `progress.json` of its directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Progress {
+    epoch: String,
+    applied: u64,
+}
+
+struct ClientStore {
+    db: DB,
+    dir: PathBuf,
+    progress: Progress,
+}
+
+impl ClientStore {
+    fn open(dir: PathBuf) -> Result<Self, RecordStoreError> {
+        fs::create_dir_all(&dir)?;
+
+        let progress_path = dir.join("progress.json");
+        let progress = match progress_path.exists() {
+            true => serde_json::from_slice(&fs::read(&progress_path)?)?,
+            false => Progress::default(),
+        };
+
+        Ok(Self {
+            db: DB::open(&dir, None)?,
+            dir,
+            progress,
+        })
+    }
+
+    fn save_progress(&self) -> Result<(), RecordStoreError> {
+        write_atomically(&self.dir.join("progress.json"), &serde_json::to_vec(&self.progress)?)?;
+        Ok(())
+    }
+}
+
+/// Records a trackr server keeps for its clients.
+///
+/// Every client gets a file store of its own in `clients/<name>`, so clients never
+/// overwrite each other's records. Records are stored as the clients send them,
+/// unencrypted
+pub struct ServerStore {
+    dir: PathBuf,
+    clients: Mutex<HashMap<String, ClientStore>>,
+    _lock: StoreLock,
+}
+
+impl ServerStore {
+    /// Opens the records kept in a directory, fails if another server uses it
+    pub fn open(dir: &Path) -> Result<Self, RecordStoreError> {
+        get_dir(dir)?;
+
+        let lock = StoreLock::acquire(dir)?;
+        fs::create_dir_all(get_path_for_clients(dir))?;
+
+        Ok(Self {
+            dir: dir.to_owned(),
+            clients: Mutex::new(HashMap::new()),
+            _lock: lock,
+        })
+    }
+
+    /// Lists names of the clients that have sent records
+    pub fn clients(&self) -> Result<Vec<String>, RecordStoreError> {
+        let mut names: Vec<String> = vec![];
+
+        for entry in fs::read_dir(get_path_for_clients(&self.dir))? {
+            let entry = entry?;
+
+            if let Some(name) = entry.file_name().to_str().and_then(decode_client_name) {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Whether a client has sent records
+    pub fn has_client(&self, client: &str) -> bool {
+        get_path_for_clients(&self.dir).join(encode_client_name(client)).is_dir()
+    }
+
+    fn with_client<T, F>(&self, client: &str, f: F) -> Result<T, RecordStoreError>
+    where
+        F: FnOnce(&mut ClientStore) -> Result<T, RecordStoreError>,
+    {
+        let mut clients = self.clients.lock().unwrap();
+
+        if !clients.contains_key(client) {
+            let dir = get_path_for_clients(&self.dir).join(encode_client_name(client));
+            clients.insert(String::from(client), ClientStore::open(dir)?);
+        }
+        f(clients.get_mut(client).unwrap())
+    }
+
+    /// Applies ops of a client that haven't been applied yet, in order.
+    ///
+    /// Returns the number of the last applied op. Ops with malformed dates are skipped
+    pub fn apply(&self, client: &str, batch: OpBatch) -> Result<u64, RecordStoreError> {
+        self.with_client(client, |store| {
+            if store.progress.epoch != batch.epoch {
+                store.progress = Progress {
+                    epoch: batch.epoch.clone(),
+                    applied: 0,
+                };
+            }
+
+            for op in batch.ops {
+                if op.seq <= store.progress.applied {
+                    continue;
+                }
+                if let Some(date) = op.date() {
+                    match op.change {
+                        Change::Push(record) => store.db.push_record(&date, record)?,
+                        Change::Replace(records) => store.db.replace_records(&date, records)?,
+                        Change::Remove => store.db.remove_records(&date)?,
+                    }
+                }
+                store.progress.applied = op.seq;
+            }
+
+            store.save_progress()?;
+            Ok(store.progress.applied)
+        })
+    }
+
+    pub fn query_dates(&self, client: &str) -> Result<Vec<NaiveDate>, RecordStoreError> {
+        self.with_client(client, |store| store.db.query_dates())
+    }
+
+    pub fn query_records(&self, client: &str, date: &NaiveDate) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        self.with_client(client, |store| store.db.query_records(date))
+    }
+}
@@ -0,0 +1,570 @@
+use super::{
+    backend::RecordBackend,
+    crypto::Cipher,
+    db::TrackingDate,
+    encoding::malformed,
+    migrations::{report_upgrade, upgrade_sqlite, SCHEMA_VERSION},
+    utils::{from_millis, to_millis, RecordStoreError},
+    ActivityRecord, Archetype, ProductivityStatus,
+};
+use chrono::NaiveDate;
+use rusqlite::{params, types::Value, Connection, OpenFlags, Row, NO_PARAMS};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    path::Path,
+    time::Duration,
+};
+
+const KIND_WINDOW: &'static str = "window";
+const KIND_AFK: &'static str = "afk";
+const KIND_PRIVATE: &'static str = "private";
+const KIND_INCOGNITO: &'static str = "incognito";
+
+/// How long to wait for another connection to finish writing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Current schema, used as is for new databases.
+///
+/// Titles, app names, classes, activities, machines, notes, projects and tags are interned
+/// in the `strings` table and records refer to them by id, tags through `record_tags`. `records_view` joins them back so that the
+/// database can be inspected with the `sqlite3` shell or any other standard tool.
+/// In encrypted stores strings hold sealed blobs instead of text
+const SCHEMA: &'static str = "
+    CREATE TABLE strings (
+        id    INTEGER PRIMARY KEY,
+        value NOT NULL
+    );
+    CREATE INDEX strings_by_value ON strings (value);
+
+    CREATE TABLE records (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        date         TEXT NOT NULL,
+        start_ms     INTEGER NOT NULL,
+        end_ms       INTEGER NOT NULL,
+        kind         TEXT NOT NULL,
+        title_id     INTEGER REFERENCES strings (id),
+        app_name_id  INTEGER REFERENCES strings (id),
+        app_class_id INTEGER REFERENCES strings (id),
+        productivity INTEGER NOT NULL,
+        activity_id  INTEGER REFERENCES strings (id),
+        machine_id   INTEGER REFERENCES strings (id),
+        note_id      INTEGER REFERENCES strings (id),
+        project_id   INTEGER REFERENCES strings (id)
+    );
+    CREATE INDEX records_by_date ON records (date);
+
+    CREATE TABLE record_tags (
+        record_id INTEGER NOT NULL REFERENCES records (id),
+        tag_id    INTEGER NOT NULL REFERENCES strings (id)
+    );
+    CREATE INDEX record_tags_by_record ON record_tags (record_id);
+
+    CREATE VIEW records_view AS
+        SELECT records.id, date, start_ms, end_ms, kind,
+            title.value AS title,
+            app_name.value AS app_name,
+            app_class.value AS app_class,
+            productivity,
+            activity.value AS activity,
+            machine.value AS machine,
+            (SELECT group_concat(tag.value, ',') FROM record_tags
+                JOIN strings AS tag ON tag.id = tag_id
+                WHERE record_id = records.id) AS tags,
+            note.value AS note,
+            project.value AS project
+        FROM records
+        LEFT JOIN strings AS title ON title.id = title_id
+        LEFT JOIN strings AS app_name ON app_name.id = app_name_id
+        LEFT JOIN strings AS app_class ON app_class.id = app_class_id
+        LEFT JOIN strings AS activity ON activity.id = activity_id
+        LEFT JOIN strings AS machine ON machine.id = machine_id
+        LEFT JOIN strings AS note ON note.id = note_id
+        LEFT JOIN strings AS project ON project.id = project_id;
+";
+
+const SELECT_RECORDS: &'static str =
+    "SELECT id, start_ms, end_ms, kind, title_id, app_name_id, app_class_id, productivity, activity_id, machine_id, note_id, project_id FROM records";
+
+/// Contents of the `strings` table.
+///
+/// Kept in memory so that sealed strings can be looked up
+/// without opening the whole table on every insert
+#[derive(Default)]
+struct Dictionary {
+    ids: HashMap<String, i64>,
+    values: HashMap<i64, String>,
+}
+
+pub struct SqliteDB {
+    conn: Connection,
+    cipher: Option<Cipher>,
+    dictionary: RefCell<Dictionary>,
+}
+
+impl SqliteDB {
+    pub fn open(path: &Path, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        let conn = Connection::open(path)?;
+
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // Readers don't block the writer and aren't blocked by it in WAL mode
+        conn.query_row("PRAGMA journal_mode = WAL", NO_PARAMS, |_| Ok(()))?;
+        if let Some(version) = upgrade_sqlite(&conn, Some(path), SCHEMA)? {
+            report_upgrade(path, version);
+        }
+        Self::with_connection(conn, cipher)
+    }
+
+    /// Opens the database for reading while another process might be writing to it
+    pub fn open_read_only(path: &Path, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        if !path.exists() {
+            return Self::memory();
+        }
+
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let version: u32 = conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+
+        if version != SCHEMA_VERSION {
+            return Err(RecordStoreError::UnsupportedSchema(version));
+        }
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Self::with_connection(conn, cipher)
+    }
+
+    pub fn memory() -> Result<Self, RecordStoreError> {
+        let conn = Connection::open_in_memory()?;
+
+        upgrade_sqlite(&conn, None, SCHEMA)?;
+        Self::with_connection(conn, None)
+    }
+
+    fn with_connection(conn: Connection, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        let db = Self { conn, cipher, dictionary: RefCell::new(Dictionary::default()) };
+
+        db.load_dictionary()?;
+        Ok(db)
+    }
+
+    fn load_dictionary(&self) -> Result<(), RecordStoreError> {
+        let mut statement = self.conn.prepare("SELECT id, value FROM strings")?;
+        let rows = statement.query_map(NO_PARAMS, |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Value>(1)?)))?;
+        let mut dictionary = Dictionary::default();
+
+        for row in rows {
+            let (id, value) = row?;
+            let value = open_text(value, self.cipher.as_ref())?.unwrap_or_default();
+
+            dictionary.ids.insert(value.clone(), id);
+            dictionary.values.insert(id, value);
+        }
+
+        self.dictionary.replace(dictionary);
+        Ok(())
+    }
+}
+
+/// Row of the records table with string ids as they are stored
+struct StoredRow {
+    id: i64,
+    start_ms: i64,
+    end_ms: i64,
+    kind: String,
+    title: Option<i64>,
+    app_name: Option<i64>,
+    app_class: Option<i64>,
+    productivity: i64,
+    activity: Option<i64>,
+    machine: Option<i64>,
+    note: Option<i64>,
+    project: Option<i64>,
+}
+
+impl StoredRow {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            start_ms: row.get(1)?,
+            end_ms: row.get(2)?,
+            kind: row.get(3)?,
+            title: row.get(4)?,
+            app_name: row.get(5)?,
+            app_class: row.get(6)?,
+            productivity: row.get(7)?,
+            activity: row.get(8)?,
+            machine: row.get(9)?,
+            note: row.get(10)?,
+            project: row.get(11)?,
+        })
+    }
+}
+
+fn seal_text(text: &str, cipher: Option<&Cipher>) -> Value {
+    match cipher {
+        None => Value::Text(String::from(text)),
+        Some(cipher) => Value::Blob(cipher.seal(text.as_bytes())),
+    }
+}
+
+/// Plain strings are read as is so that stores being encrypted stay readable
+fn open_text(value: Value, cipher: Option<&Cipher>) -> Result<Option<String>, RecordStoreError> {
+    match (value, cipher) {
+        (Value::Text(text), _) => Ok(Some(text)),
+        (Value::Blob(sealed), Some(cipher)) => {
+            String::from_utf8(cipher.open(&sealed)?).map(Some).map_err(|_| RecordStoreError::DecryptionFailed)
+        },
+        (Value::Blob(_), None) => Err(RecordStoreError::EncryptedStore),
+        _ => Ok(None),
+    }
+}
+
+impl SqliteDB {
+    /// Gets id of a string, adding it to the dictionary if it's not there yet
+    fn intern(&self, value: &str) -> Result<i64, RecordStoreError> {
+        if let Some(id) = self.dictionary.borrow().ids.get(value) {
+            return Ok(*id);
+        }
+
+        self.conn.execute(
+            "INSERT INTO strings (value) VALUES (?1)",
+            params![seal_text(value, self.cipher.as_ref())],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        let mut dictionary = self.dictionary.borrow_mut();
+
+        dictionary.ids.insert(String::from(value), id);
+        dictionary.values.insert(id, String::from(value));
+        Ok(id)
+    }
+
+    fn intern_optional(&self, value: Option<&String>) -> Result<Option<i64>, RecordStoreError> {
+        match value {
+            Some(value) => Ok(Some(self.intern(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn lookup(&self, id: Option<i64>) -> Result<Option<String>, RecordStoreError> {
+        match id {
+            Some(id) => match self.dictionary.borrow().values.get(&id) {
+                Some(value) => Ok(Some(value.clone())),
+                None => Err(malformed("record refers to a string that doesn't exist")),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn record_from_row(&self, row: StoredRow, tag_ids: &[i64]) -> Result<ActivityRecord, RecordStoreError> {
+        let archetype = match row.kind.as_str() {
+            KIND_AFK => Archetype::AFK,
+            KIND_PRIVATE => Archetype::Private,
+            KIND_INCOGNITO => Archetype::Incognito(
+                self.lookup(row.app_name)?.unwrap_or_default(),
+                self.lookup(row.app_class)?.unwrap_or_default(),
+            ),
+            _ => Archetype::ActiveWindow(
+                self.lookup(row.title)?.unwrap_or_default(),
+                self.lookup(row.app_name)?.unwrap_or_default(),
+                self.lookup(row.app_class)?.unwrap_or_default(),
+            ),
+        };
+        let productivity = match self.lookup(row.activity)? {
+            Some(name) if row.productivity > 0 => ProductivityStatus::Productive(name),
+            Some(name) if row.productivity < 0 => ProductivityStatus::Leisure(name),
+            _ => ProductivityStatus::Neutral,
+        };
+
+        Ok(ActivityRecord {
+            time_range: (from_millis(row.start_ms), from_millis(row.end_ms)),
+            productivity,
+            archetype,
+            machine: self.lookup(row.machine)?,
+            tags: tag_ids.iter()
+                .map(|id| self.lookup(Some(*id)).map(Option::unwrap_or_default))
+                .collect::<Result<BTreeSet<String>, RecordStoreError>>()?,
+            note: self.lookup(row.note)?,
+            project: self.lookup(row.project)?,
+        })
+    }
+
+    fn insert_record(&self, date: &NaiveDate, record: &ActivityRecord) -> Result<(), RecordStoreError> {
+        let date = TrackingDate::from(date);
+        let (kind, title, app_name, app_class) = match &record.archetype {
+            Archetype::ActiveWindow(title, name, class) => (KIND_WINDOW, Some(title), Some(name), Some(class)),
+            Archetype::AFK => (KIND_AFK, None, None, None),
+            Archetype::Private => (KIND_PRIVATE, None, None, None),
+            Archetype::Incognito(name, class) => (KIND_INCOGNITO, None, Some(name), Some(class)),
+        };
+        let activity = match &record.productivity {
+            ProductivityStatus::Productive(name) | ProductivityStatus::Leisure(name) => Some(name),
+            ProductivityStatus::Neutral => None,
+        };
+
+        self.conn.execute(
+            "INSERT INTO records (date, start_ms, end_ms, kind, title_id, app_name_id, app_class_id, productivity, activity_id, machine_id, note_id, project_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                date.0,
+                to_millis(&record.time_range.0),
+                to_millis(&record.time_range.1),
+                kind,
+                self.intern_optional(title)?,
+                self.intern_optional(app_name)?,
+                self.intern_optional(app_class)?,
+                i8::from(&record.productivity),
+                self.intern_optional(activity)?,
+                self.intern_optional(record.machine.as_ref())?,
+                self.intern_optional(record.note.as_ref())?,
+                self.intern_optional(record.project.as_ref())?,
+            ],
+        )?;
+
+        let record_id = self.conn.last_insert_rowid();
+        for tag in &record.tags {
+            self.conn.execute(
+                "INSERT INTO record_tags (record_id, tag_id) VALUES (?1, ?2)",
+                params![record_id, self.intern(tag)?],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Gets ids of tags of every record of a date that has any, by record id
+    fn select_tags(&self, date: &TrackingDate) -> Result<HashMap<i64, Vec<i64>>, RecordStoreError> {
+        let mut statement = self.conn.prepare(
+            "SELECT record_id, tag_id FROM record_tags JOIN records ON records.id = record_id WHERE date = ?1"
+        )?;
+        let rows = statement.query_map(params![date.0], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+        let mut tags: HashMap<i64, Vec<i64>> = HashMap::new();
+
+        for row in rows {
+            let (record_id, tag_id) = row?;
+            tags.entry(record_id).or_insert_with(Vec::new).push(tag_id);
+        }
+        Ok(tags)
+    }
+
+    fn delete_date(&self, date: &TrackingDate) -> Result<(), RecordStoreError> {
+        self.conn.execute(
+            "DELETE FROM record_tags WHERE record_id IN (SELECT id FROM records WHERE date = ?1)",
+            params![date.0],
+        )?;
+        self.conn.execute("DELETE FROM records WHERE date = ?1", params![date.0])?;
+        Ok(())
+    }
+
+    /// Gets rows of a date in the order they were inserted, skipping the first `offset` ones
+    fn select_rows(&self, date: &TrackingDate, offset: usize) -> Result<Vec<StoredRow>, RecordStoreError> {
+        let mut statement = self.conn.prepare(&format!("{} WHERE date = ?1 ORDER BY id LIMIT -1 OFFSET ?2", SELECT_RECORDS))?;
+        let rows = statement.query_map(params![date.0, offset as i64], StoredRow::from_row)?;
+        let mut result: Vec<StoredRow> = vec![];
+
+        for row in rows {
+            result.push(row?);
+        }
+        Ok(result)
+    }
+
+    /// Lists date keys as they are stored, including ones that are not valid dates
+    pub fn date_keys(&self) -> Result<Vec<String>, RecordStoreError> {
+        let mut statement = self.conn.prepare("SELECT DISTINCT date FROM records ORDER BY date")?;
+        let rows = statement.query_map(NO_PARAMS, |row| row.get::<_, String>(0))?;
+        let mut keys: Vec<String> = vec![];
+
+        for row in rows {
+            keys.push(row?);
+        }
+        Ok(keys)
+    }
+
+    /// Runs SQLite's own consistency check, returns the problems it found
+    pub fn integrity_problems(&self) -> Result<Vec<String>, RecordStoreError> {
+        let mut statement = self.conn.prepare("PRAGMA integrity_check")?;
+        let rows = statement.query_map(NO_PARAMS, |row| row.get::<_, String>(0))?;
+        let mut problems: Vec<String> = vec![];
+
+        for row in rows {
+            let message = row?;
+
+            if message != "ok" {
+                problems.push(message);
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Runs statements in a transaction, rolling it back if any of them fails
+    fn transaction<F>(&self, statements: F) -> Result<(), RecordStoreError>
+    where
+        F: FnOnce() -> Result<(), RecordStoreError>,
+    {
+        self.conn.execute_batch("BEGIN;")?;
+
+        match statements() {
+            Ok(_) => self.conn.execute_batch("COMMIT;")?,
+            Err(err) => {
+                self.conn.execute_batch("ROLLBACK;")?;
+                // Strings interned within the transaction are gone
+                self.load_dictionary()?;
+                return Err(err);
+            }
+        };
+        Ok(())
+    }
+}
+
+impl RecordBackend for SqliteDB {
+    fn push_record(&self, date: &NaiveDate, record: ActivityRecord) -> Result<(), RecordStoreError> {
+        self.transaction(|| self.insert_record(date, &record))
+    }
+
+    fn replace_records(&self, date: &NaiveDate, records: Vec<ActivityRecord>) -> Result<(), RecordStoreError> {
+        let date_record = TrackingDate::from(date);
+
+        self.transaction(|| {
+            self.delete_date(&date_record)?;
+            for record in &records {
+                self.insert_record(date, record)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn remove_records(&self, date: &NaiveDate) -> Result<(), RecordStoreError> {
+        let date_record = TrackingDate::from(date);
+
+        self.transaction(|| self.delete_date(&date_record))
+    }
+
+    fn query_records(&self, date: &NaiveDate) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        self.query_records_since(date, 0)
+    }
+
+    fn query_records_since(&self, date: &NaiveDate, index: usize) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        let date_record = TrackingDate::from(date);
+        let tags = self.select_tags(&date_record)?;
+        let mut records: Vec<ActivityRecord> = vec![];
+
+        for row in self.select_rows(&date_record, index)? {
+            let tag_ids = tags.get(&row.id).map_or(&[][..], Vec::as_slice);
+
+            records.push(self.record_from_row(row, tag_ids)?);
+        }
+
+        if records.is_empty() && index == 0 {
+            return Err(RecordStoreError::NoDataOnDate(date.clone()));
+        }
+        Ok(records)
+    }
+
+    fn query_dates(&self) -> Result<Vec<NaiveDate>, RecordStoreError> {
+        let dates = self.date_keys()?
+            .into_iter()
+            .map(|key| NaiveDate::from(TrackingDate(key)))
+            .collect();
+
+        Ok(dates)
+    }
+
+    /// Only the `strings` table holds text, so it is the only one rewritten
+    fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        let values: Vec<(i64, String)> = self.dictionary
+            .borrow()
+            .values
+            .iter()
+            .map(|(id, value)| (*id, value.clone()))
+            .collect();
+
+        self.transaction(|| {
+            for (id, value) in &values {
+                self.conn.execute(
+                    "UPDATE strings SET value = ?1 WHERE id = ?2",
+                    params![seal_text(value, cipher.as_ref()), id],
+                )?;
+            }
+            Ok(())
+        })?;
+
+        self.cipher = cipher;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn record(start_secs: u64, archetype: Archetype, productivity: ProductivityStatus) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (
+                UNIX_EPOCH + Duration::from_secs(start_secs),
+                UNIX_EPOCH + Duration::from_secs(start_secs + 60),
+            ),
+            productivity,
+            archetype,
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn push_and_query() {
+        let db = SqliteDB::memory().unwrap();
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let window = record(
+            1_000,
+            Archetype::ActiveWindow(String::from("title"), String::from("code-oss"), String::from("Code")),
+            ProductivityStatus::Productive(String::from("coding")),
+        );
+        let afk = ActivityRecord {
+            tags: vec![String::from("lunch"), String::from("offline")].into_iter().collect(),
+            note: Some(String::from("pairing with Sam")),
+            project: Some(String::from("client-x")),
+            ..record(2_000, Archetype::AFK, ProductivityStatus::Neutral)
+        };
+
+        db.push_record(&date, window.clone()).unwrap();
+        db.push_record(&date, afk.clone()).unwrap();
+
+        assert_eq!(db.query_records(&date).unwrap(), vec![window, afk.clone()]);
+        assert_eq!(db.query_dates().unwrap(), vec![date]);
+        assert_eq!(db.query_records(&date.succ()).is_err(), true);
+
+        db.replace_records(&date, vec![afk.clone()]).unwrap();
+        assert_eq!(db.query_records(&date).unwrap(), vec![afk]);
+    }
+
+    #[test]
+    fn rekey_seals_strings() {
+        let mut db = SqliteDB::memory().unwrap();
+        let (_, cipher) = crate::record_store::crypto::tests::test_params("secret");
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let window = record(
+            1_000,
+            Archetype::ActiveWindow(String::from("title"), String::from("code-oss"), String::from("Code")),
+            ProductivityStatus::Productive(String::from("coding")),
+        );
+        let strings = |db: &SqliteDB| -> Vec<Value> {
+            let mut statement = db.conn.prepare("SELECT value FROM strings ORDER BY id").unwrap();
+            let rows = statement.query_map(NO_PARAMS, |row| row.get::<_, Value>(0)).unwrap();
+
+            rows.map(Result::unwrap).collect()
+        };
+
+        db.push_record(&date, window.clone()).unwrap();
+        db.rekey(Some(cipher.clone())).unwrap();
+        db.push_record(&date, window.clone()).unwrap();
+
+        match strings(&db).as_slice() {
+            [Value::Blob(title), _, _, _] => assert_eq!(title.windows(5).any(|part| part == b"title"), false),
+            other => panic!("strings must be sealed and interned, got {:?}", other),
+        }
+        assert_eq!(db.query_records(&date).unwrap(), vec![window.clone(), window.clone()]);
+
+        db.rekey(None).unwrap();
+        assert_eq!(strings(&db)[0], Value::Text(String::from("title")));
+        assert_eq!(db.query_records(&date).unwrap(), vec![window.clone(), window]);
+    }
+}
@@ -0,0 +1,287 @@
+use super::{
+    crypto::{open_file, seal_file, Cipher},
+    db::TrackingDate,
+    store::RecordStore,
+    utils::{
+        drop_changed_days, get_path_for_stats_cache, get_path_for_stats_stale, note_changed_day,
+        read_changed_days, write_atomically, RecordStoreError,
+    },
+    RecordFilter,
+};
+use crate::stats::{tracked_dates, Stats};
+use chrono::NaiveDate;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Bumped whenever the layout of `Stats` changes, caches of other versions are dropped
+const CACHE_VERSION: u32 = 1;
+
+/// Stats of a closed day along with its window titles, which `Stats` leaves out when serialized
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedDay {
+    stats: Stats,
+    titles: HashMap<String, u64>,
+}
+
+impl CachedDay {
+    fn of(stats: &Stats) -> Self {
+        Self {
+            stats: stats.clone(),
+            titles: stats.by_title.clone(),
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        Stats { by_title: self.titles.clone(), ..self.stats.clone() }
+    }
+}
+
+/// Inode, length and modification time of a file, each save replaces the file so its inode changes even
+/// when the modification time does not
+type FileStamp = (u64, u64, SystemTime);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheData {
+    version: u32,
+    /// Length of `stats.stale` that was applied to the cache
+    stale_offset: u64,
+    days: BTreeMap<String, CachedDay>,
+}
+
+/// Stats of closed tracking days, kept in `stats.cache`.
+///
+/// Days before the current tracking date are computed once and shared by the TUI, reports and exports.
+/// Changes to them are noted in `stats.stale` by `RecordStore` along with every bump of its revision,
+/// so any process reading the store computes changed days anew. Only the writer notes changes, so once it
+/// saves a cache with all of them applied it starts the notes over. The cache is sealed if the store is encrypted
+pub struct StatsCache {
+    dir: Option<PathBuf>,
+    cipher: Option<Cipher>,
+    read_only: bool,
+    /// Cache as of the last request, read from disk again once another process replaces it
+    data: RefCell<Option<CacheData>>,
+    /// Identity of `stats.cache` when it was last read or written
+    stamp: Cell<Option<FileStamp>>,
+}
+
+impl StatsCache {
+    pub fn in_dir(dir_path: &Path, cipher: Option<Cipher>, read_only: bool) -> Self {
+        Self {
+            dir: Some(dir_path.to_owned()),
+            cipher,
+            read_only,
+            data: RefCell::new(None),
+            stamp: Cell::new(None),
+        }
+    }
+
+    /// Cache of a store kept in memory, it lives as long as the store
+    pub fn memory() -> Self {
+        Self {
+            dir: None,
+            cipher: None,
+            read_only: false,
+            data: RefCell::new(None),
+            stamp: Cell::new(None),
+        }
+    }
+
+    /// Notes that records of a day have changed
+    pub fn invalidate(&self, date: &NaiveDate) -> Result<(), RecordStoreError> {
+        match &self.dir {
+            Some(dir) => note_changed_day(&get_path_for_stats_stale(dir), date),
+            None => {
+                if let Some(data) = self.data.borrow_mut().as_mut() {
+                    data.days.remove(&TrackingDate::from(date).0);
+                }
+                Ok(())
+            },
+        }
+    }
+
+    /// Drops the cache, it is filled again with a new key
+    pub fn rekey(&mut self, cipher: Option<Cipher>) -> Result<(), RecordStoreError> {
+        if let Some(dir) = &self.dir {
+            for path in &[get_path_for_stats_cache(dir), get_path_for_stats_stale(dir)] {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+
+        self.data = RefCell::new(None);
+        self.stamp = Cell::new(None);
+        self.cipher = cipher;
+        Ok(())
+    }
+
+    /// Gets stats of tracking dates from `from` to `to` inclusive
+    pub fn for_dates(&self, store: &RecordStore, from: &NaiveDate, to: &NaiveDate) -> Result<Stats, RecordStoreError> {
+        let mut cell = self.data.borrow_mut();
+        if cell.is_none() || self.read_stamp() != self.stamp.get() {
+            *cell = Some(self.load());
+        }
+
+        let data = cell.as_mut().expect("Stats cache is loaded");
+        let mut is_changed = self.drop_stale(data)?;
+        let today = store.today();
+        let (dates, archived) = tracked_dates(store)?;
+        let mut stats = Stats::default();
+
+        for date in dates {
+            if date < *from || date > *to {
+                continue;
+            }
+
+            let key = TrackingDate::from(&date).0;
+            if let Some(day) = data.days.get(&key) {
+                stats.merge(&day.stats());
+                continue;
+            }
+
+            let day = Stats::of_tracked_day(store, &date, archived.contains(&date), &RecordFilter::default())?;
+
+            stats.merge(&day);
+            if date < today {
+                data.days.insert(key, CachedDay::of(&day));
+                is_changed = true;
+            }
+        }
+
+        if is_changed || self.is_rotatable(data) {
+            self.save(data)?;
+        }
+        Ok(stats)
+    }
+
+    /// Identity of `stats.cache`, `None` if there is no such file
+    fn read_stamp(&self) -> Option<FileStamp> {
+        let metadata = fs::metadata(get_path_for_stats_cache(self.dir.as_ref()?)).ok()?;
+
+        Some((metadata.ino(), metadata.len(), metadata.modified().ok()?))
+    }
+
+    /// Reads the cache, a missing or outdated one is filled from scratch
+    fn load(&self) -> CacheData {
+        let path = match &self.dir {
+            Some(dir) => get_path_for_stats_cache(dir),
+            None => return CacheData::default(),
+        };
+
+        self.stamp.set(self.read_stamp());
+        fs::read(&path).ok()
+            .and_then(|bytes| open_file(bytes, self.cipher.as_ref()).ok())
+            .and_then(|bytes| bincode::deserialize::<CacheData>(&bytes).ok())
+            .filter(|data| data.version == CACHE_VERSION)
+            .unwrap_or_default()
+    }
+
+    fn save(&self, data: &mut CacheData) -> Result<(), RecordStoreError> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        // Another process saved the cache since it was read, which may have started the notes over
+        if self.read_stamp() != self.stamp.get() {
+            return Ok(());
+        }
+
+        let is_rotated = self.is_rotatable(data);
+
+        if is_rotated {
+            data.stale_offset = 0;
+        }
+        let bytes = seal_file(bincode::serialize(data)?, self.cipher.as_ref());
+
+        write_atomically(&get_path_for_stats_cache(dir), &bytes)?;
+        self.stamp.set(self.read_stamp());
+        // Readers of the cache saved above read the old notes once more at worst, which only drops days again
+        if is_rotated {
+            drop_changed_days(&get_path_for_stats_stale(dir))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the writer can start the notes over, all of them are applied to the cache
+    fn is_rotatable(&self, data: &CacheData) -> bool {
+        match &self.dir {
+            Some(dir) if !self.read_only && data.stale_offset > 0 => fs::metadata(get_path_for_stats_stale(dir))
+                .map_or(false, |metadata| metadata.len() == data.stale_offset),
+            _ => false,
+        }
+    }
+
+    /// Drops days noted as changed since the cache was last read, returns whether any were dropped
+    fn drop_stale(&self, data: &mut CacheData) -> Result<bool, RecordStoreError> {
+        let path = match &self.dir {
+            Some(dir) => get_path_for_stats_stale(dir),
+            None => return Ok(false),
+        };
+        let stale_offset = data.stale_offset;
+        let day_count = data.days.len();
+
+        match read_changed_days(&path, &mut data.stale_offset)? {
+            Some(stale) => data.days.retain(|key, _| !stale.contains(key)),
+            // Notes were dropped since the cache was saved, nothing tells which days changed
+            None => data.days.clear(),
+        }
+        data.version = CACHE_VERSION;
+        Ok(data.days.len() != day_count || data.stale_offset != stale_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{db::DB, utils::test_dir, ActivityRecord, Archetype, ProductivityStatus, RecordStoreConfig};
+    use chrono::{Local, TimeZone};
+    use std::{collections::BTreeSet, time::SystemTime};
+
+    fn window(day: u32, hour: u32, title: &str) -> ActivityRecord {
+        let start = SystemTime::from(Local.ymd(2020, 6, day).and_hms(hour, 0, 0));
+
+        ActivityRecord {
+            time_range: (start, start + std::time::Duration::from_secs(600)),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::ActiveWindow(String::from(title), String::from("evince"), String::from("Evince")),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn closed_days_are_computed_until_they_change() {
+        let dir = test_dir("stats_cache");
+        let config = RecordStoreConfig::in_dir(dir.clone());
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let store = RecordStore::new(config.clone()).unwrap();
+
+        store.push_record(window(1, 9, "invoice-2291.pdf")).unwrap();
+        assert_eq!(Stats::for_dates(&store, &date, &date, &RecordFilter::default()).unwrap().total_secs, 600);
+        drop(store);
+
+        // Records of cached days are not read again, so a change behind the store's back goes unnoticed
+        DB::open(&dir, None).unwrap().write_records(&date, vec![window(1, 9, "q3.pdf")]).unwrap();
+        let reader = RecordStore::new_read_only(config.clone()).unwrap();
+        let stats = Stats::for_dates(&reader, &date, &date, &RecordFilter::default()).unwrap();
+        assert_eq!(stats.top_titles(1), vec![(String::from("invoice-2291.pdf"), 600)]);
+
+        let store = RecordStore::new(config).unwrap();
+        store.replace_records(&date, vec![window(1, 9, "invoice-2291.pdf"), window(1, 10, "q3.pdf")]).unwrap();
+        assert_eq!(Stats::for_dates(&reader, &date, &date, &RecordFilter::default()).unwrap().total_secs, 1200);
+
+        // The writer starts the notes over once its saved cache applies them, readers pick that up
+        assert_eq!(Stats::for_dates(&store, &date, &date, &RecordFilter::default()).unwrap().total_secs, 1200);
+        assert_eq!(fs::metadata(get_path_for_stats_stale(&dir)).unwrap().len(), 0);
+        store.replace_records(&date, vec![window(1, 9, "q3.pdf")]).unwrap();
+        assert_eq!(Stats::for_dates(&reader, &date, &date, &RecordFilter::default()).unwrap().total_secs, 600);
+    }
+}
@@ -1,77 +1,976 @@
-use super::config::RecordStoreConfig;
-use super::db::DB;
+use super::archive::Archive;
+use super::config::{RecordStoreConfig, StoreBackend};
+use super::backend::RecordBackend;
+use super::checkpoint::{Checkpoint, CheckpointFile};
+use super::crypto::{Cipher, HashKey, KeyParams, PassphraseSource};
+use super::db::{TrackingDate, DB};
+use super::edit::{apply_edit, forget_range, subtract, AuditEntry, AuditLog, Edit, ForgetMode};
+use super::lock::StoreLock;
+use super::merge::resolve_overlaps;
+use super::remote::RemoteBackend;
+use super::migrations::{report_upgrade, upgrade_archive, upgrade_file_store};
+use super::retention::{merge_adjacent, ArchivedDay};
+use super::search::{SearchHit, SearchIndex, SearchQuery};
+use super::sqlite::SqliteDB;
+use super::stats_cache::StatsCache;
 use super::{
-    utils::{create_file_db, create_memory_db, get_dir, Database, RecordStoreError},
+    utils::{get_dir, get_path_for_archive, get_path_for_sqlite, RecordStoreError},
     ActivityRecord,
 };
-use chrono::{Local, NaiveDate};
-use std::path::Path;
+use crate::stats::Stats;
+use chrono::{Duration, NaiveDate};
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashSet},
+    time::SystemTime,
+};
 
 pub struct RecordStore {
     config: RecordStoreConfig,
-    db: DB,
+    db: Box<dyn RecordBackend>,
+    /// Absent when records are kept in memory only
+    checkpoint: Option<CheckpointFile>,
+    archive: Option<Archive>,
+    search_index: SearchIndex,
+    stats_cache: StatsCache,
+    audit_log: AuditLog,
+    /// Held for as long as the store is open for writing
+    _lock: Option<StoreLock>,
+    read_only: bool,
+    /// Bumped whenever stored records change other than by being pushed, see `revision`
+    revision: Cell<u64>,
+    hash_key: HashKey,
 }
 
 impl RecordStore {
+    /// Opens the store, asking for the passphrase if it is encrypted
     pub fn new(config: RecordStoreConfig) -> Result<Self, RecordStoreError> {
-        let db = Self::try_create_file_db(config.data_dir.as_path())?;
+        let cipher = Self::unlock(&config)?;
+
+        Self::open(config, cipher)
+    }
+
+    /// Opens the store for reading only, asking for the passphrase if it is encrypted
+    pub fn new_read_only(config: RecordStoreConfig) -> Result<Self, RecordStoreError> {
+        let cipher = Self::unlock(&config)?;
+
+        Self::open_read_only(config, cipher)
+    }
+
+    /// Opens the store with a given key, `None` for stores that are not encrypted.
+    ///
+    /// Fails with `RecordStoreError::Locked` if another process writes to the store
+    pub fn open(config: RecordStoreConfig, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        Self::open_with_mode(config, cipher, false)
+    }
+
+    /// Opens the store for reading without getting in the way of the process writing to it, if any
+    pub fn open_read_only(config: RecordStoreConfig, cipher: Option<Cipher>) -> Result<Self, RecordStoreError> {
+        Self::open_with_mode(config, cipher, true)
+    }
+
+    fn open_with_mode(config: RecordStoreConfig, cipher: Option<Cipher>, read_only: bool) -> Result<Self, RecordStoreError> {
+        let data_path = config.data_dir.as_path();
+
+        if let Err(err) = get_dir(data_path) {
+            Self::warn_no_data_dir(err);
+            return Ok(Self {
+                db: Self::create_memory_backend(&config)?,
+                config,
+                checkpoint: None,
+                archive: None,
+                search_index: SearchIndex::memory(),
+                stats_cache: StatsCache::memory(),
+                audit_log: AuditLog::memory(),
+                _lock: None,
+                read_only,
+                revision: Cell::new(0),
+                hash_key: HashKey::generate(),
+            });
+        }
+
+        // Readers only take the lock to upgrade the store, a running writer has upgraded it already
+        let lock = match read_only {
+            true => StoreLock::try_acquire(data_path)?,
+            false => Some(StoreLock::acquire(data_path)?),
+        };
+        if lock.is_some() {
+            Self::upgrade(&config, cipher.as_ref()).map_err(RecordStoreError::into_corrupted)?;
+        }
+
+        let db = Self::create_backend(&config, cipher.clone(), read_only).map_err(RecordStoreError::into_corrupted)?;
+        let hash_key = match (HashKey::load(data_path, cipher.as_ref())?, read_only) {
+            (Some(hash_key), _) => hash_key,
+            // Readers never store windows, so they have nothing to hash
+            (None, true) => HashKey::generate(),
+            (None, false) => HashKey::create(data_path, cipher.as_ref())?,
+        };
 
         Ok(Self {
+            checkpoint: Some(CheckpointFile::in_dir(data_path, cipher.clone())),
+            archive: Some(Archive::in_dir(data_path, cipher.clone())),
+            audit_log: AuditLog::in_dir(data_path, cipher.clone()),
+            search_index: SearchIndex::in_dir(data_path, cipher.clone()),
+            stats_cache: StatsCache::in_dir(data_path, cipher, read_only),
             config,
-            db: DB::new(db)?,
+            db,
+            _lock: if read_only { None } else { lock },
+            read_only,
+            revision: Cell::new(0),
+            hash_key,
+        })
+    }
+
+    /// Gets the key of an encrypted store, asking for the passphrase
+    pub fn unlock(config: &RecordStoreConfig) -> Result<Option<Cipher>, RecordStoreError> {
+        match KeyParams::load(&config.data_dir)? {
+            Some(params) => {
+                let source = PassphraseSource::detect(config.keyfile.as_deref());
+                let passphrase = source.read("Passphrase of the record store: ")?;
+
+                Ok(Some(params.unlock(&passphrase)?))
+            },
+            None if config.encrypted => Err(RecordStoreError::NotEncrypted),
+            None => Ok(None),
+        }
+    }
+
+    fn create_backend(
+        config: &RecordStoreConfig,
+        cipher: Option<Cipher>,
+        read_only: bool,
+    ) -> Result<Box<dyn RecordBackend>, RecordStoreError> {
+        let data_path = config.data_dir.as_path();
+        let sqlite_path = get_path_for_sqlite(data_path);
+
+        let local: Box<dyn RecordBackend> = match (config.backend, read_only) {
+            (StoreBackend::Rustbreak, false) => Box::new(DB::open(data_path, cipher.clone())?),
+            (StoreBackend::Rustbreak, true) => Box::new(DB::open_read_only(data_path, cipher.clone())?),
+            (StoreBackend::Sqlite, false) => Box::new(SqliteDB::open(&sqlite_path, cipher.clone())?),
+            (StoreBackend::Sqlite, true) => Box::new(SqliteDB::open_read_only(&sqlite_path, cipher.clone())?),
+        };
+
+        // Readers never change records, so they have nothing to send
+        Ok(match (&config.remote, read_only) {
+            (Some(remote), false) => Box::new(RemoteBackend::open(local, remote.clone(), data_path, cipher)?),
+            _ => local,
         })
     }
 
-    fn try_create_file_db(data_path: &Path) -> Result<Database, RecordStoreError> {
-        match get_dir(data_path) {
-            Ok(_) => create_file_db(data_path).or(Self::create_memory_db()),
-            Err(err) => {
-                eprintln!("{}{}", [
-                    "Could not access application's data directory to access database files.",
-                    "Will proceed with in-memory database for now.",
-                    "Your tracking data WILL NOT be saved once the application is closed.",
-                    "If this issue persists you can report it at https://github.com/Nachasic/rtrackr/issues"
-                ].join("\n"), err);
-                Self::create_memory_db()
+    fn create_memory_backend(config: &RecordStoreConfig) -> Result<Box<dyn RecordBackend>, RecordStoreError> {
+        Ok(match config.backend {
+            StoreBackend::Rustbreak => Box::new(DB::memory()?),
+            StoreBackend::Sqlite => Box::new(SqliteDB::memory()?),
+        })
+    }
+
+    /// Brings store files written by older versions of the app to the current schema
+    fn upgrade(config: &RecordStoreConfig, cipher: Option<&Cipher>) -> Result<(), RecordStoreError> {
+        let data_path = config.data_dir.as_path();
+
+        match config.backend {
+            StoreBackend::Rustbreak => {
+                if let Some(version) = upgrade_file_store(data_path, cipher)? {
+                    report_upgrade(data_path, version);
+                }
+            },
+            // SQLite stores are upgraded on opening
+            StoreBackend::Sqlite => {},
+        };
+        if let Some(version) = upgrade_archive(data_path, cipher)? {
+            report_upgrade(&get_path_for_archive(data_path), version);
+        }
+        Ok(())
+    }
+
+    fn warn_no_data_dir(err: std::io::Error) {
+        eprintln!("{}{}", [
+            "Could not access application's data directory to access database files.",
+            "Will proceed with in-memory database for now.",
+            "Your tracking data WILL NOT be saved once the application is closed.",
+            "If this issue persists you can report it at https://github.com/Nachasic/rtrackr/issues"
+        ].join("\n"), err);
+    }
+
+    /// Fails for stores opened for reading only
+    fn check_writable(&self) -> Result<(), RecordStoreError> {
+        match self.read_only {
+            true => Err(RecordStoreError::ReadOnly),
+            false => Ok(()),
+        }
+    }
+
+    /// Encrypts all records and the record that is being tracked with a new key.
+    ///
+    /// `params` are saved before the records are rewritten: plain records stay readable
+    /// with a key, so an interrupted encryption can simply be run again
+    pub fn encrypt(&mut self, params: &KeyParams, cipher: Cipher) -> Result<(), RecordStoreError> {
+        self.check_writable()?;
+        params.save(&self.config.data_dir)?;
+        self.hash_key.save(&self.config.data_dir, Some(&cipher))?;
+
+        self.db.rekey(Some(cipher.clone()))?;
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.rekey(Some(cipher.clone()))?;
+        }
+        if let Some(archive) = &mut self.archive {
+            archive.rekey(Some(cipher.clone()))?;
+        }
+        self.audit_log.rekey(Some(cipher.clone()))?;
+        self.search_index.rekey(Some(cipher.clone()))?;
+        self.stats_cache.rekey(Some(cipher))?;
+        Ok(())
+    }
+
+    /// Key privacy rules hash window titles with
+    pub fn hash_key(&self) -> &HashKey {
+        &self.hash_key
+    }
+
+    /// Merges records that continue each other in every stored day.
+    ///
+    /// Returns the number of records merged into others
+    pub fn compact(&self) -> Result<usize, RecordStoreError> {
+        self.check_writable()?;
+
+        let mut merged = 0;
+
+        for date in self.db.query_dates()? {
+            let records = match self.db.query_records(&date) {
+                Err(RecordStoreError::NoDataOnDate(_)) => continue,
+                records => records?,
+            };
+            let count = records.len();
+            let compacted = merge_adjacent(records);
+
+            if compacted.len() < count {
+                merged += count - compacted.len();
+                self.db.replace_records(&date, compacted)?;
+                self.day_changed(&date)?;
             }
         }
+
+        Ok(merged)
     }
 
-    fn create_memory_db() -> Result<Database, RecordStoreError> {
-        create_memory_db().map_err(RecordStoreError::from)
+    /// Sums up days older than `retention_days` into the archive and drops their records.
+    ///
+    /// Returns the number of archived days
+    pub fn apply_retention(&self) -> Result<usize, RecordStoreError> {
+        self.check_writable()?;
+
+        let (retention_days, archive) = match (self.config.retention_days, &self.archive) {
+            (Some(days), Some(archive)) => (days, archive),
+            _ => return Ok(0),
+        };
+        let oldest_kept = self.today() - Duration::days(retention_days as i64);
+        let mut archived = 0;
+
+        for date in self.db.query_dates()? {
+            if date >= oldest_kept {
+                continue;
+            }
+
+            let records = match self.db.query_records(&date) {
+                Err(RecordStoreError::NoDataOnDate(_)) => vec![],
+                records => records?,
+            };
+
+            // Day is archived before it is dropped, an interruption leaves it in both places
+            archive.store_day(&date, records)?;
+            self.db.remove_records(&date)?;
+            self.day_changed(&date)?;
+            archived += 1;
+        }
+
+        Ok(archived)
     }
 
     pub fn query_dates(&self) -> Result<Vec<NaiveDate>, RecordStoreError> {
-        self.db.get_available_dates()
+        self.db.query_dates()
     }
 
+    /// Lists dates past the retention window that only their totals are kept of
+    pub fn query_archived_dates(&self) -> Result<Vec<NaiveDate>, RecordStoreError> {
+        match &self.archive {
+            Some(archive) => archive.query_dates(),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Gets totals of a day past the retention window
+    pub fn query_archived_day(&self, date: &NaiveDate) -> Result<ArchivedDay, RecordStoreError> {
+        match &self.archive {
+            Some(archive) => archive.query_day(date),
+            None => Err(RecordStoreError::NoDataOnDate(*date)),
+        }
+    }
+
+    /// Gets tracking date of a given moment
+    pub fn date_of(&self, time: &SystemTime) -> NaiveDate {
+        NaiveDate::from(TrackingDate::from_time(time, self.config.day_start_hour))
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.date_of(&SystemTime::now())
+    }
+
+    /// Gets the moments a tracking date begins and ends
+    pub fn day_bounds(&self, date: &NaiveDate) -> (SystemTime, SystemTime) {
+        let day_start_hour = self.config.day_start_hour;
+
+        (
+            TrackingDate::from(date).start(day_start_hour),
+            TrackingDate::from(date.succ()).start(day_start_hour),
+        )
+    }
+
+    /// Stores a record under the tracking date it belongs to.
+    ///
+    /// Records spanning several tracking days are split at day boundaries
     pub fn push_record(&self, record: ActivityRecord) -> Result<(), RecordStoreError> {
-        let current_date = Local::today().naive_local();
-        self.db
-            .write_records(&current_date, |records| records.push(record))?;
+        self.check_writable()?;
+        let today = self.today();
 
+        for (date, part) in self.split_by_days(record) {
+            self.db.push_record(&date, part)?;
+            // Closed days only get the part of a record that ran past their end, e.g. over midnight
+            if date < today {
+                self.stats_cache.invalidate(&date)?;
+            }
+        }
         Ok(())
     }
 
-    pub fn query_records(&self) -> Result<Vec<ActivityRecord>, RecordStoreError> {
-        let current_date = Local::today().naive_local();
-        let mut result: Vec<ActivityRecord> = vec![];
+    /// Stores records that may belong anywhere in the history, e.g. imported from other trackers.
+    ///
+    /// Records overlapping the ones already stored, or each other, are skipped.
+    /// Returns the number of records that were stored
+    pub fn insert_records(&self, records: Vec<ActivityRecord>) -> Result<usize, RecordStoreError> {
+        self.check_writable()?;
 
-        self.db
-            .read_records(&current_date, |records| *(&mut result) = records.clone())?;
+        let mut by_date: BTreeMap<NaiveDate, Vec<ActivityRecord>> = BTreeMap::new();
+        let mut inserted = 0;
 
-        Ok(result)
+        for record in records {
+            for (date, part) in self.split_by_days(record) {
+                by_date.entry(date).or_insert_with(Vec::new).push(part);
+            }
+        }
+
+        for (date, parts) in by_date {
+            let mut day_records = match self.db.query_records(&date) {
+                Err(RecordStoreError::NoDataOnDate(_)) => vec![],
+                records => records?,
+            };
+            let day_length = day_records.len();
+
+            for part in parts {
+                if !day_records.iter().any(|existing| existing.overlaps(&part)) {
+                    day_records.push(part);
+                }
+            }
+
+            if day_records.len() > day_length {
+                inserted += day_records.len() - day_length;
+                day_records.sort_by_key(|record| record.time_range.0);
+                self.db.replace_records(&date, day_records)?;
+                self.day_changed(&date)?;
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    /// Merges records tracked on other machines into the history.
+    ///
+    /// Stored records of the machines being merged are replaced by the incoming ones,
+    /// and overlaps between machines are resolved the same way whatever the merge order.
+    /// Returns the number of tracking days that changed
+    pub fn merge_records(&self, records: Vec<ActivityRecord>) -> Result<usize, RecordStoreError> {
+        self.check_writable()?;
+
+        let machines: HashSet<Option<String>> = records.iter().map(|record| record.machine.clone()).collect();
+        let mut by_date: BTreeMap<NaiveDate, Vec<ActivityRecord>> = BTreeMap::new();
+        let mut changed = 0;
+
+        for record in records {
+            for (date, part) in self.split_by_days(record) {
+                by_date.entry(date).or_insert_with(Vec::new).push(part);
+            }
+        }
+
+        for (date, parts) in by_date {
+            let day_records = match self.db.query_records(&date) {
+                Err(RecordStoreError::NoDataOnDate(_)) => vec![],
+                records => records?,
+            };
+            let mut merged: Vec<ActivityRecord> = day_records.iter()
+                .filter(|record| !machines.contains(&record.machine))
+                .cloned()
+                .collect();
+
+            merged.extend(parts);
+            let merged = resolve_overlaps(merged);
+
+            if merged != day_records {
+                changed += 1;
+                self.db.replace_records(&date, merged)?;
+                self.day_changed(&date)?;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Applies an edit made by hand and logs what it changed, so that it can be reverted.
+    ///
+    /// Returns the audit entries of the tracking dates that changed, edits of ranges
+    /// spanning several days get an entry per day
+    pub fn edit_records(&self, edit: Edit) -> Result<Vec<AuditEntry>, RecordStoreError> {
+        self.check_writable()?;
+
+        let day_edits: Vec<(NaiveDate, Edit)> = match &edit {
+            Edit::SetProductivity(at, _) | Edit::Split(at) | Edit::MergeNext(at) => vec![(self.date_of(at), edit.clone())],
+            Edit::Delete(from, to)
+            | Edit::AddTags(from, to, _)
+            | Edit::RemoveTags(from, to, _)
+            | Edit::SetNote(from, to, _) => {
+                if from >= to {
+                    return Err(RecordStoreError::InvalidEdit(String::from("Time range ends before it starts")));
+                }
+                self.dates_between(from, to).into_iter().map(|date| (date, edit.clone())).collect()
+            },
+            Edit::Insert(record) => {
+                if record.time_range.0 >= record.time_range.1 {
+                    return Err(RecordStoreError::InvalidEdit(String::from("Time range ends before it starts")));
+                }
+                self.split_by_days(record.clone()).into_iter().map(|(date, part)| (date, Edit::Insert(part))).collect()
+            },
+            Edit::Revert(number) => return self.revert_edit(*number),
+        };
+        let mut changes: Vec<(NaiveDate, Vec<ActivityRecord>, AuditEntry)> = vec![];
+
+        // Every day must accept the edit before any of them is written
+        for (date, day_edit) in day_edits {
+            let records = self.stored_records(&date)?;
+            let edited = apply_edit(&records, &day_edit)?;
+            let removed = subtract(&records, &edited);
+            let added = subtract(&edited, &records);
+
+            if !removed.is_empty() || !added.is_empty() {
+                let entry = AuditEntry {
+                    made_at: SystemTime::now(),
+                    date: TrackingDate::from(&date).0,
+                    edit: day_edit,
+                    removed,
+                    added,
+                };
+                changes.push((date, edited, entry));
+            }
+        }
+
+        self.write_edits(changes)
+    }
+
+    /// Brings back records replaced by an audit entry, numbered from 1
+    fn revert_edit(&self, number: usize) -> Result<Vec<AuditEntry>, RecordStoreError> {
+        if number == 0 {
+            return Err(RecordStoreError::InvalidEdit(String::from("Edits are numbered from 1")));
+        }
+
+        let entry = self.audit_log.entries()?
+            .into_iter()
+            .nth(number - 1)
+            .ok_or_else(|| RecordStoreError::InvalidEdit(format!("There is no edit #{}", number)))?;
+        let date = TrackingDate(entry.date.clone())
+            .parse()
+            .ok_or_else(|| RecordStoreError::InvalidEdit(format!("Edit #{} has a malformed date", number)))?;
+        let records = self.stored_records(&date)?;
+        let kept = subtract(&records, &entry.added);
+        let changed_since = kept.len() + entry.added.len() != records.len()
+            || entry.removed.iter().any(|old| kept.iter().any(|record| record.overlaps(old)));
+
+        if changed_since {
+            return Err(RecordStoreError::InvalidEdit(format!(
+                "Records were changed after edit #{}, revert the later edits first", number
+            )));
+        }
+
+        let mut reverted = kept;
+        reverted.extend(entry.removed.iter().cloned());
+        reverted.sort_by_key(|record| record.time_range.0);
+
+        self.write_edits(vec![(date, reverted, AuditEntry {
+            made_at: SystemTime::now(),
+            date: entry.date,
+            edit: Edit::Revert(number),
+            removed: entry.added,
+            added: entry.removed,
+        })])
+    }
+
+    /// Logs edits before writing them, an interrupted edit can then still be reverted
+    fn write_edits(
+        &self,
+        changes: Vec<(NaiveDate, Vec<ActivityRecord>, AuditEntry)>,
+    ) -> Result<Vec<AuditEntry>, RecordStoreError> {
+        let entries: Vec<AuditEntry> = changes.iter().map(|(_, _, entry)| entry.clone()).collect();
+
+        self.audit_log.append(entries.clone())?;
+        for (date, records, _) in changes {
+            self.db.replace_records(&date, records)?;
+            self.day_changed(&date)?;
+        }
+        Ok(entries)
+    }
+
+    /// Gets every edit made by hand, oldest first
+    pub fn audit_entries(&self) -> Result<Vec<AuditEntry>, RecordStoreError> {
+        self.audit_log.entries()
+    }
+
+    fn stored_records(&self, date: &NaiveDate) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        match self.db.query_records(date) {
+            Err(RecordStoreError::NoDataOnDate(_)) => Ok(vec![]),
+            result => result,
+        }
+    }
+
+    /// Tracking dates with a part of a time range
+    fn dates_between(&self, from: &SystemTime, to: &SystemTime) -> Vec<NaiveDate> {
+        let mut dates = vec![];
+        let mut date = self.date_of(from);
+
+        while self.day_bounds(&date).0 < *to {
+            dates.push(date);
+            date = date.succ();
+        }
+        dates
+    }
+
+    fn split_by_days(&self, record: ActivityRecord) -> Vec<(NaiveDate, ActivityRecord)> {
+        let mut parts: Vec<(NaiveDate, ActivityRecord)> = vec![];
+        let mut rest = record;
+
+        loop {
+            let date = self.date_of(&rest.time_range.0);
+            let (_, day_end) = self.day_bounds(&date);
+
+            if rest.time_range.1 <= day_end || day_end <= rest.time_range.0 {
+                parts.push((date, rest));
+                return parts;
+            }
+
+            let mut head = rest.clone();
+            head.time_range.1 = day_end;
+            rest.time_range.0 = day_end;
+            parts.push((date, head));
+        }
+    }
+
+    /// Saves the record that is still being tracked
+    pub fn save_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), RecordStoreError> {
+        self.check_writable()?;
+        match &self.checkpoint {
+            Some(file) => file.save(checkpoint),
+            None => Ok(()),
+        }
+    }
+
+    /// Gets the record that was still being tracked when the app was last stopped
+    pub fn load_checkpoint(&self) -> Result<Option<Checkpoint>, RecordStoreError> {
+        match &self.checkpoint {
+            Some(file) => file.load(),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the record of a checkpoint was stored already, the app may stop between pushing it and clearing
+    /// or replacing the checkpoint
+    pub fn is_checkpoint_stored(&self, checkpoint: &Checkpoint) -> Result<bool, RecordStoreError> {
+        let records = match self.db.query_records(&self.date_of(&checkpoint.started_at)) {
+            Err(RecordStoreError::NoDataOnDate(_)) => return Ok(false),
+            records => records?,
+        };
+
+        // The tracker never starts two records at the same moment
+        Ok(records.iter().any(|record| record.time_range.0 == checkpoint.started_at && record.archetype == checkpoint.archetype))
+    }
+
+    pub fn clear_checkpoint(&self) -> Result<(), RecordStoreError> {
+        self.check_writable()?;
+        match &self.checkpoint {
+            Some(file) => file.clear(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn query_records(&self) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        match self.db.query_records(&self.today()) {
+            Err(RecordStoreError::NoDataOnDate(_)) => Ok(vec![]),
+            result => result,
+        }
+    }
+
+    /// Gets records of a tracking date pushed after the first `index` ones
+    pub fn query_records_since(&self, date: &NaiveDate, index: usize) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        match self.db.query_records_since(date, index) {
+            Err(RecordStoreError::NoDataOnDate(_)) => Ok(vec![]),
+            result => result,
+        }
     }
 
     pub fn query_records_by_date(
-        &mut self,
+        &self,
         date: &NaiveDate,
     ) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        self.db.query_records(date)
+    }
+
+    /// Overwrites stored records of a tracking date
+    pub fn replace_records(&self, date: &NaiveDate, records: Vec<ActivityRecord>) -> Result<(), RecordStoreError> {
+        self.check_writable()?;
+        self.db.replace_records(date, records)?;
+        self.day_changed(date)
+    }
+
+    /// Number of times stored records were changed other than by pushing new ones, e.g. by edits.
+    ///
+    /// Records read and kept by callers, such as the datasets of the TUI, are stale once it changes
+    pub fn revision(&self) -> u64 {
+        self.revision.get()
+    }
+
+    fn day_changed(&self, date: &NaiveDate) -> Result<(), RecordStoreError> {
+        self.revision.set(self.revision.get() + 1);
+        self.search_index.invalidate(date)?;
+        self.stats_cache.invalidate(date)
+    }
+
+    /// Drops or anonymizes records within a time range, e.g. the last minutes of tracking
+    /// that showed something sensitive, along with the part of the checkpoint within it.
+    ///
+    /// Unlike edits, forgetting is not logged, the audit log would keep what is forgotten.
+    /// Returns the number of records that were changed
+    pub fn forget(&self, from: &SystemTime, to: &SystemTime, mode: ForgetMode) -> Result<usize, RecordStoreError> {
+        self.check_writable()?;
+
+        if from >= to {
+            return Err(RecordStoreError::InvalidEdit(String::from("Time range ends before it starts")));
+        }
+
+        let mut changed = 0;
+
+        for date in self.dates_between(from, to) {
+            let records = self.stored_records(&date)?;
+            let kept = forget_range(&records, from, to, mode);
+            let removed = subtract(&records, &kept).len();
+
+            if removed > 0 {
+                changed += removed;
+                self.db.replace_records(&date, kept)?;
+                self.day_changed(&date)?;
+            }
+        }
+
+        if let Some(checkpoint) = self.load_checkpoint()? {
+            match checkpoint.forget(from, to, mode) {
+                Some(kept) if kept == checkpoint => {},
+                Some(kept) => self.save_checkpoint(&kept)?,
+                None => self.clear_checkpoint()?,
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Finds records whose window titles, app names or classes match a query,
+    /// optionally within a range of tracking dates. Latest records come first
+    pub fn search(
+        &self,
+        query: &SearchQuery,
+        from: Option<&NaiveDate>,
+        to: Option<&NaiveDate>,
+    ) -> Result<Vec<SearchHit>, RecordStoreError> {
+        self.search_index.search(self, query, from, to)
+    }
+
+    /// Gets stats of tracking dates from `from` to `to` inclusive, those of closed days are cached
+    pub fn stats(&self, from: &NaiveDate, to: &NaiveDate) -> Result<Stats, RecordStoreError> {
+        self.stats_cache.for_dates(self, from, to)
+    }
+
+    /// Gets records within a time range, clipping those that cross its bounds
+    pub fn query_records_between(
+        &self,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        let first_date = self.date_of(&start);
+        let last_date = self.date_of(&end);
         let mut result: Vec<ActivityRecord> = vec![];
 
-        self.db
-            .read_records(date, |records| *(&mut result) = records.clone())?;
+        for date in self.query_dates()? {
+            if date < first_date || date > last_date {
+                continue;
+            }
+
+            let records = match self.query_records_by_date(&date) {
+                Err(RecordStoreError::NoDataOnDate(_)) => continue,
+                records => records?,
+            };
+
+            result.extend(records.iter().filter_map(|record| record.clip(start, end)));
+        }
 
+        result.sort_by_key(|record| record.time_range.0);
         Ok(result)
     }
+
+    /// Gets records of all tracking dates from `from` to `to` inclusive
+    pub fn query_records_for_dates(
+        &self,
+        from: &NaiveDate,
+        to: &NaiveDate,
+    ) -> Result<Vec<ActivityRecord>, RecordStoreError> {
+        let (start, _) = self.day_bounds(from);
+        let (_, end) = self.day_bounds(to);
+
+        self.query_records_between(start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{
+        utils::test_dir,
+        Archetype, ArchivedKind, ProductivityStatus,
+    };
+    use chrono::{Local, TimeZone};
+    use std::collections::BTreeSet;
+
+    fn local(day: u32, hour: u32, minute: u32) -> SystemTime {
+        SystemTime::from(Local.ymd(2020, 6, day).and_hms(hour, minute, 0))
+    }
+
+    fn record(start: SystemTime, end: SystemTime) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (start, end),
+            productivity: ProductivityStatus::Neutral,
+            archetype: Archetype::AFK,
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn records_are_split_at_midnight() {
+        let store = RecordStore::new(RecordStoreConfig::in_dir(test_dir("store_split"))).unwrap();
+
+        store.push_record(record(local(1, 23, 30), local(2, 0, 45))).unwrap();
+
+        assert_eq!(
+            store.db.query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![record(local(1, 23, 30), local(2, 0, 0))]
+        );
+        assert_eq!(
+            store.db.query_records(&NaiveDate::from_ymd(2020, 6, 2)).unwrap(),
+            vec![record(local(2, 0, 0), local(2, 0, 45))]
+        );
+        assert_eq!(
+            store.query_records_between(local(1, 23, 45), local(2, 0, 30)).unwrap(),
+            vec![
+                record(local(1, 23, 45), local(2, 0, 0)),
+                record(local(2, 0, 0), local(2, 0, 30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn late_night_counts_towards_previous_day() {
+        let mut config = RecordStoreConfig::in_dir(test_dir("store_day_start"));
+        config.day_start_hour = 4;
+        let store = RecordStore::new(config).unwrap();
+
+        store.push_record(record(local(1, 23, 30), local(2, 0, 45))).unwrap();
+        store.push_record(record(local(2, 3, 30), local(2, 4, 30))).unwrap();
+
+        assert_eq!(
+            store.db.query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![
+                record(local(1, 23, 30), local(2, 0, 45)),
+                record(local(2, 3, 30), local(2, 4, 0)),
+            ]
+        );
+        assert_eq!(
+            store.query_records_for_dates(&NaiveDate::from_ymd(2020, 6, 2), &NaiveDate::from_ymd(2020, 6, 2)).unwrap(),
+            vec![record(local(2, 4, 0), local(2, 4, 30))]
+        );
+    }
+
+    #[test]
+    fn inserted_records_skip_overlaps() {
+        let store = RecordStore::new(RecordStoreConfig::in_dir(test_dir("store_insert"))).unwrap();
+
+        store.push_record(record(local(1, 12, 0), local(1, 13, 0))).unwrap();
+
+        let inserted = store.insert_records(vec![
+            record(local(1, 9, 0), local(1, 10, 0)),
+            record(local(1, 9, 30), local(1, 10, 30)),
+            record(local(1, 12, 30), local(1, 14, 0)),
+            record(local(1, 13, 0), local(1, 13, 30)),
+        ]).unwrap();
+
+        assert_eq!(inserted, 2);
+        assert_eq!(
+            store.db.query_records(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(),
+            vec![
+                record(local(1, 9, 0), local(1, 10, 0)),
+                record(local(1, 12, 0), local(1, 13, 0)),
+                record(local(1, 13, 0), local(1, 13, 30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn readers_open_store_locked_by_writer() {
+        for (name, backend) in &[("store_lock", StoreBackend::Rustbreak), ("store_lock_sqlite", StoreBackend::Sqlite)] {
+            let mut config = RecordStoreConfig::in_dir(test_dir(name));
+            config.backend = *backend;
+            let writer = RecordStore::new(config.clone()).unwrap();
+            let pushed = record(local(1, 9, 0), local(1, 9, 5));
+
+            writer.push_record(pushed.clone()).unwrap();
+            match RecordStore::new(config.clone()) {
+                Err(RecordStoreError::Locked(Some(_))) => {},
+                _ => panic!("store must have a single writer"),
+            }
+
+            let reader = RecordStore::new_read_only(config.clone()).unwrap();
+            assert_eq!(reader.query_records_by_date(&NaiveDate::from_ymd(2020, 6, 1)).unwrap(), vec![pushed.clone()]);
+            match reader.push_record(pushed) {
+                Err(RecordStoreError::ReadOnly) => {},
+                _ => panic!("reader must not write"),
+            }
+
+            drop(writer);
+            assert_eq!(RecordStore::new(config).is_ok(), true);
+        }
+    }
+
+    #[test]
+    fn old_days_are_summed_up_in_archive() {
+        let mut config = RecordStoreConfig::in_dir(test_dir("store_retention"));
+        config.retention_days = Some(30);
+        let store = RecordStore::new(config).unwrap();
+        let window = |title: &str| Archetype::ActiveWindow(String::from(title), String::from("mail"), String::from("Mail"));
+        let old_date = NaiveDate::from_ymd(2020, 6, 1);
+        let now = SystemTime::now();
+        let recent = record(now - std::time::Duration::from_secs(60), now);
+
+        store.push_record(ActivityRecord { archetype: window("inbox"), ..record(local(1, 9, 0), local(1, 9, 5)) }).unwrap();
+        store.push_record(ActivityRecord { archetype: window("draft"), ..record(local(1, 9, 5), local(1, 9, 10)) }).unwrap();
+        store.push_record(record(local(1, 9, 10), local(1, 9, 20))).unwrap();
+        store.push_record(record(local(1, 9, 20), local(1, 9, 30))).unwrap();
+        store.push_record(recent.clone()).unwrap();
+
+        assert_eq!(store.compact().unwrap(), 1);
+        assert_eq!(store.apply_retention().unwrap(), 1);
+        assert_eq!(store.apply_retention().unwrap(), 0);
+
+        assert_eq!(store.query_records_by_date(&old_date).is_err(), true);
+        assert_eq!(store.query_dates().unwrap(), vec![store.date_of(&recent.time_range.0)]);
+        assert_eq!(store.query_archived_dates().unwrap(), vec![old_date]);
+
+        let totals: Vec<(ArchivedKind, u64)> = store.query_archived_day(&old_date).unwrap().totals
+            .into_iter()
+            .map(|total| (total.kind, total.secs))
+            .collect();
+        assert_eq!(totals, vec![
+            (ArchivedKind::Window(String::from("mail"), String::from("Mail")), 10 * 60),
+            (ArchivedKind::AFK, 20 * 60),
+        ]);
+        assert_eq!(store.query_records().unwrap(), vec![recent]);
+    }
+
+    #[test]
+    fn edits_are_logged_and_reverted() {
+        let store = RecordStore::new(RecordStoreConfig::in_dir(test_dir("store_edit"))).unwrap();
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let tracked = vec![record(local(1, 9, 0), local(1, 10, 0)), record(local(1, 10, 0), local(1, 11, 0))];
+
+        store.insert_records(tracked.clone()).unwrap();
+        store.edit_records(Edit::Split(local(1, 9, 30))).unwrap();
+        let entries = store.edit_records(Edit::Delete(local(1, 9, 45), local(1, 10, 15))).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(store.query_records_by_date(&date).unwrap(), vec![
+            record(local(1, 9, 0), local(1, 9, 30)),
+            record(local(1, 9, 30), local(1, 9, 45)),
+            record(local(1, 10, 15), local(1, 11, 0)),
+        ]);
+
+        assert_eq!(store.edit_records(Edit::Revert(0)).is_err(), true);
+        assert_eq!(store.edit_records(Edit::Revert(1)).is_err(), true);
+        store.edit_records(Edit::Revert(2)).unwrap();
+        store.edit_records(Edit::Revert(1)).unwrap();
+        assert_eq!(store.query_records_by_date(&date).unwrap(), tracked);
+        assert_eq!(store.audit_entries().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn stored_checkpoint_is_recognized() {
+        let store = RecordStore::new(RecordStoreConfig::in_dir(test_dir("store_stored_checkpoint"))).unwrap();
+        let checkpoint = Checkpoint {
+            archetype: Archetype::AFK,
+            started_at: local(1, 9, 0),
+            seen_at: local(1, 9, 30),
+        };
+
+        assert_eq!(store.is_checkpoint_stored(&checkpoint).unwrap(), false);
+        store.push_record(record(local(1, 8, 0), local(1, 9, 0))).unwrap();
+        assert_eq!(store.is_checkpoint_stored(&checkpoint).unwrap(), false);
+        store.push_record(checkpoint.clone().into_record()).unwrap();
+        assert_eq!(store.is_checkpoint_stored(&checkpoint).unwrap(), true);
+    }
+
+    #[test]
+    fn forgotten_records_are_not_logged() {
+        let store = RecordStore::new(RecordStoreConfig::in_dir(test_dir("store_forget"))).unwrap();
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let window = |start, end| ActivityRecord {
+            archetype: Archetype::ActiveWindow(String::from("Bank"), String::from("Navigator"), String::from("Firefox")),
+            ..record(start, end)
+        };
+        let incognito = |start, end| ActivityRecord {
+            archetype: Archetype::Incognito(String::from("Navigator"), String::from("Firefox")),
+            ..record(start, end)
+        };
+
+        store.insert_records(vec![window(local(1, 9, 0), local(1, 10, 0)), record(local(1, 10, 0), local(1, 11, 0))]).unwrap();
+        store.save_checkpoint(&Checkpoint {
+            archetype: Archetype::AFK,
+            started_at: local(1, 11, 0),
+            seen_at: local(1, 11, 30),
+        }).unwrap();
+        let revision = store.revision();
+
+        assert_eq!(store.forget(&local(1, 9, 30), &local(1, 10, 30), ForgetMode::Anonymize).unwrap(), 1);
+        assert_eq!(store.forget(&local(1, 10, 15), &local(1, 11, 15), ForgetMode::Remove).unwrap(), 1);
+        assert_eq!(store.query_records_by_date(&date).unwrap(), vec![
+            window(local(1, 9, 0), local(1, 9, 30)),
+            incognito(local(1, 9, 30), local(1, 10, 0)),
+            record(local(1, 10, 0), local(1, 10, 15)),
+        ]);
+        assert_eq!(store.load_checkpoint().unwrap(), Some(Checkpoint {
+            archetype: Archetype::AFK,
+            started_at: local(1, 11, 15),
+            seen_at: local(1, 11, 30),
+        }));
+        assert_eq!(store.audit_entries().unwrap(), vec![]);
+        assert_eq!(store.revision() > revision, true);
+    }
 }
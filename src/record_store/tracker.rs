@@ -1,4 +1,6 @@
 use super::*;
+use super::checkpoint::Checkpoint;
+use super::edit::{forget_range, ForgetMode};
 use std::time::{
     SystemTime, Duration
 };
@@ -51,6 +53,38 @@ impl RecordTracker {
         }
     }
 
+    /// Closes the current record, e.g. when the app is shutting down
+    pub fn flush(&mut self) -> Option<ActivityRecord> {
+        self.ping(None)
+    }
+
+    /// Gets a snapshot of the current record that can be saved and recovered later
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        self.current_archetype.as_ref().map(|archetype| Checkpoint {
+            archetype: archetype.clone(),
+            started_at: self.time_of_first_submission,
+            seen_at: SystemTime::now(),
+        })
+    }
+
+    /// Forgets the part of the current record tracked within a time range.
+    ///
+    /// Returns what is left of the record up to the end of the range to be stored, tracking of the same
+    /// window goes on from there
+    pub fn forget(&mut self, from: &SystemTime, to: &SystemTime, mode: ForgetMode) -> Vec<ActivityRecord> {
+        let mut record = match &self.current_archetype {
+            Some(archetype) => Self::produce_record(archetype.clone(), self.time_of_first_submission),
+            None => return vec![],
+        };
+
+        if *to <= record.time_range.0 {
+            return vec![];
+        }
+        record.time_range.1 = std::cmp::min(*to, record.time_range.1);
+        self.time_of_first_submission = record.time_range.1;
+        forget_range(std::slice::from_ref(&record), from, to, mode)
+    }
+
     fn produce_record(archetype: Archetype, start_time: SystemTime) -> ActivityRecord {
         let end_time = SystemTime::now();
 
@@ -58,6 +92,10 @@ impl RecordTracker {
             archetype,
             productivity: ProductivityStatus::Neutral,
             time_range: (start_time, end_time),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
         }
     }
 
@@ -94,3 +132,60 @@ fn report_production() {
     let report6 = tracker.ping(None);
     assert_eq!(report6.is_some(), true);
 }
+
+#[test]
+fn forget_current_record() {
+    let mut tracker = RecordTracker::new();
+    let window = Archetype::ActiveWindow(String::from("Bank"), String::from("Navigator"), String::from("Firefox"));
+
+    let now = SystemTime::now() + Duration::from_secs(60);
+    assert_eq!(tracker.forget(&SystemTime::now(), &now, ForgetMode::Remove), vec![]);
+
+    tracker.ping(Some(window.clone()));
+    let started_at = tracker.checkpoint().unwrap().started_at;
+    tracker.time_of_first_submission = started_at - Duration::from_secs(60);
+
+    let anonymized = tracker.forget(&started_at, &now, ForgetMode::Anonymize);
+    assert_eq!(anonymized.iter().map(|record| record.archetype.clone()).collect::<Vec<Archetype>>(), vec![
+        window.clone(),
+        Archetype::Incognito(String::from("Navigator"), String::from("Firefox")),
+    ]);
+    assert_eq!(anonymized[0].time_range.1, started_at);
+    assert_eq!(tracker.get_current_archetype(), &Some(window));
+    assert_eq!(tracker.forget(&started_at, &now, ForgetMode::Remove), vec![]);
+}
+
+#[test]
+fn forget_keeps_tracking_past_the_range() {
+    let mut tracker = RecordTracker::new();
+    let started_at = SystemTime::now() - Duration::from_secs(120);
+
+    tracker.ping(Some(Archetype::AFK));
+    tracker.time_of_first_submission = started_at;
+
+    let (from, to) = (started_at + Duration::from_secs(30), started_at + Duration::from_secs(60));
+
+    let kept = tracker.forget(&from, &to, ForgetMode::Remove);
+    assert_eq!(kept.iter().map(|record| record.time_range).collect::<Vec<_>>(), vec![
+        (started_at, from),
+    ]);
+    assert_eq!(tracker.checkpoint().unwrap().started_at, to);
+    assert_eq!(tracker.forget(&started_at, &started_at, ForgetMode::Remove), vec![]);
+    assert_eq!(tracker.checkpoint().unwrap().started_at, to);
+}
+
+#[test]
+fn checkpoint_and_flush() {
+    let mut tracker = RecordTracker::new();
+    assert_eq!(tracker.checkpoint(), None);
+
+    tracker.ping(Some(Archetype::AFK));
+    let checkpoint = tracker.checkpoint().unwrap();
+    assert_eq!(checkpoint.archetype, Archetype::AFK);
+
+    let record = tracker.flush().unwrap();
+    assert_eq!(record.archetype, Archetype::AFK);
+    assert_eq!(record.time_range.0, checkpoint.started_at);
+    assert_eq!(tracker.checkpoint(), None);
+    assert_eq!(tracker.flush(), None);
+}
@@ -1,32 +1,133 @@
 use super::*;
+use super::crypto::{open_file, seal_file, Cipher};
+use super::db::TrackingDate;
+use super::encoding::{decode_snapshot, encode_snapshot};
+use super::migrations::SchemaHeader;
 use chrono::NaiveDate;
 use rustbreak::{
-    backend::{Backend, FileBackend, MemoryBackend},
+    backend::{Backend, MemoryBackend},
     deser::Bincode,
     Database as RDatabase, MemoryDatabase, RustbreakError,
 };
 use std::{
-    collections::HashMap,
-    fs::{create_dir, read_dir, ReadDir},
+    collections::{HashMap, HashSet},
+    fs::{self, create_dir, read_dir, rename, File, OpenOptions, ReadDir},
+    io::{Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-pub type Database = RDatabase<HashMap<String, Vec<ActivityRecord>>, Box<dyn Backend>, Bincode>;
+pub type Database = RDatabase<Snapshot, Box<dyn Backend>, Bincode>;
+
+/// Schema header followed by records grouped by tracking date.
+///
+/// Each file of `days` holds a single day and each month file of the archive holds the days of a month,
+/// `records.db` only keeps days whose keys are not valid dates.
+/// Kept in memory as is, see `encoding` for the way it is laid out on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub header: SchemaHeader,
+    pub days: HashMap<String, Vec<ActivityRecord>>,
+}
+
+impl Snapshot {
+    pub fn new(days: HashMap<String, Vec<ActivityRecord>>) -> Self {
+        Self {
+            header: SchemaHeader::current(),
+            days,
+        }
+    }
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self::new(HashMap::new())
+    }
+}
 
 #[derive(Debug)]
 pub enum RecordStoreError {
-    FailedToSwitchDB,
     DBFailed(RustbreakError),
+    SqliteFailed(rusqlite::Error),
+    IOFailed(std::io::Error),
+    EncodingFailed(bincode::Error),
+    JsonFailed(serde_json::Error),
+    UnsupportedSchema(u32),
+    /// Store files are encrypted but no key was given
+    EncryptedStore,
+    /// Store is configured to be encrypted but its files are not
+    NotEncrypted,
+    WrongPassphrase,
+    PassphrasesDiffer,
+    DecryptionFailed,
+    /// Store files are damaged, see `fsck`
+    Corrupted(Box<RecordStoreError>),
+    /// Another process writes to the store, holds its id if known
+    Locked(Option<u32>),
+    /// Store was opened for reading only
+    ReadOnly,
+    /// Edit made by hand can't be applied to the stored records
+    InvalidEdit(String),
+    /// Server records are mirrored to could not be reached
+    ServerUnreachable(String),
+    /// Server records are mirrored to answered with an error status and message
+    ServerRejected(u16, String),
+    /// `[store]` section of the config file can't be used
+    InvalidConfig(String),
     NoDataOnDate(NaiveDate),
 }
 
+impl RecordStoreError {
+    /// Marks errors caused by store files that could not be read back
+    pub fn into_corrupted(self) -> Self {
+        match self {
+            RecordStoreError::DBFailed(_)
+            | RecordStoreError::SqliteFailed(_)
+            | RecordStoreError::EncodingFailed(_)
+            | RecordStoreError::DecryptionFailed => RecordStoreError::Corrupted(Box::new(self)),
+            _ => self,
+        }
+    }
+}
+
 impl std::fmt::Display for RecordStoreError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RecordStoreError::FailedToSwitchDB => {
-                write!(f, "Failed to switch database to a new file")
-            }
             RecordStoreError::DBFailed(err) => std::fmt::Display::fmt(err, f),
+            RecordStoreError::SqliteFailed(err) => std::fmt::Display::fmt(err, f),
+            RecordStoreError::IOFailed(err) => std::fmt::Display::fmt(err, f),
+            RecordStoreError::EncodingFailed(err) => std::fmt::Display::fmt(err, f),
+            RecordStoreError::JsonFailed(err) => std::fmt::Display::fmt(err, f),
+            RecordStoreError::UnsupportedSchema(version) => {
+                write!(f, "Record store has schema v{} which is not supported by this version of the app", version)
+            }
+            RecordStoreError::EncryptedStore => {
+                write!(f, "Record store is encrypted, set `encrypted = true` in the [store] section of the config file")
+            }
+            RecordStoreError::NotEncrypted => {
+                write!(f, "Record store is configured to be encrypted but it is not, run `trackr encrypt` first")
+            }
+            RecordStoreError::WrongPassphrase => write!(f, "Wrong passphrase"),
+            RecordStoreError::PassphrasesDiffer => write!(f, "Passphrases do not match"),
+            RecordStoreError::DecryptionFailed => {
+                write!(f, "Could not decrypt records, they are either corrupted or encrypted with another key")
+            }
+            RecordStoreError::Corrupted(err) => write!(f, "{}\n{}", err, [
+                "Record store is damaged and was not read to avoid losing tracking data.",
+                "Run `trackr fsck` to check it and `trackr fsck --salvage` to keep the readable days",
+            ].join("\n")),
+            RecordStoreError::Locked(owner) => {
+                let owner = owner.map(|pid| format!(" (pid {})", pid)).unwrap_or_default();
+
+                write!(f, "Record store is being written to by another trackr instance{}, stop it first", owner)
+            }
+            RecordStoreError::ReadOnly => write!(f, "Record store was opened for reading only"),
+            RecordStoreError::InvalidEdit(reason) => write!(f, "Could not edit records: {}", reason),
+            RecordStoreError::ServerUnreachable(err) => write!(f, "Could not reach the trackr server: {}", err),
+            RecordStoreError::ServerRejected(status, message) => {
+                write!(f, "trackr server refused the records with status {}: {}", status, message)
+            }
+            RecordStoreError::InvalidConfig(reason) => write!(f, "Invalid store configuration: {}", reason),
             RecordStoreError::NoDataOnDate(date) => {
                 write!(f, "Given date is not registered in the database {}", date)
             }
@@ -37,8 +138,24 @@ impl std::fmt::Display for RecordStoreError {
 impl std::error::Error for RecordStoreError {
     fn description(&self) -> &str {
         match self {
-            RecordStoreError::FailedToSwitchDB => "Failed to switch database to a new file",
             RecordStoreError::DBFailed(_) => "Internal DB error",
+            RecordStoreError::SqliteFailed(_) => "Internal SQLite error",
+            RecordStoreError::IOFailed(_) => "Failed to access database files",
+            RecordStoreError::EncodingFailed(_) => "Failed to encode or decode records",
+            RecordStoreError::JsonFailed(_) => "Failed to encode or decode records as JSON",
+            RecordStoreError::UnsupportedSchema(_) => "Record store schema is not supported",
+            RecordStoreError::EncryptedStore => "Record store is encrypted",
+            RecordStoreError::NotEncrypted => "Record store is not encrypted",
+            RecordStoreError::WrongPassphrase => "Wrong passphrase",
+            RecordStoreError::PassphrasesDiffer => "Passphrases do not match",
+            RecordStoreError::DecryptionFailed => "Could not decrypt records",
+            RecordStoreError::Corrupted(_) => "Record store is damaged",
+            RecordStoreError::Locked(_) => "Record store is locked by another process",
+            RecordStoreError::ReadOnly => "Record store was opened for reading only",
+            RecordStoreError::InvalidEdit(_) => "Could not edit records",
+            RecordStoreError::ServerUnreachable(_) => "Could not reach the trackr server",
+            RecordStoreError::ServerRejected(_, _) => "trackr server refused the records",
+            RecordStoreError::InvalidConfig(_) => "Invalid store configuration",
             RecordStoreError::NoDataOnDate(_) => "Given date is not registered in the database",
         }
     }
@@ -54,6 +171,30 @@ impl From<RustbreakError> for RecordStoreError {
     }
 }
 
+impl From<rusqlite::Error> for RecordStoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        RecordStoreError::SqliteFailed(err)
+    }
+}
+
+impl From<std::io::Error> for RecordStoreError {
+    fn from(err: std::io::Error) -> Self {
+        RecordStoreError::IOFailed(err)
+    }
+}
+
+impl From<bincode::Error> for RecordStoreError {
+    fn from(err: bincode::Error) -> Self {
+        RecordStoreError::EncodingFailed(err)
+    }
+}
+
+impl From<serde_json::Error> for RecordStoreError {
+    fn from(err: serde_json::Error) -> Self {
+        RecordStoreError::JsonFailed(err)
+    }
+}
+
 /// Gets application's data directory where activity records are stored.
 ///
 /// If such directory doesn't exist, attempts to create one
@@ -71,31 +212,185 @@ pub fn get_path_for_db(dir_path: &Path) -> PathBuf {
     dir_path.join(String::from("records.db"))
 }
 
+/// Directory holding a snapshot file per tracking day
+pub fn get_path_for_days(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("days"))
+}
+
+pub fn get_path_for_day(dir_path: &Path, date_key: &str) -> PathBuf {
+    get_path_for_days(dir_path).join(format!("{}.db", date_key))
+}
+
+pub fn get_path_for_journal(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("records.journal"))
+}
+
+pub fn get_path_for_sqlite(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("records.sqlite3"))
+}
+
+pub fn get_path_for_key_params(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("encryption.params"))
+}
+
+/// Key titles are hashed with by privacy rules, see `HashKey`
+pub fn get_path_for_hash_key(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("hash.key"))
+}
+
+pub fn get_path_for_archive(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("archive"))
+}
+
+pub fn get_path_for_lock(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("trackr.lock"))
+}
+
+pub fn get_path_for_checkpoint(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("current.checkpoint"))
+}
+
+pub fn get_path_for_search_index(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("search.index"))
+}
+
+/// File listing tracking dates that changed since they were indexed for search
+pub fn get_path_for_search_stale(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("search.stale"))
+}
+
+pub fn get_path_for_stats_cache(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("stats.cache"))
+}
+
+/// File listing tracking dates that changed since their stats were cached
+pub fn get_path_for_stats_stale(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("stats.stale"))
+}
+
+/// Edits made to records by hand, see `AuditLog`
+pub fn get_path_for_audit_log(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("audit.log"))
+}
+
+/// Changes that are yet to reach the server records are mirrored to
+pub fn get_path_for_outbox(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("remote.outbox"))
+}
+
+/// Directory of a trackr server holding a file store per client
+pub fn get_path_for_clients(dir_path: &Path) -> PathBuf {
+    dir_path.join(String::from("clients"))
+}
+
+/// Converts time to milliseconds since UNIX epoch
+pub fn to_millis(time: &SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Converts milliseconds since UNIX epoch back to time
+pub fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(std::cmp::max(millis, 0) as u64)
+}
+
+/// Replaces contents of a file so that readers never see it half-written.
+///
+/// Readers of the store save caches such as the search index alongside the writer,
+/// so the temporary file is named after the process to keep them from renaming each other's
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    rename(&temp_path, path)
+}
+
 pub fn create_memory_db() -> Result<Database, RustbreakError> {
-    let db =
-        MemoryDatabase::<HashMap<String, Vec<ActivityRecord>>, Bincode>::memory(HashMap::new())?;
+    let db = MemoryDatabase::<Snapshot, Bincode>::memory(Snapshot::default())?;
 
     Ok(db.with_backend(Box::new(MemoryBackend::default())))
 }
 
-pub fn switch_db(db: Database, dir_path: &Path) -> Result<Database, RecordStoreError> {
-    let path = get_path_for_db(dir_path);
-    let file_existed = path.as_path().exists();
-    let backend = FileBackend::open(path).map_err(|_| RecordStoreError::FailedToSwitchDB)?;
-    let db: Database = db.with_backend(Box::new(backend));
+/// Notes that records of a tracking date changed for the caches that are kept of them, such as the search index
+pub fn note_changed_day(path: &Path, date: &NaiveDate) -> Result<(), RecordStoreError> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    writeln!(file, "{}", TrackingDate::from(date).0)?;
+    Ok(())
+}
 
-    if !file_existed {
-        // DB struct tries to load from file upon creation
-        // which causes EOF in case of freshly-created file
-        // To mitigate that - save db immediately when creating a new file
-        db.save()?;
+/// Drops notes written by `note_changed_day` once every cache kept of them has applied them
+pub fn drop_changed_days(path: &Path) -> Result<(), RecordStoreError> {
+    if path.exists() {
+        OpenOptions::new().write(true).open(path)?.set_len(0)?;
     }
+    Ok(())
+}
+
+/// Reads dates noted past `offset` of a file written by `note_changed_day` and moves `offset` past them.
+///
+/// Returns `None` if notes were dropped since `offset` was taken, nothing tells which days changed then
+pub fn read_changed_days(path: &Path, offset: &mut u64) -> Result<Option<HashSet<String>>, RecordStoreError> {
+    let length = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(err) => return Err(RecordStoreError::from(err)),
+    };
 
-    Ok(db)
+    let is_dropped = length < *offset;
+
+    if is_dropped {
+        *offset = 0;
+    }
+    // Nothing was noted since the last read, so the notes are not read again
+    if length == *offset {
+        return Ok(if is_dropped { None } else { Some(HashSet::new()) });
+    }
+
+    let mut unread: Vec<u8> = vec![];
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(*offset))?;
+    file.read_to_end(&mut unread)?;
+    // A note that is still being written is left for the next read
+    let complete_length = unread.iter().rposition(|byte| *byte == b'\n').map_or(0, |position| position + 1);
+
+    *offset += complete_length as u64;
+    match is_dropped {
+        true => Ok(None),
+        false => Ok(Some(String::from_utf8_lossy(&unread[..complete_length]).lines().map(String::from).collect())),
+    }
 }
 
-pub fn create_file_db(dir_path: &Path) -> Result<Database, RecordStoreError> {
-    let memory_db = create_memory_db()?;
+/// Reads a snapshot file, returns `None` if there is no such file yet
+pub fn read_snapshot(path: &Path, cipher: Option<&Cipher>) -> Result<Option<Snapshot>, RecordStoreError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let bytes = open_file(fs::read(path)?, cipher)?;
+    Ok(Some(decode_snapshot(&bytes)?))
+}
 
-    switch_db(memory_db, dir_path)
+pub fn write_snapshot(path: &Path, snapshot: &Snapshot, cipher: Option<&Cipher>) -> Result<(), RecordStoreError> {
+    let bytes = seal_file(encode_snapshot(snapshot), cipher);
+
+    write_atomically(path, &bytes)?;
+    Ok(())
+}
+
+/// Creates an empty scratch directory for tests that touch the file system
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("trackr-{}-{}", name, std::process::id()));
+
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
 }
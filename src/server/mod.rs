@@ -0,0 +1,184 @@
+//! Self-hostable HTTP/JSON service keeping records of many machines.
+//!
+//! Every request must carry `Authorization: Bearer <token>`. Routes:
+//!
+//! - `GET /clients` lists clients that have sent records
+//! - `GET /clients/<name>/days` lists tracking dates of a client
+//! - `GET /clients/<name>/days/<YYYY-MM-DD>` gets records of a client on a date
+//! - `POST /clients/<name>/ops` applies an `OpBatch`, answers with `Applied`
+use crate::record_store::{decode_client_name, Applied, OpBatch, RecordStoreError, ServerStore};
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::{io::Read, net::SocketAddr};
+use tiny_http::{Header, Method, Request, Response};
+
+pub const DEFAULT_ADDRESS: &'static str = "127.0.0.1:8631";
+/// Largest request body accepted, in bytes
+const MAX_BODY_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum ServerError {
+    BindFailed(String),
+    StoreFailed(RecordStoreError),
+}
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::BindFailed(err) => write!(f, "Could not listen for connections: {}", err),
+            ServerError::StoreFailed(err) => std::fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {
+    fn description(&self) -> &str {
+        match self {
+            ServerError::BindFailed(_) => "Could not listen for connections",
+            ServerError::StoreFailed(_) => "Failed to access records",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+impl From<RecordStoreError> for ServerError {
+    fn from(err: RecordStoreError) -> Self {
+        ServerError::StoreFailed(err)
+    }
+}
+
+/// Status and JSON body of an answer
+type Reply = (u16, String);
+
+fn json_reply<T: Serialize>(value: &T) -> Reply {
+    match serde_json::to_string(value) {
+        Ok(body) => (200, body),
+        Err(err) => error_reply(500, &err.to_string()),
+    }
+}
+
+fn error_reply(status: u16, message: &str) -> Reply {
+    (status, serde_json::json!({ "error": message }).to_string())
+}
+
+/// Compares secrets in time that doesn't depend on where they differ
+fn secrets_match(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+pub struct Server {
+    http: tiny_http::Server,
+    store: ServerStore,
+    token: String,
+}
+
+impl Server {
+    /// Listens on an address such as `127.0.0.1:8631`, port 0 picks a free one
+    pub fn bind(address: &str, store: ServerStore, token: String) -> Result<Self, ServerError> {
+        let http = tiny_http::Server::http(address).map_err(|err| ServerError::BindFailed(err.to_string()))?;
+
+        Ok(Self { http, store, token })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.http.server_addr()
+    }
+
+    /// Answers requests until the process is stopped
+    pub fn run(&self) {
+        for request in self.http.incoming_requests() {
+            // A client that went away must not stop the others
+            if let Err(err) = self.respond(request) {
+                eprintln!("Could not answer a request: {}", err);
+            }
+        }
+    }
+
+    fn respond(&self, mut request: Request) -> Result<(), std::io::Error> {
+        let (status, body) = match self.is_authorized(&request) {
+            true => self.route(&mut request),
+            false => error_reply(401, "Missing or wrong token"),
+        };
+        let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+            .expect("header is valid ASCII");
+
+        request.respond(Response::from_string(body).with_status_code(status).with_header(content_type))
+    }
+
+    fn is_authorized(&self, request: &Request) -> bool {
+        let expected = format!("Bearer {}", self.token);
+
+        request.headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .map_or(false, |header| secrets_match(header.value.as_str().as_bytes(), expected.as_bytes()))
+    }
+
+    fn route(&self, request: &mut Request) -> Reply {
+        let path = String::from(request.url().split('?').next().unwrap_or_default());
+        let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+        let method = request.method().clone();
+
+        match (method, segments.as_slice()) {
+            (Method::Get, ["clients"]) => self.reply(self.store.clients()),
+            (Method::Get, ["clients", client, "days"]) => self.with_client(client, |client| {
+                let dates = self.store.query_dates(&client)?;
+
+                Ok(dates.iter().map(|date| date.format("%Y-%m-%d").to_string()).collect::<Vec<String>>())
+            }),
+            (Method::Get, ["clients", client, "days", date]) => {
+                let date = match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                    Ok(date) => date,
+                    Err(_) => return error_reply(400, "Dates must look like YYYY-MM-DD"),
+                };
+
+                self.with_client(client, |client| self.store.query_records(&client, &date))
+            },
+            (Method::Post, ["clients", client, "ops"]) => {
+                let client = match decode_client_name(client) {
+                    Some(client) => client,
+                    None => return error_reply(400, "Malformed client name"),
+                };
+                let mut body = String::new();
+
+                if let Err(err) = request.as_reader().take(MAX_BODY_SIZE).read_to_string(&mut body) {
+                    return error_reply(400, &err.to_string());
+                }
+                let batch: OpBatch = match serde_json::from_str(&body) {
+                    Ok(batch) => batch,
+                    Err(err) => return error_reply(400, &err.to_string()),
+                };
+                if batch.ops.iter().any(|op| op.date().is_none()) {
+                    return error_reply(400, "Dates must look like YYYY-MM-DD");
+                }
+
+                self.reply(self.store.apply(&client, batch).map(|applied| Applied { applied }))
+            },
+            _ => error_reply(404, "Unknown route"),
+        }
+    }
+
+    /// Runs a query on records of a known client given by an encoded name
+    fn with_client<T, F>(&self, encoded: &str, f: F) -> Reply
+    where
+        T: Serialize,
+        F: FnOnce(String) -> Result<T, RecordStoreError>,
+    {
+        match decode_client_name(encoded) {
+            Some(client) if self.store.has_client(&client) => self.reply(f(client)),
+            Some(_) => error_reply(404, "Unknown client"),
+            None => error_reply(400, "Malformed client name"),
+        }
+    }
+
+    fn reply<T: Serialize>(&self, result: Result<T, RecordStoreError>) -> Reply {
+        match result {
+            Ok(value) => json_reply(&value),
+            Err(RecordStoreError::NoDataOnDate(_)) => error_reply(404, "No records on this date"),
+            Err(err) => error_reply(500, &err.to_string()),
+        }
+    }
+}
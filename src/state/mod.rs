@@ -1,7 +1,9 @@
 use crate::{
     window_manager::MouseState,
     record_store::{
+        ActivityRecord,
         Archetype,
+        ForgetMode,
         RecordTracker,
         RecordStore,
         RecordStoreConfig
@@ -9,17 +11,28 @@ use crate::{
     classifier::{
         Classifier, ClassifierConfig
     },
+    control::{
+        describe_mode, ControlCommand, ControlError, ControlSocket
+    },
     tui::{
         Router, Routes
     },
 };
 use std::time;
 
+/// How often the record that is still being tracked is saved to disk
+const CHECKPOINT_INTERVAL: time::Duration = time::Duration::from_secs(30);
+
 pub struct AppState {
     // Tracking information
     last_moment_active: time::SystemTime,
+    last_checkpoint: time::SystemTime,
     last_mouse_position: (i32, i32),
     last_active_window: Option<Archetype>,
+    /// Windows are tracked without their titles while set
+    incognito: bool,
+    /// Absent if the socket could not be set up, the tracker still runs
+    control_socket: Option<ControlSocket>,
     
     record_tracker: RecordTracker,
     record_store: RecordStore,
@@ -31,27 +44,77 @@ pub struct AppState {
 
 impl AppState {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let record_store_config = RecordStoreConfig::default();
+        let record_store_config = RecordStoreConfig::load()?;
         let record_classifier_config = ClassifierConfig::default();
+        let data_dir = record_store_config.data_dir.clone();
 
-        Ok(Self {
+        let mut state = Self {
             last_moment_active: time::SystemTime::now(),
+            last_checkpoint: time::SystemTime::now(),
             last_mouse_position: (0, 0),
             router: Router {
                 active_route: Routes::Main,
             },
             last_active_window: None,
+            incognito: false,
+            control_socket: None,
             
             record_tracker: RecordTracker::new(),
             record_store: RecordStore::new(record_store_config)?,
             record_classifier: Classifier::from(record_classifier_config)
-        })
+        };
+
+        // Bound once the store is open, so that the store lock is held
+        match ControlSocket::bind(&data_dir) {
+            Ok(socket) => state.control_socket = Some(socket),
+            Err(err) => eprintln!("Could not listen for commands of `trackr incognito`: {}", err),
+        }
+
+        state.recover_checkpoint()?;
+        if let Err(err) = state.record_store.apply_retention() {
+            eprintln!("Could not archive days past the retention window: {}", err);
+        }
+        Ok(state)
+    }
+
+    /// Stores the record that was still open when the app was stopped last time
+    fn recover_checkpoint(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let checkpoint = match self.record_store.load_checkpoint() {
+            Ok(checkpoint) => checkpoint,
+            Err(err) => {
+                eprintln!("Could not recover the record that was being tracked when the app was stopped: {}", err);
+                None
+            }
+        };
+
+        // A checkpoint whose record was pushed before the app stopped would be stored twice otherwise
+        let checkpoint = match checkpoint {
+            Some(checkpoint) if self.record_store.is_checkpoint_stored(&checkpoint)? => None,
+            checkpoint => checkpoint,
+        };
+
+        if let Some(checkpoint) = checkpoint {
+            let mut record = checkpoint.into_record();
+
+            self.finish_record(&mut record);
+            self.record_store.push_record(record)?;
+        }
+
+        self.record_store.clear_checkpoint()?;
+        Ok({})
     }
 
     pub fn update_window_info(&mut self, info: Option<Archetype>) -> Result<(), Box<dyn std::error::Error>> {
+        // Nothing past this point, checkpoints included, sees what privacy rules hide
+        let info = self.record_classifier.apply_privacy(info, self.record_store.hash_key());
+        let info = match self.incognito {
+            true => info.map(Archetype::without_title),
+            false => info,
+        };
         let is_same_window = info == self.last_active_window;
         let is_afk = is_same_window && self.get_afk_seconds() > self.record_classifier.afk_timeout.as_secs();
         let info_clone = info.clone();
+        let previous_archetype = self.record_tracker.get_current_archetype().clone();
 
         let mut record = if is_afk {
             self.record_tracker.ping(Some(Archetype::AFK))
@@ -63,15 +126,109 @@ impl AppState {
             self.record_tracker.ping(info)
         };
 
+        let is_tracking_changed = previous_archetype != *self.record_tracker.get_current_archetype();
+
         match record {
             Some(ref mut rec) => {
-                self.record_classifier.classify(rec);
+                self.finish_record(rec);
                 self.record_store.push_record(rec.clone())?;
 
             },
             _ => {}
         };
 
+        if is_tracking_changed || self.is_checkpoint_due() {
+            self.save_checkpoint()?;
+        }
+
+        Ok({})
+    }
+
+    pub fn is_incognito(&self) -> bool {
+        self.incognito
+    }
+
+    /// Switches incognito mode, the current record is closed on the next update of window info
+    pub fn set_incognito(&mut self, incognito: bool) {
+        self.incognito = incognito;
+    }
+
+    pub fn toggle_incognito(&mut self) {
+        self.set_incognito(!self.incognito);
+    }
+
+    /// Runs commands sent by `trackr incognito` and other clients of the control socket.
+    ///
+    /// Returns why connections of clients were dropped, they are shown rather than printed over the TUI
+    pub fn handle_control_requests(&mut self) -> Vec<ControlError> {
+        let mut socket = match self.control_socket.take() {
+            Some(socket) => socket,
+            None => return vec![],
+        };
+
+        let errors = socket.poll(|command| {
+            match command {
+                ControlCommand::Incognito(Some(incognito)) => self.set_incognito(incognito),
+                ControlCommand::Incognito(None) => self.toggle_incognito(),
+                ControlCommand::Status => {},
+                ControlCommand::Forget(period, mode) => {
+                    return self.forget_last(period, mode)
+                        .map(|forgotten| format!("forgot {} records", forgotten))
+                        .map_err(|err| err.to_string());
+                },
+            }
+            Ok(describe_mode(self.incognito))
+        });
+        self.control_socket = Some(socket);
+        errors
+    }
+
+    /// Forgets what was tracked over a period ending now, both stored records and the record being tracked.
+    ///
+    /// Returns the number of stored records that were changed
+    pub fn forget_last(&mut self, period: time::Duration, mode: ForgetMode) -> Result<usize, Box<dyn std::error::Error>> {
+        let now = time::SystemTime::now();
+        let since = now.checked_sub(period).unwrap_or(time::UNIX_EPOCH);
+        let forgotten = self.record_store.forget(&since, &now, mode)?;
+
+        for mut record in self.record_tracker.forget(&since, &now, mode) {
+            self.finish_record(&mut record);
+            self.record_store.push_record(record)?;
+        }
+        self.save_checkpoint()?;
+        Ok(forgotten)
+    }
+
+    /// Closes and stores the record that is being tracked, called before the app exits
+    pub fn shutdown(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(mut record) = self.record_tracker.flush() {
+            self.finish_record(&mut record);
+            self.record_store.push_record(record)?;
+        }
+
+        self.record_store.clear_checkpoint()?;
+        Ok({})
+    }
+
+    /// Classifies a closed record and stamps it with the name of this machine
+    fn finish_record(&self, record: &mut ActivityRecord) {
+        self.record_classifier.classify(record);
+        record.machine = Some(String::from(self.record_classifier.machine_name()));
+    }
+
+    fn is_checkpoint_due(&self) -> bool {
+        time::SystemTime::now()
+            .duration_since(self.last_checkpoint)
+            .map_or(true, |elapsed| elapsed >= CHECKPOINT_INTERVAL)
+    }
+
+    fn save_checkpoint(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.record_tracker.checkpoint() {
+            Some(checkpoint) => self.record_store.save_checkpoint(&checkpoint)?,
+            None => self.record_store.clear_checkpoint()?,
+        };
+
+        self.last_checkpoint = time::SystemTime::now();
         Ok({})
     }
 
@@ -92,6 +249,13 @@ impl AppState {
         }
     }
 
+    /// Takes idle time reported by the OS in place of polled input
+    pub fn update_idle_time(&mut self, idle_time: time::Duration) {
+        let now = time::SystemTime::now();
+
+        self.last_moment_active = now.checked_sub(idle_time).unwrap_or(now);
+    }
+
     pub fn get_afk_seconds(&self) -> u64 {
         time::SystemTime::now()
             .duration_since(self.last_moment_active)
@@ -0,0 +1,341 @@
+//! Totals of tracked time over ranges of tracking dates.
+//!
+//! All durations are kept in whole seconds, so that stats of days can be added up
+//! and serialized as they are.
+use crate::record_store::{
+    ActivityRecord, Archetype, ArchivedKind, ArchivedTotal, ProductivityStatus, RecordFilter, RecordStore, RecordStoreError,
+};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Timelike};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+/// Tracked time of one or more tracking days broken down in several ways
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub total_secs: u64,
+    pub afk_secs: u64,
+    /// Time of days past the retention window, which only totals per activity are kept of
+    pub archived_secs: u64,
+    /// Keyed by `productive`, `neutral` and `leisure`
+    pub by_productivity: BTreeMap<String, u64>,
+    /// Keyed by activity names of productive and leisure records
+    pub by_activity: BTreeMap<String, u64>,
+    pub by_app: BTreeMap<String, u64>,
+    pub by_class: BTreeMap<String, u64>,
+    /// Keyed by machine names, `unknown` for records tracked before machines were named
+    pub by_machine: BTreeMap<String, u64>,
+    /// Records with several tags count towards each of them, untagged ones towards none
+    pub by_tag: BTreeMap<String, u64>,
+    /// Keyed by project names, records of no project are left out
+    pub by_project: BTreeMap<String, u64>,
+    /// Time within each hour of the local clock, 0 to 23
+    pub by_hour: Vec<u64>,
+    /// Time of tracking dates falling on each weekday, Monday first
+    pub by_weekday: Vec<u64>,
+    /// Window titles, see `top_titles`
+    #[serde(skip)]
+    pub by_title: HashMap<String, u64>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            total_secs: 0,
+            afk_secs: 0,
+            archived_secs: 0,
+            by_productivity: BTreeMap::new(),
+            by_activity: BTreeMap::new(),
+            by_app: BTreeMap::new(),
+            by_class: BTreeMap::new(),
+            by_machine: BTreeMap::new(),
+            by_tag: BTreeMap::new(),
+            by_project: BTreeMap::new(),
+            by_hour: vec![0; 24],
+            by_weekday: vec![0; 7],
+            by_title: HashMap::new(),
+        }
+    }
+}
+
+impl Stats {
+    /// Gets stats of records stored under a tracking date
+    pub fn of_day(date: &NaiveDate, records: &[ActivityRecord]) -> Self {
+        let mut stats = Self::default();
+
+        for record in records {
+            stats.add_record(date, record);
+        }
+        stats
+    }
+
+    /// Counts a record stored under a tracking date
+    pub fn add_record(&mut self, date: &NaiveDate, record: &ActivityRecord) {
+        let secs = record.duration().as_secs();
+        let app = match &record.archetype {
+            Archetype::ActiveWindow(_, name, class) | Archetype::Incognito(name, class) => Some((name, class)),
+            Archetype::AFK | Archetype::Private => None,
+        };
+        let is_afk = record.archetype == Archetype::AFK;
+
+        self.add_activity(date, secs, &record.productivity, app, is_afk, &record.machine, &record.project);
+        for tag in &record.tags {
+            *self.by_tag.entry(tag.clone()).or_insert(0) += secs;
+        }
+        self.add_hours(record);
+        if let Archetype::ActiveWindow(title, _, _) = &record.archetype {
+            if !title.is_empty() {
+                *self.by_title.entry(title.clone()).or_insert(0) += secs;
+            }
+        }
+    }
+
+    /// Counts time of a day past the retention window, its tags, hours and titles aren't kept
+    pub fn add_archived(&mut self, date: &NaiveDate, total: &ArchivedTotal) {
+        let is_afk = total.kind == ArchivedKind::AFK;
+
+        self.archived_secs += total.secs;
+        self.add_activity(date, total.secs, &total.productivity, total.kind.app(), is_afk, &total.machine, &total.project);
+    }
+
+    fn add_activity(
+        &mut self,
+        date: &NaiveDate,
+        secs: u64,
+        productivity: &ProductivityStatus,
+        app: Option<(&String, &String)>,
+        is_afk: bool,
+        machine: &Option<String>,
+        project: &Option<String>,
+    ) {
+        let productivity = match productivity {
+            ProductivityStatus::Productive(activity) => ("productive", Some(activity)),
+            ProductivityStatus::Leisure(activity) => ("leisure", Some(activity)),
+            ProductivityStatus::Neutral => ("neutral", None),
+        };
+
+        self.total_secs += secs;
+        *self.by_productivity.entry(String::from(productivity.0)).or_insert(0) += secs;
+        if let Some(activity) = productivity.1 {
+            *self.by_activity.entry(activity.clone()).or_insert(0) += secs;
+        }
+        *self.by_machine.entry(machine.clone().unwrap_or_else(|| String::from("unknown"))).or_insert(0) += secs;
+        if let Some(project) = project {
+            *self.by_project.entry(project.clone()).or_insert(0) += secs;
+        }
+        self.by_weekday[date.weekday().num_days_from_monday() as usize] += secs;
+
+        // Private windows only count towards totals
+        if let Some((name, class)) = app {
+            *self.by_app.entry(name.clone()).or_insert(0) += secs;
+            *self.by_class.entry(class.clone()).or_insert(0) += secs;
+        }
+        if is_afk {
+            self.afk_secs += secs;
+        }
+    }
+
+    /// Splits a record at full hours of the local clock
+    fn add_hours(&mut self, record: &ActivityRecord) {
+        let end = DateTime::<Local>::from(record.time_range.1);
+        let mut start = DateTime::<Local>::from(record.time_range.0);
+
+        while start < end {
+            let hour_start = start.with_minute(0).and_then(|time| time.with_second(0)).and_then(|time| time.with_nanosecond(0));
+            let next_hour = hour_start.map_or(end, |time| std::cmp::min(time + Duration::hours(1), end));
+
+            self.by_hour[start.hour() as usize] += (next_hour - start).num_seconds().max(0) as u64;
+            start = next_hour;
+        }
+    }
+
+    /// Gets stats of records that `filter` keeps on tracking dates from `from` to `to` inclusive.
+    ///
+    /// Unfiltered stats of closed days are cached by the store
+    pub fn for_dates(store: &RecordStore, from: &NaiveDate, to: &NaiveDate, filter: &RecordFilter) -> Result<Self, RecordStoreError> {
+        match filter.is_empty() {
+            true => store.stats(from, to),
+            false => Self::filtered(store, from, to, filter),
+        }
+    }
+
+    fn filtered(store: &RecordStore, from: &NaiveDate, to: &NaiveDate, filter: &RecordFilter) -> Result<Self, RecordStoreError> {
+        let mut stats = Self::default();
+        let (dates, archived) = tracked_dates(store)?;
+
+        for date in dates {
+            if date >= *from && date <= *to {
+                stats.merge(&Self::of_tracked_day(store, &date, archived.contains(&date), filter)?);
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Gets stats of what `filter` keeps of a day, records stored after the day was archived included
+    pub fn of_tracked_day(
+        store: &RecordStore,
+        date: &NaiveDate,
+        is_archived: bool,
+        filter: &RecordFilter,
+    ) -> Result<Self, RecordStoreError> {
+        let records: Vec<ActivityRecord> = match store.query_records_by_date(date) {
+            Err(RecordStoreError::NoDataOnDate(_)) => vec![],
+            records => records?,
+        };
+        let mut stats = Self::of_day(date, &records.into_iter().filter(|record| filter.matches(record)).collect::<Vec<_>>());
+
+        if is_archived {
+            for total in store.query_archived_day(date)?.totals.iter().filter(|total| filter.matches_archived(total)) {
+                stats.add_archived(date, total);
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Adds up stats of another range of days
+    pub fn merge(&mut self, other: &Stats) {
+        self.total_secs += other.total_secs;
+        self.afk_secs += other.afk_secs;
+        self.archived_secs += other.archived_secs;
+        for (map, other_map) in vec![
+            (&mut self.by_productivity, &other.by_productivity),
+            (&mut self.by_activity, &other.by_activity),
+            (&mut self.by_app, &other.by_app),
+            (&mut self.by_class, &other.by_class),
+            (&mut self.by_machine, &other.by_machine),
+            (&mut self.by_tag, &other.by_tag),
+            (&mut self.by_project, &other.by_project),
+        ] {
+            for (key, secs) in other_map {
+                *map.entry(key.clone()).or_insert(0) += secs;
+            }
+        }
+        for (title, secs) in &other.by_title {
+            *self.by_title.entry(title.clone()).or_insert(0) += secs;
+        }
+        for (hour, secs) in other.by_hour.iter().enumerate() {
+            self.by_hour[hour] += secs;
+        }
+        for (weekday, secs) in other.by_weekday.iter().enumerate() {
+            self.by_weekday[weekday] += secs;
+        }
+    }
+
+    /// Gets up to `count` titles with the most time, longest first
+    pub fn top_titles(&self, count: usize) -> Vec<(String, u64)> {
+        let mut titles: Vec<(String, u64)> = self.by_title.iter()
+            .map(|(title, secs)| (title.clone(), *secs))
+            .collect();
+
+        titles.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        titles.truncate(count);
+        titles
+    }
+}
+
+/// Lists tracking dates with records or archived totals, along with the archived ones
+pub fn tracked_dates(store: &RecordStore) -> Result<(Vec<NaiveDate>, HashSet<NaiveDate>), RecordStoreError> {
+    let archived: HashSet<NaiveDate> = store.query_archived_dates()?.into_iter().collect();
+    let mut dates = store.query_dates()?;
+
+    dates.extend(archived.iter().cloned());
+    dates.sort();
+    dates.dedup();
+    Ok((dates, archived))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_store::{test_dir, RecordStoreConfig};
+    use chrono::TimeZone;
+    use std::{collections::BTreeSet, time::SystemTime};
+
+    fn local(day: u32, hour: u32, minute: u32) -> SystemTime {
+        SystemTime::from(Local.ymd(2020, 6, day).and_hms(hour, minute, 0))
+    }
+
+    fn record(start: SystemTime, end: SystemTime, title: &str, productivity: ProductivityStatus) -> ActivityRecord {
+        ActivityRecord {
+            time_range: (start, end),
+            productivity,
+            archetype: Archetype::ActiveWindow(String::from(title), String::from("code"), String::from("Code")),
+            machine: None,
+            tags: BTreeSet::new(),
+            note: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn breaks_down_tracked_time() {
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let coding = ProductivityStatus::Productive(String::from("coding"));
+        let stats = Stats::of_day(&date, &[
+            record(local(1, 9, 30), local(1, 10, 15), "main.rs", coding.clone()),
+            record(local(1, 10, 15), local(1, 10, 30), "README.md", ProductivityStatus::Neutral),
+            ActivityRecord {
+                tags: vec![String::from("incident-482")].into_iter().collect(),
+                project: Some(String::from("client-x")),
+                ..record(local(1, 10, 30), local(1, 11, 0), "main.rs", coding)
+            },
+            ActivityRecord {
+                archetype: Archetype::AFK,
+                ..record(local(1, 11, 0), local(1, 11, 10), "", ProductivityStatus::Neutral)
+            },
+        ]);
+
+        assert_eq!(stats.total_secs, 100 * 60);
+        assert_eq!(stats.afk_secs, 10 * 60);
+        assert_eq!(stats.by_activity.get("coding"), Some(&(75 * 60)));
+        assert_eq!(stats.by_productivity.get("neutral"), Some(&(25 * 60)));
+        assert_eq!(stats.by_app.get("code"), Some(&(90 * 60)));
+        assert_eq!(stats.by_tag.get("incident-482"), Some(&(30 * 60)));
+        assert_eq!(stats.by_project.get("client-x"), Some(&(30 * 60)));
+        assert_eq!(stats.by_project.len(), 1);
+        assert_eq!(&stats.by_hour[9..12], &[30 * 60, 60 * 60, 10 * 60]);
+        // 2020-06-01 is a Monday
+        assert_eq!(stats.by_weekday[0], 100 * 60);
+        assert_eq!(stats.top_titles(1), vec![(String::from("main.rs"), 75 * 60)]);
+    }
+
+    #[test]
+    fn sums_up_days_of_range() {
+        let store = RecordStore::new(RecordStoreConfig::in_dir(test_dir("stats_range"))).unwrap();
+        let (first, last) = (NaiveDate::from_ymd(2020, 6, 1), NaiveDate::from_ymd(2020, 6, 2));
+
+        store.push_record(record(local(1, 9, 0), local(1, 10, 0), "main.rs", ProductivityStatus::Neutral)).unwrap();
+        store.push_record(record(local(2, 9, 0), local(2, 9, 30), "main.rs", ProductivityStatus::Neutral)).unwrap();
+        store.push_record(record(local(3, 9, 0), local(3, 9, 30), "main.rs", ProductivityStatus::Neutral)).unwrap();
+
+        let stats = Stats::for_dates(&store, &first, &last, &RecordFilter::default()).unwrap();
+        assert_eq!(stats.total_secs, 90 * 60);
+        assert_eq!(stats.by_weekday[0..2], [60 * 60, 30 * 60]);
+        assert_eq!(Stats::for_dates(&store, &first, &last, &RecordFilter::default()).unwrap(), stats);
+    }
+
+    #[test]
+    fn counts_archived_days() {
+        let mut config = RecordStoreConfig::in_dir(test_dir("stats_archived"));
+        config.retention_days = Some(30);
+        let store = RecordStore::new(config).unwrap();
+        let date = NaiveDate::from_ymd(2020, 6, 1);
+        let coding = ProductivityStatus::Productive(String::from("coding"));
+
+        store.push_record(record(local(1, 9, 0), local(1, 10, 0), "main.rs", coding.clone())).unwrap();
+        store.apply_retention().unwrap();
+        store.insert_records(vec![ActivityRecord {
+            tags: vec![String::from("review")].into_iter().collect(),
+            ..record(local(1, 11, 0), local(1, 11, 30), "lib.rs", coding)
+        }]).unwrap();
+
+        let stats = Stats::for_dates(&store, &date, &date, &RecordFilter::default()).unwrap();
+        assert_eq!(stats.total_secs, 90 * 60);
+        assert_eq!(stats.archived_secs, 60 * 60);
+        assert_eq!(stats.by_activity.get("coding"), Some(&(90 * 60)));
+        assert_eq!(stats.top_titles(2), vec![(String::from("lib.rs"), 30 * 60)]);
+
+        let filter = RecordFilter { tag: Some(String::from("review")), project: None };
+        assert_eq!(Stats::for_dates(&store, &date, &date, &filter).unwrap().total_secs, 30 * 60);
+    }
+}
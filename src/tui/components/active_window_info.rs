@@ -12,13 +12,15 @@ use tui::{
 };
 
 pub struct ActiveWindowInfo {
-    display: Option<DisplayArchetype>
+    display: Option<DisplayArchetype>,
+    incognito: bool,
 }
 
 impl From<&AppState> for ActiveWindowInfo {
     fn from(state: &AppState) -> Self {
         Self {
-            display: Option::<DisplayArchetype>::from(state)
+            display: Option::<DisplayArchetype>::from(state),
+            incognito: state.is_incognito(),
         }
     }
 }
@@ -26,12 +28,17 @@ impl From<&AppState> for ActiveWindowInfo {
 impl StatefulTUIComponent for ActiveWindowInfo {
     fn tick(&mut self, app_state: &AppState) {
         self.display = Option::<DisplayArchetype>::from(app_state);
+        self.incognito = app_state.is_incognito();
     }
 
     fn render(&self, frame: &mut TUIFrame, chunk: Rect) {
         let window_info_text = (&self.display).to_widgets();
+        let title = match self.incognito {
+            true => " Active window info, incognito (i to leave) ",
+            false => " Active window info (i for incognito) ",
+        };
         let block = Block::default()
-            .title(" Active window info ")
+            .title(title)
             .title_style(*STYLE::STYLE_TEXT_HEADER)
             .borders(Borders::TOP);
         let widget = Paragraph::new(window_info_text.iter())
@@ -1,4 +1,5 @@
 pub mod active_window_info;
+pub mod today_summary;
 use tui::{
     backend::CrosstermBackend,
     Frame,
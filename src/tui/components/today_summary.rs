@@ -0,0 +1,88 @@
+use crate::{
+    AppState,
+    record_store::format_duration,
+    stats::Stats,
+    tui::{
+        style as STYLE,
+        components::{ StatefulTUIComponent, TUIFrame },
+        utils::*
+    }
+};
+use chrono::NaiveDate;
+use tui::{
+    layout::Rect,
+    widgets::{ Paragraph, Block, Borders, Text },
+};
+use std::time::Duration;
+
+/// Totals of the current tracking date, updated with records as they are pushed
+#[derive(Default)]
+pub struct TodaySummary {
+    date: Option<NaiveDate>,
+    /// Number of today's records counted in `stats`
+    counted_records: usize,
+    /// Revision of the store `stats` were counted at
+    revision: u64,
+    stats: Stats,
+}
+
+impl From<&AppState> for TodaySummary {
+    fn from(state: &AppState) -> Self {
+        let mut result = Self::default();
+
+        result.tick(state);
+        result
+    }
+}
+
+impl TodaySummary {
+    fn line(&self, caption: &str, secs: u64) -> String {
+        let share = secs * 100 / self.stats.total_secs.max(1);
+
+        format!("{}{} ({}%)\n", caption, format_duration(Duration::from_secs(secs)), share)
+    }
+}
+
+impl StatefulTUIComponent for TodaySummary {
+    fn tick(&mut self, app_state: &AppState) {
+        let today = app_state.store().today();
+        let revision = app_state.store().revision();
+
+        // Records counted already may have been edited or forgotten since
+        if self.date != Some(today) || self.revision != revision {
+            *self = Self { date: Some(today), revision, ..Self::default() };
+        }
+
+        let new_records = app_state.store().query_records_since(&today, self.counted_records)
+            .unwrap_or(vec![]);
+
+        self.counted_records += new_records.len();
+        for record in &new_records {
+            self.stats.add_record(&today, record);
+        }
+    }
+
+    fn render(&self, frame: &mut TUIFrame, chunk: Rect) {
+        let by_productivity = |name: &str| self.stats.by_productivity.get(name).cloned().unwrap_or(0);
+        let total = format!("{}\n", format_duration(Duration::from_secs(self.stats.total_secs)));
+        let lines = [
+            self.line("Productive: ", by_productivity("productive")),
+            self.line("Leisure: ", by_productivity("leisure")),
+            self.line("AFK: ", self.stats.afk_secs),
+        ];
+        let mut text = vec![
+            Text::Styled(cow("Tracked: "), *STYLE::STYLE_TEXT_HEADER),
+            Text::Raw(cow(&total)),
+        ];
+        text.extend(lines.iter().map(|line| Text::Raw(cow(line))));
+
+        let block = Block::default()
+            .title(" Today ")
+            .title_style(*STYLE::STYLE_TEXT_HEADER)
+            .borders(Borders::TOP);
+        let widget = Paragraph::new(text.iter())
+            .block(block);
+
+        frame.render_widget(widget, chunk)
+    }
+}
@@ -2,23 +2,29 @@ mod components;
 mod style;
 mod routes;
 mod utils;
-use crossterm::terminal::enable_raw_mode;
-use std::io;
+use crossterm::terminal::{enable_raw_mode, disable_raw_mode};
+use std::{io, time::Duration};
 use tui::{
     backend::CrosstermBackend,
     Terminal,
     layout:: { Layout, Direction, Constraint },
-    widgets::{ Block, Borders }
+    widgets::{ Block, Borders, Paragraph, Text }
 };
-use crate::AppState;
-use components::active_window_info::*;
+use crate::{AppState, event::Key, record_store::ForgetMode};
+use components::{ active_window_info::*, today_summary::* };
 
 pub use routes::*;
 
+/// Minutes forgotten by `f` and `F` on the main screen
+const FORGET_MINUTES: u64 = 5;
+
 pub struct Tui {
     terminal: Terminal<CrosstermBackend<io::Stdout>>,
     current_route_component: Box<dyn StatefulTUIComponent>,
-    active_window_component: ActiveWindowInfo
+    active_window_component: ActiveWindowInfo,
+    today_summary_component: TodaySummary,
+    /// Outcome of the last action taken on the main screen, shown in the footer
+    message: Option<String>,
 }
 
 impl Tui {
@@ -28,22 +34,65 @@ impl Tui {
         let backend = CrosstermBackend::new(stdout);
         let current_route_component = Box::new(RouteMain::from(state));
         let active_window_component = ActiveWindowInfo::from(state);
+        let today_summary_component = TodaySummary::from(state);
 
         Ok(Self {
             terminal: Terminal::new(backend)?,
             current_route_component,
-            active_window_component
+            active_window_component,
+            today_summary_component,
+            message: None,
         })
     }
 
     pub fn switch_route(&mut self, route: Routes, state: &AppState) {
         match route {
-            Routes::Main => self.current_route_component = Box::new(RouteMain::from(state))
+            Routes::Main => self.current_route_component = Box::new(RouteMain::from(state)),
+            Routes::Search => self.current_route_component = Box::new(RouteSearch::from(state)),
+            Routes::Edit => self.current_route_component = Box::new(RouteEdit::from(state)),
+            Routes::Projects => self.current_route_component = Box::new(RouteProjects::from(state)),
         }
     }
 
+    /// Switches between routes on their hotkeys, passes other keys to the current route
+    pub fn handle_key(&mut self, key: Key, state: &mut AppState) {
+        let route = match (state.router.get_active_route(), key) {
+            (Routes::Main, Key::Char('i')) => {
+                state.toggle_incognito();
+                return self.active_window_component.tick(state);
+            },
+            (Routes::Main, Key::Char('f')) | (Routes::Main, Key::Char('F')) => {
+                let (mode, done) = match key {
+                    Key::Char('f') => (ForgetMode::Remove, "Forgot"),
+                    _ => (ForgetMode::Anonymize, "Dropped window titles of"),
+                };
+
+                self.message = Some(match state.forget_last(Duration::from_secs(FORGET_MINUTES * 60), mode) {
+                    Ok(_) => format!("{} the last {} minutes", done, FORGET_MINUTES),
+                    Err(err) => format!("Could not forget the last {} minutes: {}", FORGET_MINUTES, err),
+                });
+                // Stats and datasets are updated right away rather than on the next tick
+                return self.tick(state);
+            },
+            (Routes::Main, Key::Char('/')) => Routes::Search,
+            (Routes::Main, Key::Char('e')) => Routes::Edit,
+            (Routes::Main, Key::Char('p')) => Routes::Projects,
+            (Routes::Search, Key::Esc) | (Routes::Edit, Key::Esc) | (Routes::Projects, Key::Esc) => Routes::Main,
+            _ => return self.current_route_component.handle_key(key),
+        };
+
+        state.router.switch(route);
+        self.switch_route(route, state);
+    }
+
+    /// Shows a message in the footer until the next action replaces it
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
     pub fn tick(&mut self, state: &AppState) {
         self.active_window_component.tick(state);
+        self.today_summary_component.tick(state);
         self.current_route_component.tick(state);
     }
 
@@ -52,10 +101,20 @@ impl Tui {
         self.terminal.clear()
     }
 
+    /// Brings the terminal back to the state it was in before the app started
+    pub fn restore(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.terminal.show_cursor()?;
+        disable_raw_mode()?;
+        Ok({})
+    }
+
     pub fn draw(&mut self, state: &AppState) -> std::io::Result<()> {
         self.current_route_component.before_render(state);
         let component = &self.current_route_component;
         let active_window_component = &self.active_window_component;
+        let today_summary_component = &self.today_summary_component;
+        let hint = format!("f forget, F anonymize the last {} minutes, i incognito", FORGET_MINUTES);
+        let message = self.message.as_deref().unwrap_or(&hint);
         
         self.terminal.draw(|ref mut f| {
             let chunks = Layout::default()
@@ -72,14 +131,25 @@ impl Tui {
             let footer_block = Block::default()
                 .title(" Hint ")
                 .borders(Borders::ALL);
+            let footer_text = [Text::raw(message)];
+            let footer = Paragraph::new(footer_text.iter())
+                .block(footer_block);
             
+            today_summary_component.render(f, header_chunks[0]);
             active_window_component.render(f, header_chunks[1]);
             component.render(f, chunks[1]);
-            f.render_widget(footer_block, chunks[2]);
+            f.render_widget(footer, chunks[2]);
         })
     }
 }
 
+impl Drop for Tui {
+    fn drop(&mut self) {
+        // Terminal must not be left in raw mode even if the app bails out with an error
+        self.restore().ok();
+    }
+}
+
 pub use self::{
     components::*,
 };
\ No newline at end of file
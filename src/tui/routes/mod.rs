@@ -1,12 +1,21 @@
+mod route_edit;
 mod route_main;
+mod route_projects;
+mod route_search;
 mod route;
 
+pub use route_edit::*;
 pub use route_main::*;
+pub use route_projects::*;
+pub use route_search::*;
 pub use route::*;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Routes {
-    Main
+    Main,
+    Search,
+    Edit,
+    Projects,
 }
 
 pub struct Router {
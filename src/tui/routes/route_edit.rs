@@ -0,0 +1,304 @@
+use crate::{
+    event::Key,
+    record_store::{ format_duration, ActivityRecord, Archetype, AuditEntry, Edit, ProductivityStatus },
+    state::{ AppState },
+    tui::{
+        style as STYLE,
+        components::{ StatefulTUIComponent, TUIFrame },
+        routes::{ Route },
+        utils::*,
+    }
+};
+use tui::{
+    layout::{ Constraint, Direction, Layout, Rect },
+    widgets::{ Block, Borders, List, ListState, Paragraph, Text },
+};
+use chrono::{ DateTime, Local, NaiveDate };
+
+/// Change asked for with a key, made before the next render
+#[derive(Debug, Clone)]
+enum PendingChange {
+    Edit(Edit),
+    /// Reverts the latest edit that is not reverted yet
+    Undo,
+    /// Shows records of another tracking date
+    SwitchDate(NaiveDate),
+}
+
+/// What is being typed for the selected record
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum InputKind {
+    ProductiveActivity,
+    LeisureActivity,
+    Tag,
+    Note,
+}
+
+#[derive(Debug, Clone)]
+struct Input {
+    kind: InputKind,
+    text: String,
+}
+
+/// Edit screen, opened with `e` from the main screen
+/// Displays:
+/// - Records of a tracking date, today by default
+/// - Outcome of the last edit
+/// - Activity, tag or note being typed, if any
+#[derive(Debug, Default, Clone)]
+pub struct RouteEdit {
+    date: Option<NaiveDate>,
+    records: Vec<ActivityRecord>,
+    list_state: ListState,
+    pending: Option<PendingChange>,
+    input: Option<Input>,
+    /// Outcome of the last edit
+    message: Option<String>,
+}
+impl Route for RouteEdit {}
+
+impl From<&AppState> for RouteEdit {
+    fn from(state: &AppState) -> Self {
+        let mut route = Self::default();
+
+        route.load(state, state.store().today());
+        route
+    }
+}
+
+/// Number of the latest audit entry that is neither a revert nor reverted already
+fn latest_unreverted(entries: &[AuditEntry]) -> Option<usize> {
+    let reverted: Vec<usize> = entries.iter()
+        .filter_map(|entry| match entry.edit {
+            Edit::Revert(number) => Some(number),
+            _ => None,
+        })
+        .collect();
+
+    (1..=entries.len()).rev().find(|number| match entries[number - 1].edit {
+        Edit::Revert(_) => false,
+        _ => !reverted.contains(number),
+    })
+}
+
+impl RouteEdit {
+    fn load(&mut self, state: &AppState, date: NaiveDate) {
+        match state.store().query_records_by_date(&date) {
+            Ok(records) => self.records = records,
+            Err(err) => {
+                self.records = vec![];
+                self.message = Some(err.to_string());
+            }
+        }
+        self.date = Some(date);
+
+        let selected = match self.records.len() {
+            0 => None,
+            len => Some(std::cmp::min(self.list_state.selected().unwrap_or(0), len - 1)),
+        };
+        self.list_state.select(selected);
+    }
+
+    fn selected(&self) -> Option<&ActivityRecord> {
+        self.list_state.selected().and_then(|index| self.records.get(index))
+    }
+
+    /// Asks for an edit of the selected record
+    fn edit_selected<F: FnOnce(&ActivityRecord) -> Edit>(&mut self, f: F) {
+        self.pending = self.selected().map(|record| PendingChange::Edit(f(record)));
+    }
+
+    fn handle_input_key(&mut self, input: &mut Input, event: Key) -> bool {
+        match event {
+            Key::Char(c) => input.text.push(c),
+            Key::Backspace => { input.text.pop(); },
+            Key::Enter => {
+                let text = input.text.trim().to_string();
+
+                if !text.is_empty() {
+                    self.edit_selected(|record| {
+                        let (start, end) = record.time_range;
+
+                        match input.kind {
+                            InputKind::ProductiveActivity => Edit::SetProductivity(start, ProductivityStatus::Productive(text)),
+                            InputKind::LeisureActivity => Edit::SetProductivity(start, ProductivityStatus::Leisure(text)),
+                            InputKind::Tag => Edit::AddTags(start, end, vec![text].into_iter().collect()),
+                            InputKind::Note => Edit::SetNote(start, end, Some(text)),
+                        }
+                    });
+                }
+                return false;
+            },
+            _ => {}
+        }
+        true
+    }
+
+    fn apply_pending(&mut self, app_state: &AppState) {
+        let date = self.date.unwrap_or_else(|| app_state.store().today());
+        let store = app_state.store();
+
+        match self.pending.take() {
+            Some(PendingChange::Edit(edit)) => {
+                self.message = Some(match store.edit_records(edit.clone()) {
+                    Ok(entries) if entries.is_empty() => String::from("Nothing changed"),
+                    Ok(_) => format!("Done: {}", edit),
+                    Err(err) => err.to_string(),
+                });
+            },
+            Some(PendingChange::Undo) => {
+                let latest = store.audit_entries().map(|entries| latest_unreverted(&entries));
+
+                self.message = Some(match latest {
+                    Ok(Some(number)) => match store.edit_records(Edit::Revert(number)) {
+                        Ok(_) => format!("Reverted edit #{}", number),
+                        Err(err) => err.to_string(),
+                    },
+                    Ok(None) => String::from("No edits to revert"),
+                    Err(err) => err.to_string(),
+                });
+            },
+            Some(PendingChange::SwitchDate(date)) => {
+                self.list_state.select(None);
+                self.message = None;
+                return self.load(app_state, date);
+            },
+            None => return,
+        }
+        self.load(app_state, date);
+    }
+}
+
+impl StatefulTUIComponent for RouteEdit {
+    fn handle_key(&mut self, event: Key) {
+        if let Some(mut input) = self.input.take() {
+            if self.handle_input_key(&mut input, event) {
+                self.input = Some(input);
+            }
+            return;
+        }
+
+        match event {
+            Key::Up => {
+                let selected = self.list_state.selected().map_or(0, |index| index.saturating_sub(1));
+                self.list_state.select(Some(selected));
+            },
+            Key::Down => {
+                let last = self.records.len().saturating_sub(1);
+                let selected = self.list_state.selected().map_or(0, |index| std::cmp::min(index + 1, last));
+                self.list_state.select(Some(selected));
+            },
+            Key::Char('[') => self.pending = self.date.map(|date| PendingChange::SwitchDate(date.pred())),
+            Key::Char(']') => self.pending = self.date.map(|date| PendingChange::SwitchDate(date.succ())),
+            Key::Char('s') => self.edit_selected(|record| Edit::Split(record.time_range.0 + record.duration() / 2)),
+            Key::Char('m') => self.edit_selected(|record| Edit::MergeNext(record.time_range.0)),
+            Key::Char('d') => self.edit_selected(|record| Edit::Delete(record.time_range.0, record.time_range.1)),
+            Key::Char('n') => self.edit_selected(|record| Edit::SetProductivity(record.time_range.0, ProductivityStatus::Neutral)),
+            Key::Char('p') | Key::Char('l') | Key::Char('t') | Key::Char('o') if self.selected().is_some() => {
+                let kind = match event {
+                    Key::Char('p') => InputKind::ProductiveActivity,
+                    Key::Char('l') => InputKind::LeisureActivity,
+                    Key::Char('t') => InputKind::Tag,
+                    _ => InputKind::Note,
+                };
+
+                self.input = Some(Input { kind, text: String::new() });
+            },
+            Key::Char('T') => self.edit_selected(|record| {
+                Edit::RemoveTags(record.time_range.0, record.time_range.1, record.tags.clone())
+            }),
+            Key::Char('O') => self.edit_selected(|record| Edit::SetNote(record.time_range.0, record.time_range.1, None)),
+            Key::Char('u') => self.pending = Some(PendingChange::Undo),
+            _ => {}
+        }
+    }
+
+    fn before_render(&mut self, app_state: &AppState) {
+        self.apply_pending(app_state);
+    }
+
+    fn tick(&mut self, app_state: &AppState) {
+        // Records keep being tracked while the screen is open
+        if let Some(date) = self.date {
+            if self.input.is_none() {
+                self.load(app_state, date);
+            }
+        }
+    }
+
+    fn render(&self, frame: &mut TUIFrame, chunk: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(3)].as_ref())
+            .split(chunk);
+
+        let title = format!(
+            " Records of {} (↑↓ select, [ ] day, s split, m merge, d delete, n/p/l classify, t/T tag, o/O note, u undo, Esc back) ",
+            self.date.map_or_else(String::new, |date| date.format("%Y-%m-%d").to_string())
+        );
+        let lines: Vec<String> = self.records.iter()
+            .map(|record| {
+                let (title, name) = match &record.archetype {
+                    Archetype::ActiveWindow(title, name, _) => (title.as_str(), name.as_str()),
+                    Archetype::AFK => ("", "AFK"),
+                    Archetype::Private => ("", "Private"),
+                    Archetype::Incognito(name, _) => ("(incognito)", name.as_str()),
+                };
+                let productivity = match &record.productivity {
+                    ProductivityStatus::Productive(activity) => format!("+ {}", activity),
+                    ProductivityStatus::Leisure(activity) => format!("- {}", activity),
+                    ProductivityStatus::Neutral => String::from("  neutral"),
+                };
+
+                let project = record.project.as_ref().map_or_else(String::new, |project| format!(" [{}]", project));
+                let tags: String = record.tags.iter().map(|tag| format!(" #{}", tag)).collect();
+                let note = record.note.as_ref().map_or_else(String::new, |note| format!(" ({})", note));
+
+                format!(
+                    "{}-{} {:>7}  {:<16}  {}  {}{}{}{}",
+                    DateTime::<Local>::from(record.time_range.0).format("%H:%M:%S"),
+                    DateTime::<Local>::from(record.time_range.1).format("%H:%M:%S"),
+                    format_duration(record.duration()),
+                    productivity,
+                    name,
+                    title,
+                    project,
+                    tags,
+                    note,
+                )
+            })
+            .collect();
+        let records = List::new(lines.iter().map(|line| Text::Raw(cow(line))))
+            .block(Block::default()
+                .title(&title)
+                .title_style(*STYLE::STYLE_TEXT_HEADER)
+                .borders(Borders::ALL))
+            .highlight_style(*STYLE::STYLE_TEXT_HEADER)
+            .highlight_symbol("> ");
+        // Rendering a stateful widget needs its state mutable
+        let mut list_state = self.list_state.clone();
+
+        let status = match &self.input {
+            Some(input) => {
+                let label = match input.kind {
+                    InputKind::ProductiveActivity => "Productive activity",
+                    InputKind::LeisureActivity => "Leisure activity",
+                    InputKind::Tag => "Tag",
+                    InputKind::Note => "Note",
+                };
+
+                format!("{}: {}_", label, input.text)
+            },
+            None => self.message.clone().unwrap_or_default(),
+        };
+        let status_text = [Text::Raw(cow(&status))];
+        let status = Paragraph::new(status_text.iter())
+            .block(Block::default()
+                .title(" Edit (Enter to apply, empty text cancels) ")
+                .title_style(*STYLE::STYLE_TEXT_HEADER)
+                .borders(Borders::ALL));
+
+        frame.render_stateful_widget(records, chunks[0], &mut list_state);
+        frame.render_widget(status, chunks[1]);
+    }
+}
@@ -14,7 +14,9 @@ use tui::{
     symbols,
     style::{ Style, Color },
 };
+use chrono::NaiveDate;
 use std::{
+    collections::BTreeSet,
     time::Duration
 };
 
@@ -41,6 +43,10 @@ const ROLLING_AVERAGE_TIME_WINDOW: Duration = Duration::from_secs(5 * 60);
 /// - Pause/resume tracking button
 #[derive(Debug, Default, Clone)]
 pub struct RouteMain {
+    /// Tracking date `records` belong to
+    date: Option<NaiveDate>,
+    /// Revision of the store `records` were read at
+    revision: u64,
     records: Vec<ActivityRecord>,
     // /// Total duration of tracking current record
     tracking_time: Duration,
@@ -73,6 +79,8 @@ impl From<&AppState> for RouteMain {
             .unwrap_or(vec![]);
 
         let mut result = Self {
+            date: Some(state.store().today()),
+            revision: state.store().revision(),
             records,
             tracking_time: Duration::from_secs(0),
             processed_records: 0,
@@ -177,7 +185,19 @@ impl RouteMain {
 
 impl StatefulTUIComponent for RouteMain {
     fn tick(&mut self, app_state: &AppState) {
-        let records = app_state.store().query_records()
+        let today = app_state.store().today();
+        let revision = app_state.store().revision();
+        // Records are read again once they were edited or forgotten
+        let is_new_day = self.date != Some(today) || self.revision != revision;
+
+        if is_new_day {
+            self.date = Some(today);
+            self.revision = revision;
+            self.records.clear();
+        }
+
+        // Only records pushed since the last tick are fetched
+        let new_records = app_state.store().query_records_since(&today, self.records.len())
             .unwrap_or(vec![]);
 
         self.tracking_time = app_state.tracker().get_current_tracking_period();
@@ -188,7 +208,11 @@ impl StatefulTUIComponent for RouteMain {
             let mut activity = ActivityRecord {
                 archetype: arch.clone(),
                 time_range: (start_time, end_time),
-                productivity: crate::record_store::ProductivityStatus::Neutral
+                productivity: crate::record_store::ProductivityStatus::Neutral,
+                machine: None,
+                tags: BTreeSet::new(),
+                note: None,
+                project: None,
             };
 
             app_state.classifier().classify(&mut activity);
@@ -197,8 +221,8 @@ impl StatefulTUIComponent for RouteMain {
             self.current_activity = None;
         }
         
-        if records.len() != self.records.len() {
-            self.records = records;
+        if is_new_day || !new_records.is_empty() {
+            self.records.extend(new_records);
             self.update_productivity_dataset();
         } else if self.get_cached_dataset_duration() + self.tracking_time > ROLLING_AVERAGE_TIME_WINDOW {
             self.update_productivity_dataset();
@@ -0,0 +1,118 @@
+use crate::{
+    event::Key,
+    record_store::{ format_duration, RecordFilter },
+    state::{ AppState },
+    stats::Stats,
+    tui::{
+        style as STYLE,
+        components::{ StatefulTUIComponent, TUIFrame },
+        routes::{ Route },
+        utils::*,
+    }
+};
+use tui::{
+    layout::Rect,
+    widgets::{ Block, Borders, List, Text },
+};
+use chrono::Duration;
+
+/// Periods the screen can show, as their names and numbers of tracking dates ending today
+const PERIODS: [(&str, i64); 3] = [("today", 1), ("last 7 days", 7), ("last 30 days", 30)];
+/// Width of the longest bar
+const BAR_WIDTH: u64 = 30;
+
+/// Project screen, opened with `p` from the main screen
+/// Displays:
+/// - Tracked time of every project over a period ending today, most time first
+/// - Tracked time that falls under no project
+#[derive(Default)]
+pub struct RouteProjects {
+    /// Index into `PERIODS`
+    period: usize,
+    /// Period `stats` are of
+    loaded_period: Option<usize>,
+    stats: Stats,
+    /// Error of the last update, if any
+    error: Option<String>,
+}
+impl Route for RouteProjects {}
+
+impl From<&AppState> for RouteProjects {
+    fn from(state: &AppState) -> Self {
+        let mut route = Self::default();
+
+        route.tick(state);
+        route
+    }
+}
+
+impl RouteProjects {
+    /// Lines of projects sorted by time, followed by the time of no project
+    fn lines(&self) -> Vec<String> {
+        let mut projects: Vec<(&str, u64)> = self.stats.by_project.iter()
+            .map(|(name, secs)| (name.as_str(), *secs))
+            .collect();
+        projects.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let assigned_secs: u64 = projects.iter().map(|(_, secs)| secs).sum();
+        projects.push(("(no project)", self.stats.total_secs.saturating_sub(assigned_secs)));
+
+        let total_secs = self.stats.total_secs.max(1);
+        let longest = projects.iter().map(|(_, secs)| *secs).max().unwrap_or(0).max(1);
+
+        projects.into_iter()
+            .map(|(name, secs)| format!(
+                "{:>8} {:>4}%  {:<24} {}",
+                format_duration(std::time::Duration::from_secs(secs)),
+                secs * 100 / total_secs,
+                name,
+                "#".repeat((secs * BAR_WIDTH / longest) as usize),
+            ))
+            .collect()
+    }
+}
+
+impl StatefulTUIComponent for RouteProjects {
+    fn handle_key(&mut self, event: Key) {
+        match event {
+            Key::Left => self.period = self.period.checked_sub(1).unwrap_or(PERIODS.len() - 1),
+            Key::Right => self.period = (self.period + 1) % PERIODS.len(),
+            _ => {}
+        }
+    }
+
+    fn before_render(&mut self, app_state: &AppState) {
+        if self.loaded_period != Some(self.period) {
+            self.tick(app_state);
+        }
+    }
+
+    fn tick(&mut self, app_state: &AppState) {
+        let to = app_state.store().today();
+        let from = to - Duration::days(PERIODS[self.period].1 - 1);
+
+        match Stats::for_dates(app_state.store(), &from, &to, &RecordFilter::default()) {
+            Ok(stats) => {
+                self.stats = stats;
+                self.loaded_period = Some(self.period);
+                self.error = None;
+            },
+            Err(err) => self.error = Some(err.to_string()),
+        }
+    }
+
+    fn render(&self, frame: &mut TUIFrame, chunk: Rect) {
+        let title = match &self.error {
+            Some(err) => format!(" Projects could not be updated: {} ", err),
+            None => format!(" Projects, {} (←→ period, Esc back) ", PERIODS[self.period].0),
+        };
+        let lines = self.lines();
+        let projects = List::new(lines.iter().map(|line| Text::Raw(cow(line))))
+            .block(Block::default()
+                .title(&title)
+                .title_style(*STYLE::STYLE_TEXT_HEADER)
+                .borders(Borders::ALL));
+
+        frame.render_widget(projects, chunk);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    event::Key,
+    record_store::{ format_duration, SearchHit, SearchQuery },
+    state::{ AppState },
+    tui::{
+        style as STYLE,
+        components::{ StatefulTUIComponent, TUIFrame },
+        routes::{ Route },
+        utils::*,
+    }
+};
+use tui::{
+    layout::{ Constraint, Direction, Layout, Rect },
+    widgets::{ Block, Borders, List, ListState, Paragraph, Text },
+};
+use std::time::Duration;
+
+/// Search screen, opened with `/` from the main screen
+/// Displays:
+/// - Query being typed
+/// - Records matching the query, latest first, with their durations
+/// - Number of matching records and their total duration
+#[derive(Debug, Default, Clone)]
+pub struct RouteSearch {
+    query: String,
+    /// Set once Enter is pressed, the search runs before the next render
+    is_search_pending: bool,
+    hits: Vec<SearchHit>,
+    total_duration: Duration,
+    /// Error of the last search, if any
+    error: Option<String>,
+    list_state: ListState,
+}
+impl Route for RouteSearch {}
+
+impl From<&AppState> for RouteSearch {
+    fn from(_state: &AppState) -> Self {
+        Self::default()
+    }
+}
+
+impl StatefulTUIComponent for RouteSearch {
+    fn handle_key(&mut self, event: Key) {
+        match event {
+            Key::Char(c) => self.query.push(c),
+            Key::Backspace => { self.query.pop(); },
+            Key::Enter => self.is_search_pending = true,
+            Key::Up => {
+                let selected = self.list_state.selected().map_or(0, |index| index.saturating_sub(1));
+                self.list_state.select(Some(selected));
+            },
+            Key::Down => {
+                let last = self.hits.len().saturating_sub(1);
+                let selected = self.list_state.selected().map_or(0, |index| std::cmp::min(index + 1, last));
+                self.list_state.select(Some(selected));
+            },
+            _ => {}
+        }
+    }
+
+    fn before_render(&mut self, app_state: &AppState) {
+        if !self.is_search_pending {
+            return;
+        }
+        self.is_search_pending = false;
+
+        match app_state.store().search(&SearchQuery::parse(&self.query), None, None) {
+            Ok(hits) => {
+                self.total_duration = hits.iter().map(|hit| hit.record.duration()).sum();
+                self.hits = hits;
+                self.error = None;
+            },
+            Err(err) => {
+                self.hits = vec![];
+                self.total_duration = Duration::from_secs(0);
+                self.error = Some(err.to_string());
+            }
+        }
+        self.list_state.select(if self.hits.is_empty() { None } else { Some(0) });
+    }
+
+    fn tick(&mut self, _app_state: &AppState) {}
+
+    fn render(&self, frame: &mut TUIFrame, chunk: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(chunk);
+
+        let query_text = [Text::Raw(cow(&self.query)), Text::Raw(cow("_"))];
+        let query = Paragraph::new(query_text.iter())
+            .block(Block::default()
+                .title(" Search (Enter to search, Esc to go back) ")
+                .title_style(*STYLE::STYLE_TEXT_HEADER)
+                .borders(Borders::ALL));
+
+        let title = match &self.error {
+            Some(err) => format!(" Search failed: {} ", err),
+            None => format!(" {} records, {} in total ", self.hits.len(), format_duration(self.total_duration)),
+        };
+        let lines: Vec<String> = self.hits.iter().map(SearchHit::to_string).collect();
+        let results = List::new(lines.iter().map(|line| Text::Raw(cow(line))))
+            .block(Block::default()
+                .title(&title)
+                .title_style(*STYLE::STYLE_TEXT_HEADER)
+                .borders(Borders::ALL))
+            .highlight_style(*STYLE::STYLE_TEXT_HEADER)
+            .highlight_symbol("> ");
+        // Rendering a stateful widget needs its state mutable
+        let mut list_state = self.list_state.clone();
+
+        frame.render_widget(query, chunks[0]);
+        frame.render_stateful_widget(results, chunks[1], &mut list_state);
+    }
+}
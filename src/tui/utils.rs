@@ -48,6 +48,8 @@ impl From<&AppState> for Option<DisplayArchetype> {
 }
 
 const CAPTION_AFK: &'static str = r#"AFK"#;
+const CAPTION_PRIVATE: &'static str = r#"Private window"#;
+const CAPTION_INCOGNITO: &'static str = r#"(incognito, title is not recorded)"#;
 
 
 
@@ -81,6 +83,20 @@ impl <'a> ToWidgets for &'a Option<DisplayArchetype> {
                         Text::Raw(cow("\n")),
                         Text::Styled(cow("Productivity: "), *STYLE::STYLE_TEXT_HEADER),
                     ],
+                    Archetype::Private => vec![
+                        Text::Styled(cow(CAPTION_PRIVATE), *STYLE::STYLE_TEXT_WARNING),
+                        Text::Raw(cow("\n")),
+                        Text::Styled(cow("Productivity: "), *STYLE::STYLE_TEXT_HEADER),
+                    ],
+                    Archetype::Incognito(name, _) => vec![
+                        Text::Styled(cow("Title: "), *STYLE::STYLE_TEXT_HEADER),
+                        Text::Styled(cow(CAPTION_INCOGNITO), *STYLE::STYLE_TEXT_WARNING),
+                        Text::Raw(cow("\n")),
+                        Text::Styled(cow("Application: "), *STYLE::STYLE_TEXT_HEADER),
+                        Text::Raw(cow(name.as_str())),
+                        Text::Raw(cow("\n")),
+                        Text::Styled(cow("Productivity: "), *STYLE::STYLE_TEXT_HEADER),
+                    ],
                     Archetype::ActiveWindow(title, name, ..) => vec![
                             // Text::Styled(cow("Active window:"), *STYLE::STYLE_TEXT_HEADER),
                             // Text::Raw(cow("\n")),
@@ -1,6 +1,7 @@
 use crate::{ 
     record_store::{ Archetype }
 };
+use std::time::Duration;
 
 pub struct MouseState {
     pub coords: (i32, i32),
@@ -13,4 +14,10 @@ pub trait OSWindowManager {
     fn get_window_archetype(&self) -> Option<Archetype>;
     fn query_mouse_pointer(&self) -> MouseState;
     fn query_keyboard(&self) -> Self::KeyboardState;
+
+    /// Time since the last input of the user, `None` if the OS can't tell
+    /// and the keyboard and the mouse have to be polled instead
+    fn idle_time(&self) -> Option<Duration> {
+        None
+    }
 }
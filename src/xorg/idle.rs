@@ -0,0 +1,62 @@
+use std::{os::raw::c_void, time::Duration};
+use x11::{
+    xlib::{Window, XFree},
+    xss::{XScreenSaverAllocInfo, XScreenSaverInfo, XScreenSaverQueryExtension, XScreenSaverQueryInfo},
+};
+
+use super::display::Display;
+
+/// Idle time kept by the X server through the MIT-SCREEN-SAVER extension.
+///
+/// The server counts every input event, so unlike polling the keyboard
+/// and the pointer nothing is missed between queries
+pub struct ScreenSaverInfo(*mut XScreenSaverInfo);
+
+impl ScreenSaverInfo {
+    /// Returns `None` if the X server lacks the extension
+    pub fn query_extension(display: &Display) -> Option<Self> {
+        let mut event_base = 0;
+        let mut error_base = 0;
+
+        if unsafe { XScreenSaverQueryExtension(display.0, &mut event_base, &mut error_base) } == 0 {
+            return None;
+        }
+
+        let info = unsafe { XScreenSaverAllocInfo() };
+        if info.is_null() {
+            return None;
+        }
+        Some(Self(info))
+    }
+
+    /// Gets time since the last input on the screen of a root window
+    pub fn idle_time(&self, display: &Display, root_window: Window) -> Option<Duration> {
+        if unsafe { XScreenSaverQueryInfo(display.0, root_window, self.0) } == 0 {
+            return None;
+        }
+
+        // `c_ulong` is narrower than `u64` on some platforms
+        Some(Duration::from_millis(unsafe { (*self.0).idle } as u64))
+    }
+}
+
+impl Drop for ScreenSaverInfo {
+    fn drop(&mut self) {
+        unsafe { XFree(self.0 as *mut c_void) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs an X server, run with `xvfb-run cargo test -- --ignored`"]
+    fn idle_time_under_xvfb() {
+        let display = Display::open().expect("No X display");
+        let root_window = display.get_default_root_window();
+        let info = ScreenSaverInfo::query_extension(&display).expect("X server lacks MIT-SCREEN-SAVER");
+
+        assert_eq!(info.idle_time(&display, root_window).is_some(), true);
+    }
+}
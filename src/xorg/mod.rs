@@ -3,6 +3,7 @@ mod atom;
 mod atoms;
 mod display;
 mod events;
+mod idle;
 
 use crate::{
     window_manager::{MouseState, OSWindowManager},
@@ -12,19 +13,25 @@ use atom::*;
 use atoms::*;
 use display::Display;
 use events::*;
+use idle::ScreenSaverInfo;
+use std::time::Duration;
 
 pub struct XORGWindowManager {
     display: Display,
     root_window: u64,
+    /// Absent if the X server lacks the MIT-SCREEN-SAVER extension
+    screen_saver: Option<ScreenSaverInfo>,
 }
 
 impl Default for XORGWindowManager {
     fn default() -> Self {
         let display = Display::open().unwrap();
         let root_window = display.get_default_root_window();
+        let screen_saver = ScreenSaverInfo::query_extension(&display);
         Self {
             display,
             root_window,
+            screen_saver,
         }
     }
 }
@@ -57,4 +64,8 @@ impl OSWindowManager for XORGWindowManager {
     fn query_mouse_pointer(&self) -> MouseState {
         query_mouse_pointer(&self.display, self.root_window)
     }
+
+    fn idle_time(&self) -> Option<Duration> {
+        self.screen_saver.as_ref().and_then(|info| info.idle_time(&self.display, self.root_window))
+    }
 }